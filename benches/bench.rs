@@ -0,0 +1,91 @@
+// Benchmarks of the workloads the b-tree and record code is tuned for, over
+// fixtures/bench.db. Run them with `cargo bench`, before and after a change,
+// and criterion reports the difference.
+
+use std::rc::Rc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use rqlite::index::Bound;
+use rqlite::pager::Pager;
+use rqlite::record::{Field, Record};
+use rqlite::schema::Schema;
+use rqlite::table::RawTableLeafCell;
+
+const ROWS: usize = 20_000;
+
+fn schema() -> Schema {
+    let pager = Pager::open("fixtures/bench.db").unwrap();
+    Schema::new(Rc::new(pager)).unwrap()
+}
+
+fn full_scan(c: &mut Criterion) {
+    let schema = schema();
+    let table = schema.table("rows").unwrap();
+    c.bench_function("full scan", |b| {
+        b.iter(|| {
+            let mut rows = table.select_iter(vec!["id", "value", "label"]).unwrap();
+            assert_eq!(rows.try_fold(0, |n, row| row.map(|_| n + 1)).unwrap(), ROWS);
+        })
+    });
+}
+
+fn point_get(c: &mut Criterion) {
+    let schema = schema();
+    let table = schema.table("rows").unwrap();
+    let mut row_id = 0;
+    c.bench_function("point get", |b| {
+        b.iter(|| {
+            // Step through the rowids out of order, so each lookup starts
+            // afresh.
+            row_id = (row_id + 7919) % ROWS as i64;
+            assert!(table.get(row_id + 1, &["label"]).unwrap().is_some());
+        })
+    });
+}
+
+fn index_range(c: &mut Criterion) {
+    let schema = schema();
+    let indices = schema.indices().unwrap();
+    let index = indices.iter().find(|i| i.name() == "rows_value").unwrap();
+    // A scan that drops entries would look faster than it is.
+    assert_eq!(index.dump().unwrap().len(), ROWS);
+    let bound = |value: u64, inclusive| {
+        Some(Bound {
+            value: Field::from(value),
+            inclusive,
+        })
+    };
+    c.bench_function("index range scan", |b| {
+        b.iter(|| {
+            // Values 1000..2000 are about 10% of the rows.
+            let entries = index
+                .scan_bounds(vec![], bound(1000, true), bound(2000, false))
+                .unwrap();
+            assert!(!entries.is_empty());
+        })
+    });
+}
+
+fn record_decode(c: &mut Criterion) {
+    let schema = schema();
+    let payloads: Vec<_> = schema
+        .table("rows")
+        .unwrap()
+        .raw_cells()
+        .unwrap()
+        .into_iter()
+        .map(|cell: RawTableLeafCell| cell.payload)
+        .collect();
+    c.bench_function("record decode", |b| {
+        b.iter(|| {
+            for payload in &payloads {
+                let record = Record::from_bytes(payload.clone()).unwrap();
+                assert_eq!(record.len(), 3);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, full_scan, point_get, index_range, record_decode);
+criterion_main!(benches);
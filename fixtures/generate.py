@@ -0,0 +1,451 @@
+#!/usr/bin/env python3
+"""Builds the SQLite databases used by the tests.
+
+Run from the repository root. Existing databases are left alone, so that
+adding a fixture doesn't churn the bytes of the others.
+"""
+
+import gzip
+import os
+import shutil
+import sqlite3
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def sales(db):
+    db.execute("CREATE TABLE sales(id integer primary key, region text, amount int)")
+    rows = (
+        [("north", n) for n in (10, 20, 30, 40, 50)]
+        + [("south", n) for n in (5, 15)]
+        + [("east", n) for n in (1, 2, 3, 4)]
+    )
+    db.executemany("INSERT INTO sales(region, amount) VALUES (?, ?)", rows)
+
+
+def large(db):
+    db.execute("CREATE TABLE numbers(id integer primary key, value int, label text)")
+    db.executemany(
+        "INSERT INTO numbers(id, value, label) VALUES (?, ?, ?)",
+        ((n, n * 7 % 1000, "row-%d" % n) for n in range(1, 5001)),
+    )
+
+
+def without_rowid(db):
+    db.execute("CREATE TABLE kv(k text primary key, v int) WITHOUT ROWID")
+    db.execute("CREATE INDEX kv_v ON kv(v)")
+    db.execute("CREATE TABLE pairs(a int, b text)")
+    db.execute("CREATE INDEX pairs_b ON pairs(b)")
+    rows = [("a", 3), ("b", 1), ("c", 2)]
+    db.executemany("INSERT INTO kv(k, v) VALUES (?, ?)", rows)
+    db.executemany("INSERT INTO pairs(a, b) VALUES (?, ?)", [(v, k) for k, v in rows])
+
+
+def indexed(db):
+    db.execute("CREATE TABLE items(id integer primary key, price int, name text)")
+    db.execute("CREATE INDEX items_price ON items(price)")
+    db.executemany(
+        "INSERT INTO items(id, price, name) VALUES (?, ?, ?)",
+        ((n, n * 37 % 500, "item-%d" % n) for n in range(1, 301)),
+    )
+
+
+def generated(db):
+    db.execute(
+        "CREATE TABLE shapes("
+        "side int, "
+        "area int GENERATED ALWAYS AS (side * side) STORED, "
+        "perimeter int AS (side * 4), "
+        "name text)"
+    )
+    db.executemany(
+        "INSERT INTO shapes(side, name) VALUES (?, ?)", [(2, "small"), (5, "large")]
+    )
+
+
+def deep(db):
+    # Small pages, so that the table b-tree has several levels of interior
+    # pages, and rowids that need the full signed 64-bit range.
+    db.execute("PRAGMA page_size = 512")
+    db.execute("CREATE TABLE deep(id integer primary key, value int)")
+    ids = list(range(1, 20001)) + [-(2 ** 62), -1000, -1, 2 ** 62]
+    db.executemany(
+        "INSERT INTO deep(id, value) VALUES (?, ?)", ((n, n % 1000) for n in ids)
+    )
+
+
+def analyzed(db):
+    db.execute("CREATE TABLE people(id integer primary key, city text, age int)")
+    db.execute("CREATE INDEX people_city ON people(city)")
+    db.execute("CREATE INDEX people_age ON people(age)")
+    db.executemany(
+        "INSERT INTO people(city, age) VALUES (?, ?)",
+        ((["york", "leeds"][n % 2], n % 100) for n in range(1000)),
+    )
+    db.execute("CREATE TABLE pets(id integer primary key, legs int, weight int)")
+    db.execute("CREATE INDEX pets_legs ON pets(legs)")
+    db.execute("CREATE INDEX pets_weight ON pets(weight)")
+    db.executemany(
+        "INSERT INTO pets(legs, weight) VALUES (?, ?)",
+        ((n % 3 * 2, n) for n in range(100)),
+    )
+    db.execute("ANALYZE")
+    # Leave pets without statistics.
+    db.execute("DELETE FROM sqlite_stat1 WHERE tbl = 'pets'")
+
+
+def overflow(db):
+    # Rows much bigger than a page, whose payloads spill onto overflow pages.
+    db.execute("PRAGMA page_size = 1024")
+    db.execute("CREATE TABLE docs(id integer primary key, body text)")
+    db.execute("CREATE INDEX docs_body ON docs(body)")
+    db.execute("CREATE TABLE tags(id integer primary key, doc int, tag text)")
+    db.executemany(
+        "INSERT INTO docs(id, body) VALUES (?, ?)",
+        ((n, ("doc %d " % n) * (n * 300)) for n in range(1, 11)),
+    )
+    db.executemany(
+        "INSERT INTO tags(doc, tag) VALUES (?, ?)",
+        ((n % 10 + 1, "tag-%d" % n) for n in range(500)),
+    )
+
+
+def vacuum(db):
+    # Incremental vacuum keeps pointer-map pages among the b-tree pages. With
+    # 1024-byte pages each one covers 204 pages, so there are two of them.
+    db.execute("PRAGMA page_size = 1024")
+    db.execute("PRAGMA auto_vacuum = INCREMENTAL")
+    db.execute("CREATE TABLE logs(id integer primary key, line text)")
+    db.execute("CREATE TABLE levels(id integer primary key, name text)")
+    db.executemany(
+        "INSERT INTO logs(id, line) VALUES (?, ?)",
+        ((n, "log line %d " % n * 8) for n in range(1, 3001)),
+    )
+    db.executemany(
+        "INSERT INTO levels(name) VALUES (?)", [("debug",), ("info",), ("error",)]
+    )
+
+
+def views(db):
+    db.execute("CREATE TABLE people(id integer primary key, name text, age int)")
+    db.execute("CREATE TABLE audit(person int, note text)")
+    db.execute(
+        "CREATE VIEW adults AS SELECT id, name, age FROM people WHERE age >= 18"
+    )
+    db.execute(
+        "CREATE TRIGGER people_insert AFTER INSERT ON people "
+        "BEGIN INSERT INTO audit(person, note) VALUES (new.id, 'added'); END"
+    )
+    db.executemany(
+        "INSERT INTO people(name, age) VALUES (?, ?)",
+        [("ann", 34), ("bob", 12), ("cat", 18), ("dan", 17), ("eve", 71)],
+    )
+
+
+def composite(db):
+    db.execute(
+        "CREATE TABLE visits(id integer primary key, site text, day int, hits int)"
+    )
+    db.execute("CREATE INDEX visits_site_day ON visits(site, day)")
+    db.execute("CREATE INDEX visits_day ON visits(day)")
+    db.executemany(
+        "INSERT INTO visits(site, day, hits) VALUES (?, ?, ?)",
+        (
+            (site, day, day * 10 + n)
+            for day in range(1, 31)
+            for n, site in enumerate("abcde")
+        ),
+    )
+
+
+def nullable(db):
+    db.execute("CREATE TABLE contacts(id integer primary key, email text)")
+    db.execute("CREATE INDEX contacts_email ON contacts(email)")
+    db.executemany(
+        "INSERT INTO contacts(id, email) VALUES (?, ?)",
+        (
+            (n, None if n % 3 == 0 else "user-%d@example.com" % n)
+            for n in range(1, 301)
+        ),
+    )
+
+
+def blobs(db):
+    # Blobs in a TEXT column, as we can't parse a BLOB column yet.
+    db.execute("CREATE TABLE files(id integer primary key, name text, data text)")
+    db.executemany(
+        "INSERT INTO files(name, data) VALUES (?, ?)",
+        ((("file-%d " % n) * 10, bytes([n]) * 2000) for n in range(1, 11)),
+    )
+
+
+
+
+def autoincrement(db):
+    # sqlite_sequence keeps the largest rowid each table has ever had, even
+    # once the rows with it are deleted.
+    db.execute("CREATE TABLE jobs(id integer primary key autoincrement, name text)")
+    db.execute("CREATE TABLE runs(id integer primary key autoincrement, job int)")
+    db.execute("CREATE TABLE plain(id integer primary key, note text)")
+    db.executemany(
+        "INSERT INTO jobs(name) VALUES (?)", [("job %d" % n,) for n in range(5)]
+    )
+    db.execute("DELETE FROM jobs WHERE id > 3")
+    db.execute("INSERT INTO runs(id, job) VALUES (100, 1)")
+    db.execute("INSERT INTO plain(note) VALUES ('no sequence')")
+
+def reals(db):
+    # REAL columns store whole numbers as integers, to save space.
+    db.execute("CREATE TABLE readings(id integer primary key, value real)")
+    db.executemany(
+        "INSERT INTO readings(value) VALUES (?)", [(2.0,), (2.5,), (-3.0,), (None,)]
+    )
+
+
+def sensors(db):
+    # Enough rows that the composite index is more than one page deep, so
+    # some of its entries are in interior pages.
+    db.execute(
+        "CREATE TABLE readings(id integer primary key, sensor int, hour int, value int)"
+    )
+    db.execute("CREATE INDEX readings_sensor_hour ON readings(sensor, hour)")
+    db.executemany(
+        "INSERT INTO readings(sensor, hour, value) VALUES (?, ?, ?)",
+        ((n % 10, n // 10 % 24, n) for n in range(5000)),
+    )
+
+
+def dates(db):
+    # SQLite has no boolean or date types: columns declared with them have
+    # NUMERIC affinity, so text that looks like a number is stored as one.
+    db.execute(
+        "CREATE TABLE tasks(id integer primary key, done BOOLEAN, due DATE, "
+        "updated DATETIME, created TIMESTAMP)"
+    )
+    db.executemany(
+        "INSERT INTO tasks(done, due, updated, created) VALUES (?, ?, ?, ?)",
+        [
+            (True, "2024-01-31", "2024-01-02 03:04:05", 1704164645),
+            (False, None, "1704164645", "2024-01-02T03:04:05Z"),
+        ],
+    )
+    # Only a column declared INTEGER aliases the rowid, so this key is stored
+    # in the row like any other column.
+    db.execute("CREATE TABLE holidays(day DATE PRIMARY KEY, name TEXT)")
+    db.execute("INSERT INTO holidays VALUES (20240101, 'new year')")
+
+
+def freelist(db):
+    # Dropping a table puts its pages on the freelist, where they stay until
+    # VACUUM. With 1024-byte pages a trunk lists up to 254 free pages, so
+    # there's more than one trunk.
+    db.execute("PRAGMA page_size = 1024")
+    db.execute("CREATE TABLE dropped(id integer primary key, line text)")
+    db.execute("CREATE TABLE kept(id integer primary key, line text)")
+    db.executemany(
+        "INSERT INTO dropped(id, line) VALUES (?, ?)",
+        ((n, "dropped line %d " % n * 10) for n in range(1, 3001)),
+    )
+    db.execute("INSERT INTO kept(line) VALUES ('kept')")
+    db.commit()
+    db.execute("DROP TABLE dropped")
+
+
+def bench(db):
+    # For the benchmarks in src/bench.rs, which rely on its size: 20,000 rows
+    # of a few columns, and an index over one of them.
+    db.execute("CREATE TABLE rows(id integer primary key, value int, label text)")
+    db.execute("CREATE INDEX rows_value ON rows(value)")
+    db.executemany(
+        "INSERT INTO rows(id, value, label) VALUES (?, ?, ?)",
+        ((n, n * 7919 % 10000, "label %d" % n) for n in range(1, 20001)),
+    )
+
+def checksum(path):
+    # Python's sqlite3 can't ask for reserved bytes, so start with an empty
+    # database and reserve 8 bytes per page in its header by hand. SQLite
+    # keeps them free from then on.
+    db = sqlite3.connect(path)
+    db.execute("PRAGMA page_size = 1024")
+    db.execute("PRAGMA user_version = 1")
+    db.commit()
+    db.close()
+    data = bytearray(open(path, "rb").read())
+    data[20] = 8
+    # The (empty) schema page's cell content now ends where the reserved
+    # bytes start.
+    struct.pack_into(">H", data, 105, 1024 - 8)
+    open(path, "wb").write(data)
+
+    db = sqlite3.connect(path)
+    db.execute("CREATE TABLE notes(id integer primary key, body text)")
+    db.executemany(
+        "INSERT INTO notes(id, body) VALUES (?, ?)",
+        ((n, "note %d " % n * 5) for n in range(1, 201)),
+    )
+    db.commit()
+    db.close()
+
+    # Fill in the checksums as SQLite's checksum VFS would.
+    data = bytearray(open(path, "rb").read())
+    for start in range(0, len(data), 1024):
+        s1 = s2 = 0
+        for offset in range(start, start + 1024 - 8, 8):
+            a, b = struct.unpack_from("<II", data, offset)
+            s1 = (s1 + a + s2) & 0xFFFFFFFF
+            s2 = (s2 + b + s1) & 0xFFFFFFFF
+        struct.pack_into("<II", data, start + 1024 - 8, s1, s2)
+    open(path, "wb").write(data)
+
+
+
+def wal(path):
+    # Copies a database in WAL mode while it's still open, so that its
+    # changes are only in the -wal file. There's no -shm file, as with many
+    # copied databases.
+    work = path + ".tmp"
+    db = sqlite3.connect(work)
+    db.execute("PRAGMA journal_mode = WAL")
+    db.execute("PRAGMA wal_autocheckpoint = 0")
+    db.execute("CREATE TABLE events(id integer primary key, name text)")
+    db.commit()
+    db.executemany(
+        "INSERT INTO events(id, name) VALUES (?, ?)",
+        ((n, "event %d" % n) for n in range(1, 501)),
+    )
+    db.commit()
+    db.execute("UPDATE events SET name = 'changed' WHERE id <= 10")
+    db.commit()
+    shutil.copy(work, path)
+    shutil.copy(work + "-wal", path + "-wal")
+    db.close()
+    for leftover in (work, work + "-wal", work + "-shm"):
+        if os.path.exists(leftover):
+            os.remove(leftover)
+
+    # A torn frame, as a crash part way through a write would leave, which
+    # readers must ignore.
+    with open(path + "-wal", "ab") as f:
+        f.write(b"\xff" * (24 + 4096))
+
+def rootless(db):
+    # Views and triggers have a rootpage of 0, and so do virtual tables. Here
+    # a table and an index have lost theirs too, one to 0 and one to NULL,
+    # alongside a view and a trigger.
+    db.execute("CREATE TABLE kept(id integer primary key, name text)")
+    db.execute("CREATE INDEX kept_name ON kept(name)")
+    db.execute("CREATE TABLE ghost(id integer primary key, name text)")
+    db.execute("CREATE INDEX ghost_name ON ghost(name)")
+    db.execute("CREATE TABLE phantom(id int)")
+    db.execute("CREATE VIEW named AS SELECT id, name FROM kept WHERE name IS NOT NULL")
+    db.execute(
+        "CREATE TRIGGER kept_delete AFTER DELETE ON kept "
+        "BEGIN DELETE FROM ghost WHERE id = old.id; END"
+    )
+    db.executemany(
+        "INSERT INTO kept(name) VALUES (?)", [("ann",), ("bob",), (None,)]
+    )
+    db.commit()
+    db.execute("PRAGMA writable_schema = ON")
+    db.execute(
+        "UPDATE sqlite_master SET rootpage = 0 WHERE name IN ('ghost', 'ghost_name')"
+    )
+    db.execute("UPDATE sqlite_master SET rootpage = NULL WHERE name = 'phantom'")
+
+
+def altered(db):
+    # Adding a column doesn't rewrite the table, so rows inserted before it
+    # was added have fewer fields than the table has columns.
+    db.execute("CREATE TABLE items(id integer primary key, name text)")
+    db.executemany("INSERT INTO items(name) VALUES (?)", [("bolt",), ("nut",)])
+    db.execute("ALTER TABLE items ADD COLUMN qty int DEFAULT 5")
+    db.execute("ALTER TABLE items ADD COLUMN price real DEFAULT 2")
+    db.execute("ALTER TABLE items ADD COLUMN note text")
+    db.execute("INSERT INTO items(name, qty, price, note) VALUES ('washer', 7, 0.5, 'loose')")
+
+
+def signed(db):
+    db.execute("CREATE TABLE nums(id integer primary key, v int)")
+    db.executemany("INSERT INTO nums(v) VALUES (?)", [(-5,), (3,), (-1,), (10,)])
+
+
+def constraints(db):
+    # The indices SQLite makes for UNIQUE and PRIMARY KEY constraints,
+    # alongside one made by CREATE INDEX.
+    db.execute(
+        "CREATE TABLE codes(name text unique, code text primary key, n int, "
+        "unique (n, name))"
+    )
+    db.execute("CREATE INDEX codes_n ON codes(n)")
+    db.executemany(
+        "INSERT INTO codes(name, code, n) VALUES (?, ?, ?)",
+        [("one", "a", 1), ("two", "b", 2)],
+    )
+
+
+def compressed(path):
+    # large.db, compressed as an archived database might be. The timestamp is
+    # fixed so that regenerating it gives the same bytes.
+    with open(os.path.join(HERE, "large.db"), "rb") as f:
+        data = f.read()
+    with open(path, "wb") as f:
+        f.write(gzip.compress(data, mtime=0))
+
+
+FIXTURES = {
+    "sales.db": sales,
+    "large.db": large,
+    "without_rowid.db": without_rowid,
+    "indexed.db": indexed,
+    "generated.db": generated,
+    "deep.db": deep,
+    "analyzed.db": analyzed,
+    "overflow.db": overflow,
+    "vacuum.db": vacuum,
+    "views.db": views,
+    "composite.db": composite,
+    "nullable.db": nullable,
+    "blobs.db": blobs,
+    "bench.db": bench,
+    "autoincrement.db": autoincrement,
+    "reals.db": reals,
+    "freelist.db": freelist,
+    "dates.db": dates,
+    "sensors.db": sensors,
+    "rootless.db": rootless,
+    "altered.db": altered,
+    "signed.db": signed,
+    "constraints.db": constraints,
+}
+
+# Fixtures that need to do more than run SQL, which are given the path to
+# build rather than a connection.
+RAW_FIXTURES = {
+    "checksum.db": checksum,
+    "wal.db": wal,
+    "large.db.gz": compressed,
+}
+
+
+def main():
+    for name, build in FIXTURES.items():
+        path = os.path.join(HERE, name)
+        if os.path.exists(path):
+            continue
+        db = sqlite3.connect(path)
+        build(db)
+        db.commit()
+        db.close()
+        print("Generated", name)
+
+    for name, build in RAW_FIXTURES.items():
+        path = os.path.join(HERE, name)
+        if os.path.exists(path):
+            continue
+        build(path)
+        print("Generated", name)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,185 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::*;
+use crate::expr::compare;
+use crate::record::{Field, FieldKey, LiteralValue};
+use crate::types::Type;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AggregateFunction {
+    CountStar,
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    /// The aggregate function called `name`, if there is one. `COUNT(*)` is
+    /// also called "count".
+    pub fn from_name(name: &str) -> Option<AggregateFunction> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "count" => AggregateFunction::Count,
+            "sum" => AggregateFunction::Sum,
+            "avg" => AggregateFunction::Avg,
+            "min" => AggregateFunction::Min,
+            "max" => AggregateFunction::Max,
+            _ => return None,
+        })
+    }
+}
+
+/// An aggregate function call, such as `COUNT(*)` or `SUM(price)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregate {
+    function: AggregateFunction,
+    column: Option<String>,
+    // Whether each distinct value is only counted once.
+    distinct: bool,
+}
+
+impl Aggregate {
+    pub fn new(function: AggregateFunction, column: Option<String>, distinct: bool) -> Aggregate {
+        Aggregate {
+            function,
+            column,
+            distinct,
+        }
+    }
+
+    /// The name the aggregate is known by in a grouped row, which is also the
+    /// name of an un-aliased aggregate column (e.g. `count(*)`).
+    pub fn name(&self) -> String {
+        let function = match self.function {
+            AggregateFunction::CountStar => return "count(*)".to_owned(),
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+        };
+        let distinct = if self.distinct { "DISTINCT " } else { "" };
+        format!(
+            "{}({}{})",
+            function,
+            distinct,
+            self.column.as_ref().unwrap()
+        )
+    }
+
+    pub fn column(&self) -> Option<&str> {
+        self.column.as_deref()
+    }
+
+    pub fn accumulator(&self) -> Accumulator {
+        Accumulator {
+            aggregate: self.clone(),
+            count: 0,
+            sum: Sum::Integer(0),
+            extreme: None,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Sum {
+    Integer(i64),
+    Float(f64),
+}
+
+/// The running state of an aggregate over the rows of one group.
+pub struct Accumulator {
+    aggregate: Aggregate,
+    // Rows seen for COUNT(*), otherwise non-NULL values seen.
+    count: u64,
+    sum: Sum,
+    // The smallest/largest value seen for MIN/MAX.
+    extreme: Option<Field>,
+    // The values seen so far, for DISTINCT aggregates.
+    seen: HashSet<FieldKey>,
+}
+
+impl Accumulator {
+    pub fn update(&mut self, row: &HashMap<String, Field>) -> Result<()> {
+        let column = match self.aggregate.column {
+            Some(ref column) => column,
+            None => {
+                self.count += 1;
+                return Ok(());
+            }
+        };
+        let field = row
+            .get(column)
+            .ok_or_else(|| format!("Unknown column: {}", column))?;
+        if field.ty() == Type::Null {
+            return Ok(());
+        }
+        if self.aggregate.distinct && !self.seen.insert(field.key()) {
+            return Ok(());
+        }
+        self.count += 1;
+
+        match self.aggregate.function {
+            AggregateFunction::Sum | AggregateFunction::Avg => {
+                self.sum = match (self.sum, field.ty()) {
+                    (Sum::Integer(s), Type::Integer) => {
+                        // XXX Field doesn't know integers are signed yet.
+                        let i = field.as_integer()? as i64;
+                        match s.checked_add(i) {
+                            Some(s) => Sum::Integer(s),
+                            None => bail!("integer overflow"),
+                        }
+                    }
+                    (Sum::Integer(s), Type::Float) => Sum::Float(s as f64 + field.as_float()?),
+                    (Sum::Float(s), Type::Integer) => {
+                        Sum::Float(s + field.as_integer()? as i64 as f64)
+                    }
+                    (Sum::Float(s), Type::Float) => Sum::Float(s + field.as_float()?),
+                    (_, ty) => bail!(ErrorKind::Unsupported(format!(
+                        "SUM/AVG over {:?} values",
+                        ty
+                    ))),
+                };
+            }
+            AggregateFunction::Min | AggregateFunction::Max => {
+                let wanted = match self.aggregate.function {
+                    AggregateFunction::Min => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let replace = match self.extreme {
+                    Some(ref extreme) => compare(field, extreme) == wanted,
+                    None => true,
+                };
+                if replace {
+                    self.extreme = Some(field.clone());
+                }
+            }
+            AggregateFunction::Count | AggregateFunction::CountStar => {}
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Field {
+        let null = Field::Literal(LiteralValue::Null);
+        match self.aggregate.function {
+            AggregateFunction::CountStar | AggregateFunction::Count => Field::from(self.count),
+            AggregateFunction::Sum if self.count == 0 => null,
+            AggregateFunction::Sum => match self.sum {
+                Sum::Integer(s) => Field::from(s as u64),
+                Sum::Float(s) => Field::from(s),
+            },
+            AggregateFunction::Avg if self.count == 0 => null,
+            AggregateFunction::Avg => {
+                let sum = match self.sum {
+                    Sum::Integer(s) => s as f64,
+                    Sum::Float(s) => s,
+                };
+                Field::from(sum / self.count as f64)
+            }
+            AggregateFunction::Min | AggregateFunction::Max => self.extreme.unwrap_or(null),
+        }
+    }
+}
@@ -1,20 +1,59 @@
+mod overflow;
 mod page;
 mod range;
 
+use std::collections::HashSet;
 use std::marker::PhantomData;
-use std::mem;
 use std::rc::Rc;
 
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+
+pub use self::overflow::PayloadReader;
 use self::page::*;
-pub use self::page::{Cell, InteriorCell};
+pub use self::page::{
+    index_max_local, local_payload_len, overflow_page, table_max_local, BTreeKind, Cell,
+    InteriorCell, Page, PageIter, PageType,
+};
 pub use self::range::*;
+use crate::errors::*;
 use crate::pager::Pager;
-use crate::Result;
 
 // Interior pages have an extra right-pointer.
 const PAGE_INTERIOR_HEADER_LEN: usize = 12;
 const PAGE_LEAF_HEADER_LEN: usize = 8;
 
+// SQLite won't go deeper than this (BTCURSOR_MAX_DEPTH), so a b-tree that
+// does must have a cycle in it.
+const MAX_DEPTH: usize = 20;
+
+// The type of a page of a b-tree of `L` cells. Reading a page from another
+// kind of b-tree would decode its cells as garbage, so that's an error: it
+// means the b-tree's root page is wrong.
+fn page_type<L: Cell>(bytes: &Bytes, page_num: usize, header_offset: usize) -> Result<PageType> {
+    match get_page_type(bytes, header_offset) {
+        Some((kind, ty)) if kind == L::KIND => Ok(ty),
+        _ => bail!(ErrorKind::UnexpectedPageType(
+            page_num,
+            bytes[header_offset],
+            L::KIND
+        )),
+    }
+}
+
+/// Reads a single page of a b-tree of `C` cells, so that its cells can be
+/// decoded without going through the rest of the b-tree. The page's cells
+/// must be ones `C` can decode: a b-tree's leaf and interior cells differ.
+pub fn read_page<C: Cell>(pager: &Pager, page_num: usize) -> Result<Page<C>> {
+    let bytes = pager.get_page(page_num)?;
+    let header_offset = if page_num == 1 { 100 } else { 0 };
+    let header_len = match page_type::<C>(&bytes, page_num, header_offset)? {
+        PageType::Interior => PAGE_INTERIOR_HEADER_LEN,
+        PageType::Leaf => PAGE_LEAF_HEADER_LEN,
+    };
+    Page::new(bytes, header_offset, header_len)
+}
+
 pub struct BTree<K, I, L>
 where
     I: InteriorCell<Key = K>,
@@ -38,7 +77,194 @@ where
         })
     }
 
+    /// The number of levels in the b-tree: 1 if the root is a leaf, 2 if the
+    /// root's children are leaves, and so on. All leaves are at the same
+    /// depth, so this only has to follow the left-most pointers down.
+    pub fn depth(&self) -> Result<usize> {
+        let mut page_num = self.page_num;
+        for depth in 1..=MAX_DEPTH {
+            let bytes = self.pager.get_page(page_num)?;
+            let header_offset = if page_num == 1 { 100 } else { 0 };
+            if page_type::<L>(&bytes, page_num, header_offset)? == PageType::Leaf {
+                return Ok(depth);
+            }
+            let mut interior =
+                Page::<I>::new(bytes, header_offset, PAGE_INTERIOR_HEADER_LEN)?.iter();
+            page_num = match interior.next() {
+                Some(cell) => cell?.left(),
+                None => interior.right(),
+            };
+        }
+        bail!(
+            "B-tree rooted at page {} is more than {} pages deep: it may contain a cycle",
+            self.page_num,
+            MAX_DEPTH
+        );
+    }
+
+    /// Every page the b-tree uses: its interior and leaf pages, and the
+    /// overflow pages of its cells' payloads.
+    pub fn pages(&self) -> Result<Vec<usize>> {
+        let header = &self.pager.header;
+        let usable_size = header.page_size - header.reserved_byes_per_page;
+        let mut pages = HashSet::new();
+        let mut visit = |page_num| {
+            if !pages.insert(page_num) {
+                bail!(
+                    "Page {} is used twice by the b-tree rooted at page {}: it may contain a cycle",
+                    page_num,
+                    self.page_num
+                );
+            }
+            self.pager.get_page(page_num)
+        };
+
+        let mut overflows = vec![];
+        let mut stack = vec![self.page_num];
+        while let Some(page_num) = stack.pop() {
+            let bytes = visit(page_num)?;
+            let header_offset = if page_num == 1 { 100 } else { 0 };
+            match page_type::<L>(&bytes, page_num, header_offset)? {
+                PageType::Interior => {
+                    let page = Page::<I>::new(bytes, header_offset, PAGE_INTERIOR_HEADER_LEN)?;
+                    for index in 0..page.len() {
+                        let cell = page.cell(index)?;
+                        // Every interior cell starts with its left-pointer,
+                        // so there's no need to decode the rest.
+                        if cell.len() < 4 {
+                            bail!("Interior cell too short: {} bytes", cell.len());
+                        }
+                        stack.push(BigEndian::read_u32(&cell) as usize);
+                        overflows.extend(I::overflow_page(&cell, usable_size)?);
+                    }
+                    stack.push(page.right());
+                }
+                PageType::Leaf => {
+                    let page = Page::<L>::new(bytes, header_offset, PAGE_LEAF_HEADER_LEN)?;
+                    for index in 0..page.len() {
+                        overflows.extend(L::overflow_page(&page.cell(index)?, usable_size)?);
+                    }
+                }
+            }
+        }
+
+        // "The first four bytes of each overflow page are a big-endian integer
+        //  which is the page number of the next page in the chain, or zero for
+        //  the final page in the chain."
+        for mut page_num in overflows {
+            while page_num != 0 {
+                page_num = BigEndian::read_u32(&visit(page_num)?) as usize;
+            }
+        }
+
+        let mut pages: Vec<_> = pages.into_iter().collect();
+        pages.sort();
+        Ok(pages)
+    }
+
+    /// The b-tree's leaf pages, in the order they appear in the file rather
+    /// than in key order. Only the interior pages are read to find them.
+    pub fn leaves(&self) -> Result<Vec<usize>> {
+        let depth = self.depth()?;
+        let mut leaves = vec![];
+        let mut stack = vec![(self.page_num, 1)];
+        while let Some((page_num, level)) = stack.pop() {
+            if level == depth {
+                leaves.push(page_num);
+                continue;
+            }
+            let bytes = self.pager.get_page(page_num)?;
+            let header_offset = if page_num == 1 { 100 } else { 0 };
+            if page_type::<L>(&bytes, page_num, header_offset)? == PageType::Leaf {
+                bail!(
+                    "Leaf page {} is shallower than the other leaves of the b-tree rooted at page {}",
+                    page_num,
+                    self.page_num
+                );
+            }
+            let page = Page::<I>::new(bytes, header_offset, PAGE_INTERIOR_HEADER_LEN)?;
+            stack.push((page.right(), level + 1));
+            for cell in page.iter() {
+                stack.push((cell?.left(), level + 1));
+            }
+        }
+
+        leaves.sort();
+        if let Some(pair) = leaves.windows(2).find(|pair| pair[0] == pair[1]) {
+            bail!(
+                "Page {} is used twice by the b-tree rooted at page {}: it may contain a cycle",
+                pair[0],
+                self.page_num
+            );
+        }
+        Ok(leaves)
+    }
+
+    /// The number of cells in the b-tree's leaves, which for a table is its
+    /// number of rows. Only the page headers are read, so no cells are
+    /// decoded.
+    pub fn count(&self) -> Result<usize> {
+        let mut cells = 0;
+        for page_num in self.leaves()? {
+            cells += read_page::<L>(&self.pager, page_num)?.len();
+        }
+        Ok(cells)
+    }
+
+    /// Estimates the number of cells in the b-tree's leaves, from the number
+    /// of leaf pages and the number of cells on up to `samples` of them,
+    /// spread evenly across the b-tree. Only the sampled leaves are read.
+    pub fn estimate_len(&self, samples: usize) -> Result<usize> {
+        let leaves = self.leaves()?;
+        let samples = samples.max(1).min(leaves.len());
+        let mut cells = 0;
+        for i in 0..samples {
+            let page_num = leaves[i * leaves.len() / samples];
+            cells += read_page::<L>(&self.pager, page_num)?.len();
+        }
+        Ok(cells * leaves.len() / samples)
+    }
+
+    /// Iterates over the cells of every leaf page, visiting the pages in the
+    /// order they appear in the file. That makes for sequential reads, but
+    /// the order of the cells is unspecified.
+    pub fn iter_physical(self) -> Result<PhysicalIter<L>> {
+        Ok(PhysicalIter {
+            pages: self.leaves()?.into_iter(),
+            pager: self.pager,
+            leaf: None,
+        })
+    }
+
     pub fn iter_range<R>(self, range: R) -> BTreeIter<K, I, L, R>
+    where
+        R: Range<Key = K>,
+    {
+        self.iter_with(range, false)
+    }
+
+    pub fn iter(self) -> BTreeIter<K, I, L, RangeAll<K>> {
+        // We're going to visit every page, so it's worth reading ahead.
+        self.iter_with(RangeAll::new(), true)
+    }
+
+    /// Iterates over the cells of the b-tree's leaves in descending key
+    /// order. Only the pages on the way to the cells that are read are
+    /// visited, so taking the first few cells is cheap.
+    pub fn iter_rev(self) -> RevIter<I, L> {
+        let mut iter = RevIter {
+            pager: self.pager,
+            interiors: vec![],
+            leaf: None,
+            error: None,
+        };
+        if let Err(e) = iter.descend(self.page_num) {
+            iter.error = Some(e);
+        }
+        iter
+    }
+
+    fn iter_with<R>(self, range: R, readahead: bool) -> BTreeIter<K, I, L, R>
     where
         R: Range<Key = K>,
     {
@@ -48,14 +274,14 @@ where
             leaf: None,
             range,
             last_comparison: RangeComparison::InRange,
+            readahead,
+            error: None,
         };
-        iter.descend(self.page_num);
+        if let Err(e) = iter.descend(self.page_num) {
+            iter.error = Some(e);
+        }
         iter
     }
-
-    pub fn iter(self) -> BTreeIter<K, I, L, RangeAll<K>> {
-        self.iter_range(RangeAll::new())
-    }
 }
 
 impl<K, I, L> BTree<K, I, L>
@@ -64,10 +290,217 @@ where
     I: InteriorCell<Key = K>,
     L: Cell<Key = K>,
 {
-    pub fn get(self, key: K) -> Option<L> {
-        let mut iter: Vec<_> = self.iter_range(RangeOne::new(key)).collect();
+    /// Where the cell for `key` is stored: the number of its leaf page, and
+    /// its index among that page's cells.
+    pub fn locate(&self, key: K) -> Result<Option<(usize, usize)>> {
+        let mut page_num = self.page_num;
+        for _ in 0..MAX_DEPTH {
+            let bytes = self.pager.get_page(page_num)?;
+            let header_offset = if page_num == 1 { 100 } else { 0 };
+            match page_type::<L>(&bytes, page_num, header_offset)? {
+                PageType::Interior => {
+                    let mut page =
+                        Page::<I>::new(bytes, header_offset, PAGE_INTERIOR_HEADER_LEN)?.iter();
+                    // The key is in the left-pointer of the first cell whose
+                    // key is >= it, or the right-pointer if there isn't one.
+                    let mut next = None;
+                    for cell in &mut page {
+                        let cell = cell?;
+                        if *cell.key() >= key {
+                            next = Some(cell.left());
+                            break;
+                        }
+                    }
+                    page_num = next.unwrap_or_else(|| page.right());
+                }
+                PageType::Leaf => {
+                    let page = Page::<L>::new(bytes, header_offset, PAGE_LEAF_HEADER_LEN)?;
+                    for (index, cell) in page.iter().enumerate() {
+                        if *cell?.key() == key {
+                            return Ok(Some((page_num, index)));
+                        }
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+        bail!(
+            "B-tree rooted at page {} is more than {} pages deep: it may contain a cycle",
+            self.page_num,
+            MAX_DEPTH
+        );
+    }
+
+    pub fn get(self, key: K) -> Result<Option<L>> {
+        let mut iter = self
+            .iter_range(RangeOne::new(key))
+            .collect::<Result<Vec<_>>>()?;
         assert!(iter.len() <= 1);
-        iter.pop()
+        Ok(iter.pop())
+    }
+}
+
+pub struct PhysicalIter<L: Cell> {
+    pager: Rc<Pager>,
+    pages: std::vec::IntoIter<usize>,
+    leaf: Option<PageIter<L>>,
+}
+
+impl<L: Cell> PhysicalIter<L> {
+    fn read_leaf(&self, page_num: usize) -> Result<PageIter<L>> {
+        let bytes = self.pager.get_page(page_num)?;
+        let header_offset = if page_num == 1 { 100 } else { 0 };
+        if page_type::<L>(&bytes, page_num, header_offset)? != PageType::Leaf {
+            bail!("Expected page {} to be a leaf page", page_num);
+        }
+        Ok(Page::<L>::new(bytes, header_offset, PAGE_LEAF_HEADER_LEN)?.iter())
+    }
+}
+
+impl<L: Cell> Iterator for PhysicalIter<L> {
+    type Item = Result<L>;
+
+    fn next(&mut self) -> Option<Result<L>> {
+        loop {
+            if let Some(cell) = self.leaf.as_mut().and_then(|leaf| leaf.next()) {
+                return Some(cell);
+            }
+            let page_num = self.pages.next()?;
+            match self.read_leaf(page_num) {
+                Ok(leaf) => self.leaf = Some(leaf),
+                Err(e) => {
+                    // Stop after an error, so that it's the last thing returned.
+                    self.pages = Vec::new().into_iter();
+                    self.leaf = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+pub struct RevIter<I: InteriorCell, L: Cell> {
+    pager: Rc<Pager>,
+    // The interior pages we've descended through, each with the number of
+    // its children we've still to visit. They're visited from the right, so
+    // that's the right-pointer and then the left-pointers of the cells from
+    // the last to the first.
+    interiors: Vec<(Page<I>, usize)>,
+    // The leaf we're reading, with the number of its cells still to read.
+    leaf: Option<(Page<L>, usize)>,
+    // An error hit while descending to the root page, to be returned by the
+    // first call to `next()`.
+    error: Option<Error>,
+}
+
+impl<I: InteriorCell, L: Cell> RevIter<I, L> {
+    fn descend(&mut self, page_num: usize) -> Result<()> {
+        if self.interiors.len() >= MAX_DEPTH {
+            bail!(
+                "B-tree is more than {} pages deep at page {}: it may contain a cycle",
+                MAX_DEPTH,
+                page_num
+            );
+        }
+        let bytes = self.pager.get_page(page_num)?;
+        let header_offset = if page_num == 1 { 100 } else { 0 };
+        match page_type::<L>(&bytes, page_num, header_offset)? {
+            PageType::Interior => {
+                let page = Page::<I>::new(bytes, header_offset, PAGE_INTERIOR_HEADER_LEN)?;
+                let children = page.len() + 1;
+                self.interiors.push((page, children));
+            }
+            PageType::Leaf => {
+                let page = Page::<L>::new(bytes, header_offset, PAGE_LEAF_HEADER_LEN)?;
+                let cells = page.len();
+                self.leaf = Some((page, cells));
+            }
+        }
+        Ok(())
+    }
+
+    // The next child to visit, or None once every child of every interior
+    // page we've descended through has been visited. A left-pointer comes
+    // with the entry of its cell, if it has one, which is bigger than
+    // everything in the child and so comes before it.
+    fn next_child(&mut self) -> Result<Option<(usize, Option<Bytes>)>> {
+        while let Some((page, remaining)) = self.interiors.last_mut() {
+            if *remaining == 0 {
+                self.interiors.pop();
+                continue;
+            }
+            *remaining -= 1;
+            if *remaining == page.len() {
+                return Ok(Some((page.right(), None)));
+            }
+            let cell = I::from_bytes(page.cell(*remaining)?)?;
+            return Ok(Some((cell.left(), cell.entry())));
+        }
+        Ok(None)
+    }
+
+    // Gives up on the iteration after an error, so that it's the last thing
+    // the iterator returns.
+    fn fail(&mut self, error: Error) -> Option<Result<L>> {
+        self.interiors.clear();
+        self.leaf = None;
+        Some(Err(error))
+    }
+}
+
+impl<I: InteriorCell, L: Cell> Iterator for RevIter<I, L> {
+    type Item = Result<L>;
+
+    fn next(&mut self) -> Option<Result<L>> {
+        if let Some(e) = self.error.take() {
+            return self.fail(e);
+        }
+        loop {
+            if let Some((ref page, ref mut remaining)) = self.leaf {
+                if *remaining > 0 {
+                    *remaining -= 1;
+                    return Some(page.cell(*remaining).and_then(L::from_bytes));
+                }
+            }
+            self.leaf = None;
+            match self.next_child() {
+                Ok(Some((page_num, entry))) => {
+                    if let Err(e) = self.descend(page_num) {
+                        return self.fail(e);
+                    }
+                    // Descending only reads the child's page, so the entry
+                    // can still be returned before any of its cells.
+                    if let Some(entry) = entry {
+                        return match L::from_bytes(entry) {
+                            Ok(cell) => Some(Ok(cell)),
+                            Err(e) => self.fail(e),
+                        };
+                    }
+                }
+                Ok(None) => return None,
+                Err(e) => return self.fail(e),
+            }
+        }
+    }
+}
+
+// An interior page that `BTreeIter` has descended through.
+struct Level<I: InteriorCell> {
+    // The cells whose left-pointers we've still to visit, or None once we're
+    // visiting the right-pointer or know there's nothing more in range.
+    cells: Option<PageIter<I>>,
+    // The entry of the cell whose left-pointer we're visiting, if it has one,
+    // which comes after everything its left-pointer leads to.
+    entry: Option<Bytes>,
+}
+
+// Derived Clone impls would needlessly require I: Clone.
+impl<I: InteriorCell> Clone for Level<I> {
+    fn clone(&self) -> Self {
+        Level {
+            cells: self.cells.clone(),
+            entry: self.entry.clone(),
+        }
     }
 }
 
@@ -78,12 +511,18 @@ where
     R: Range<Key = K>,
 {
     pager: Rc<Pager>,
-    interiors: Vec<Option<PageIter<I>>>,
+    interiors: Vec<Level<I>>,
     leaf: Option<PageIter<L>>,
     // We remember the last comparison we did, so that when we're ascending
     // back up our stack we can decide whether to visit right-pointers.
     last_comparison: RangeComparison,
     range: R,
+    // Whether to prefetch the pages following each leaf. Leaves often sit
+    // next to each other in the file, so this helps sequential scans.
+    readahead: bool,
+    // An error hit while descending to the root page, to be returned by the
+    // first call to `next()`.
+    error: Option<Error>,
 }
 
 impl<K, I, L, R> BTreeIter<K, I, L, R>
@@ -92,24 +531,50 @@ where
     L: Cell<Key = K>,
     R: Range<Key = K>,
 {
-    fn descend(&mut self, page_num: usize) {
-        let bytes = self.pager.get_page(page_num).unwrap();
+    fn descend(&mut self, page_num: usize) -> Result<()> {
+        if self.interiors.len() >= MAX_DEPTH {
+            bail!(
+                "B-tree is more than {} pages deep at page {}: it may contain a cycle",
+                MAX_DEPTH,
+                page_num
+            );
+        }
+        let bytes = self.pager.get_page(page_num)?;
         let header_offset = if page_num == 1 { 100 } else { 0 };
-        let ty = get_page_type(&bytes, header_offset);
+        let ty = page_type::<L>(&bytes, page_num, header_offset)?;
         match ty {
-            PageType::Interior => self.interiors.push(Some(
-                Page::<I>::new(bytes, header_offset, PAGE_INTERIOR_HEADER_LEN)
-                    .unwrap()
-                    .iter(),
-            )),
+            PageType::Interior => self.interiors.push(Level {
+                cells: Some(Page::<I>::new(bytes, header_offset, PAGE_INTERIOR_HEADER_LEN)?.iter()),
+                entry: None,
+            }),
             PageType::Leaf => {
-                self.leaf = Some(
-                    Page::<L>::new(bytes, header_offset, PAGE_LEAF_HEADER_LEN)
-                        .unwrap()
-                        .iter(),
-                )
+                if self.readahead {
+                    // Readahead is only an optimisation: if it fails, the
+                    // error will surface when the page is actually read.
+                    let _ = self.pager.prefetch(page_num + 1, self.pager.readahead());
+                }
+                self.leaf = Some(Page::<L>::new(bytes, header_offset, PAGE_LEAF_HEADER_LEN)?.iter())
             }
         };
+        Ok(())
+    }
+
+    // Gives up on the iteration after an error, so that it's the last thing
+    // the iterator returns.
+    fn fail(&mut self, error: Error) -> Option<Result<L>> {
+        self.interiors.clear();
+        self.leaf = None;
+        Some(Err(error))
+    }
+
+    // Once we've seen a key past the end of the range, there's nothing left
+    // to return from any of the pages on our stack. We keep them, so that we
+    // still know how deep we are, but forget their cells and entries.
+    fn finish(&mut self) {
+        for level in self.interiors.iter_mut() {
+            level.cells = None;
+            level.entry = None;
+        }
     }
 
     fn compare<C: Cell<Key = K>>(&mut self, cell: &C) -> &RangeComparison {
@@ -118,22 +583,49 @@ where
     }
 }
 
+/// Cloning an iterator gives an independent one which continues from the same
+/// position, which is cheap as pages are reference counted.
+impl<K, I, L, R> Clone for BTreeIter<K, I, L, R>
+where
+    I: InteriorCell<Key = K>,
+    L: Cell<Key = K>,
+    R: Range<Key = K> + Clone,
+{
+    fn clone(&self) -> Self {
+        BTreeIter {
+            pager: self.pager.clone(),
+            interiors: self.interiors.clone(),
+            leaf: self.leaf.clone(),
+            last_comparison: self.last_comparison,
+            range: self.range.clone(),
+            readahead: self.readahead,
+            // Errors can't be cloned, so the clone gets one with the same
+            // message.
+            error: self.error.as_ref().map(|e| e.to_string().into()),
+        }
+    }
+}
+
 impl<K, I, L, R> Iterator for BTreeIter<K, I, L, R>
 where
     I: InteriorCell<Key = K>,
     L: Cell<Key = K>,
     R: Range<Key = K>,
 {
-    type Item = L;
+    type Item = Result<L>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return self.fail(e);
+        }
         loop {
-            match mem::replace(&mut self.leaf, None) {
+            match self.leaf.take() {
                 // We're iterating through the cells in a leaf page.
                 // Attempt to get the next cell and then decide whether to yield it.
                 Some(mut leaf) => {
                     match leaf.next() {
-                        Some(cell) => {
+                        Some(Err(e)) => return self.fail(e),
+                        Some(Ok(cell)) => {
                             match *self.compare(&cell) {
                                 // Silently ignore this value, but continue to  iterate through
                                 // the leaf.
@@ -143,7 +635,13 @@ where
                                 // Return this cell and then continue to iterate through this leaf.
                                 RangeComparison::InRange => {
                                     self.leaf = Some(leaf);
-                                    return Some(cell);
+                                    return Some(Ok(cell));
+                                }
+                                // Return this cell, but all cells that come after it are going to
+                                // be Greater. Don't put self.leaf back, so that we start to ascend
+                                // back up.
+                                RangeComparison::UpperBoundary => {
+                                    return Some(Ok(cell));
                                 }
                                 // All cells that come after this are going to  be Greater. Don't
                                 // put self.leaf back, so that we start to ascend back up.
@@ -158,59 +656,292 @@ where
                 // We've just finished iterating through the cells in a leaf and
                 // now need to move onto the next leaf.
                 None => {
-                    match self.interiors.pop() {
-                        // We were previously iterating through the left-pointer
-                        // of one of the cells in this interior page. See if
-                        // there's another cell to descend into, otherwise
-                        // look at the right-pointer.
-                        Some(Some(mut interior)) => {
-                            match interior.next() {
-                                // There's another cell in this interior page
-                                // for us to descend into.
-                                Some(cell) => {
-                                    self.interiors.push(Some(interior));
-
-                                    match *self.compare(&cell) {
-                                        // If the key is Greater than the range, then there's no
-                                        // need to descend into the right-pointer, as all keys are
-                                        // <= the key. Continue to iterate through this interior
-                                        // page, as it may contain bigger keys.
-                                        RangeComparison::Greater => {}
-                                        _ => {
-                                            self.descend(cell.left());
-                                        }
+                    // Empty interiors stack means we've reached the root again and
+                    // have iterated down all of its children (left and right).
+                    // We're done!
+                    let mut level = self.interiors.pop()?;
+
+                    // We've come back up from a cell's left-pointer, and the
+                    // cell has an entry of its own, which comes next.
+                    if let Some(entry) = level.entry.take() {
+                        self.interiors.push(level);
+                        let cell = match L::from_bytes(entry) {
+                            Ok(cell) => cell,
+                            Err(e) => return self.fail(e),
+                        };
+                        match *self.compare(&cell) {
+                            RangeComparison::Less => continue,
+                            RangeComparison::InRange => return Some(Ok(cell)),
+                            RangeComparison::UpperBoundary => {
+                                self.finish();
+                                return Some(Ok(cell));
+                            }
+                            RangeComparison::Greater => {
+                                self.finish();
+                                continue;
+                            }
+                        }
+                    }
+
+                    // We were previously iterating through the left-pointer
+                    // of one of the cells in this interior page. See if
+                    // there's another cell to descend into, otherwise
+                    // look at the right-pointer. If we were iterating through
+                    // the right pointer of an interior page instead, ignore it:
+                    // we'll loop back round and move up two levels of the stack
+                    // in one go.
+                    if let Some(mut interior) = level.cells {
+                        match interior.next() {
+                            Some(Err(e)) => return self.fail(e),
+                            // There's another cell in this interior page
+                            // for us to descend into.
+                            Some(Ok(cell)) => {
+                                match *self.compare(&cell) {
+                                    // The left-pointer holds keys <= this key, so may hold
+                                    // some in the range, but every key after it is Greater.
+                                    // Descend, but don't come back: forget every page on
+                                    // our stack, so we finish once we're done with the
+                                    // left-pointer and the cell's own entry.
+                                    RangeComparison::UpperBoundary => {
+                                        self.finish();
+                                        self.interiors.push(Level {
+                                            cells: None,
+                                            entry: cell.entry(),
+                                        });
+                                    }
+                                    RangeComparison::Greater => {
+                                        self.finish();
+                                        self.interiors.push(Level {
+                                            cells: None,
+                                            entry: None,
+                                        });
                                     }
+                                    // Every key in the left-pointer is <= this key, so
+                                    // is Less than the range. Skip it and move on to the
+                                    // next cell.
+                                    RangeComparison::Less => {
+                                        self.interiors.push(Level {
+                                            cells: Some(interior),
+                                            entry: None,
+                                        });
+                                        continue;
+                                    }
+                                    // Descend and then continue to iterate through this
+                                    // interior page, as it may contain bigger keys.
+                                    RangeComparison::InRange => self.interiors.push(Level {
+                                        cells: Some(interior),
+                                        entry: cell.entry(),
+                                    }),
+                                }
+                                if let Err(e) = self.descend(cell.left()) {
+                                    return self.fail(e);
                                 }
-                                // There are no more left-pointers on this page.
-                                None => {
-                                    match self.last_comparison {
-                                        // If the last comparison was Greater than the range, or on
-                                        // the upper boundary, then we know the right-pointer
-                                        // contains only keys which are Greater. Don't descend.
-                                        RangeComparison::UpperBoundary
-                                        | RangeComparison::Greater => {}
-                                        _ => {
-                                            // We push None so that we can keep track of our  level
-                                            // within the tree. We'll silently move past it when we
-                                            // ascend later.
-                                            self.interiors.push(None);
-                                            self.descend(interior.right());
+                            }
+                            // There are no more left-pointers on this page.
+                            None => {
+                                match self.last_comparison {
+                                    // If the last comparison was Greater than the range, or on
+                                    // the upper boundary, then we know the right-pointer
+                                    // contains only keys which are Greater. Don't descend.
+                                    RangeComparison::UpperBoundary | RangeComparison::Greater => {}
+                                    _ => {
+                                        // We push a level without cells so that we can keep
+                                        // track of our level within the tree. We'll silently
+                                        // move past it when we ascend later.
+                                        self.interiors.push(Level {
+                                            cells: None,
+                                            entry: None,
+                                        });
+                                        if let Err(e) = self.descend(interior.right()) {
+                                            return self.fail(e);
                                         }
                                     }
                                 }
                             }
                         }
-                        // We were previously iterating through the right pointer of an
-                        // interior page. Ignore it - we'll loop back round and move up
-                        // two levels of the stack in one go.
-                        Some(None) => {}
-                        // Empty interiors stack means we've reached the root again and
-                        // have iterated down all of its children (left and right).
-                        // We're done!
-                        None => return None,
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use byteorder::{BigEndian, ByteOrder};
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::table::{Table, TableInteriorCell, TableLeafCell};
+    use crate::testing::{open_doctored_fixture, open_fixture};
+    use crate::util::read_varint_from;
+
+    const SQL: &str = "CREATE TABLE numbers(id integer primary key, value int, label text)";
+
+    // Points the first cell of the numbers table's root (an interior page) at
+    // `left`.
+    fn scan_with_left_pointer(left: u32) -> Result<usize> {
+        let pager = open_doctored_fixture("large.db", |bytes| {
+            let page = &mut bytes[4096..8192];
+            let cell = BigEndian::read_u16(&page[12..]) as usize;
+            BigEndian::write_u32(&mut page[cell..], left);
+        });
+        Table::new(pager, 2, "numbers", SQL)?.len()
+    }
+
+    // A cell that decodes only the rowid of a table leaf cell.
+    struct RowId(i64);
+
+    impl Cell for RowId {
+        type Key = i64;
+        const KIND: BTreeKind = BTreeKind::Table;
+
+        fn from_bytes(bytes: Bytes) -> Result<RowId> {
+            let (_, len) = read_varint_from(&bytes)?;
+            let (row_id, _) = read_varint_from(&bytes[len..])?;
+            Ok(RowId(row_id as i64))
+        }
+
+        fn key(&self) -> &i64 {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn test_custom_cell() {
+        let schema = open_fixture("large.db");
+        let table = schema.table("numbers").unwrap();
+        let (page_num, _) = table.locate(1).unwrap().unwrap();
+        let pager = schema.pager();
+
+        let page = read_page::<RowId>(pager, page_num).unwrap();
+        assert_eq!(page.page_type(), Some((BTreeKind::Table, PageType::Leaf)));
+        let row_ids: Vec<_> = pager
+            .cells::<RowId>(page_num)
+            .unwrap()
+            .map(|cell| cell.unwrap().0)
+            .collect();
+        let expected: Vec<_> = (1..=row_ids.len() as i64).collect();
+        assert_eq!(row_ids, expected);
+
+        // The root of an index isn't a page of a table b-tree.
+        let schema = open_fixture("indexed.db");
+        assert!(schema.pager().cells::<RowId>(3).is_err());
+    }
+
+    type RowIdBTree = BTree<i64, TableInteriorCell, RowId>;
+
+    fn row_ids<I: Iterator<Item = Result<RowId>>>(cells: I) -> Vec<i64> {
+        cells.map(|cell| cell.unwrap().0).collect()
+    }
+
+    #[test]
+    fn test_single_leaf_root() {
+        // The sales table is small enough that its root page is a leaf.
+        let schema = open_fixture("sales.db");
+        let btree = || RowIdBTree::new(schema.pager().clone(), 2).unwrap();
+        assert_eq!(btree().depth().unwrap(), 1);
+        assert_eq!(btree().leaves().unwrap(), vec![2]);
+
+        let expected: Vec<_> = (1..=11).collect();
+        assert_eq!(row_ids(btree().iter()), expected);
+        assert_eq!(row_ids(btree().iter_physical().unwrap()), expected);
+        let mut reversed = expected.clone();
+        reversed.reverse();
+        assert_eq!(row_ids(btree().iter_rev()), reversed);
+        assert_eq!(btree().get(11).unwrap().map(|cell| cell.0), Some(11));
+        assert!(btree().get(12).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_interior_root_without_cells() {
+        // Empty the numbers table's root of cells, leaving only its
+        // right-pointer, which we point at the leaf its first cell did.
+        let mut leaf = 0;
+        let pager = open_doctored_fixture("large.db", |bytes| {
+            let page = &mut bytes[4096..8192];
+            let cell = BigEndian::read_u16(&page[12..]) as usize;
+            leaf = BigEndian::read_u32(&page[cell..]);
+            BigEndian::write_u16(&mut page[3..], 0);
+            BigEndian::write_u32(&mut page[8..], leaf);
+        });
+        let btree = || RowIdBTree::new(pager.clone(), 2).unwrap();
+        let expected = row_ids(pager.cells::<RowId>(leaf as usize).unwrap());
+        assert_eq!(expected[0], 1);
+
+        assert_eq!(btree().depth().unwrap(), 2);
+        assert_eq!(btree().leaves().unwrap(), vec![leaf as usize]);
+        assert_eq!(row_ids(btree().iter()), expected);
+        let mut reversed = expected.clone();
+        reversed.reverse();
+        assert_eq!(row_ids(btree().iter_rev()), reversed);
+        let last = *expected.last().unwrap();
+        assert_eq!(btree().get(last).unwrap().map(|cell| cell.0), Some(last));
+        assert_eq!(btree().count().unwrap(), expected.len());
+    }
+
+    #[test]
+    fn test_left_pointer_out_of_range() {
+        for &left in &[0, 26, 0xffff_ffff] {
+            match scan_with_left_pointer(left) {
+                Err(Error(ErrorKind::InvalidPageNumber(page), _)) => {
+                    assert_eq!(page, left as usize)
+                }
+                other => panic!("Expected InvalidPageNumber, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_cycle_detected() {
+        // The root pointing back at itself.
+        let err = scan_with_left_pointer(2).unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+    }
+
+    #[test]
+    fn test_clone_mid_scan() {
+        let pager = Rc::new(Pager::open("fixtures/large.db").unwrap());
+        let btree = BTree::<i64, TableInteriorCell, TableLeafCell>::new(pager, 2).unwrap();
+        let mut iter = btree.iter();
+        for _ in 0..1234 {
+            iter.next().unwrap().unwrap();
+        }
+
+        let fork = iter.clone();
+        let rest: Vec<i64> = iter.map(|cell| cell.unwrap().row_id).collect();
+        let forked: Vec<i64> = fork.map(|cell| cell.unwrap().row_id).collect();
+        assert_eq!(rest, (1235..=5000).collect::<Vec<_>>());
+        assert_eq!(rest, forked);
+    }
+
+    fn deep_btree() -> (Rc<Pager>, BTree<i64, TableInteriorCell, TableLeafCell>) {
+        let pager = Rc::new(Pager::open("fixtures/deep.db").unwrap());
+        let btree = BTree::new(pager.clone(), 2).unwrap();
+        (pager, btree)
+    }
+
+    #[test]
+    fn test_range_skips_subtrees() {
+        let (pager, btree) = deep_btree();
+        let row_ids: Vec<i64> = btree
+            .iter_range(RangeGtEq::new(19990))
+            .map(|cell| cell.unwrap().row_id)
+            .collect();
+        let mut expected: Vec<i64> = (19990..=20000).collect();
+        expected.push(1 << 62);
+        assert_eq!(row_ids, expected);
+        // The tree is three levels deep, and the range covers at most a
+        // couple of leaves.
+        assert!(pager.pages_read() < 10, "read {} pages", pager.pages_read());
+
+        for &key in &[-1000, 1, 7777, 20000] {
+            let (pager, btree) = deep_btree();
+            let cell = btree.get(key).unwrap().unwrap();
+            assert_eq!(cell.row_id, key);
+            assert_eq!(pager.pages_read(), 3);
+        }
+        let (_, btree) = deep_btree();
+        assert!(btree.get(12345678).unwrap().is_none());
+    }
+}
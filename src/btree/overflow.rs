@@ -0,0 +1,77 @@
+use std::cmp;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+
+use crate::pager::Pager;
+
+/// Reads a cell's payload, following its chain of overflow pages as the
+/// payload is read rather than reassembling it up front. This means a large
+/// payload can be read a piece at a time, in bounded memory.
+pub struct PayloadReader {
+    pager: Rc<Pager>,
+    // The unread part of the page currently being read from.
+    chunk: Bytes,
+    // The next overflow page in the chain, or 0 if there are no more.
+    next_page: usize,
+    // How many bytes of the payload are left to read, including `chunk`.
+    remaining: usize,
+    // How many overflow pages have been read, so that a cycle in the chain
+    // can't keep us reading forever.
+    pages_read: usize,
+}
+
+impl PayloadReader {
+    /// A reader of a `len`-byte payload, of which `local` is the part kept
+    /// in the cell. The rest starts on `overflow_page`.
+    pub fn new(pager: Rc<Pager>, local: Bytes, overflow_page: Option<usize>, len: usize) -> Self {
+        PayloadReader {
+            pager,
+            chunk: local,
+            next_page: overflow_page.unwrap_or(0),
+            remaining: len,
+            pages_read: 0,
+        }
+    }
+
+    // "The first four bytes of each overflow page are a big-endian integer
+    //  which is the page number of the next page in the chain, or zero for
+    //  the final page in the chain. The fifth byte through the last usable
+    //  byte are used to hold overflow content."
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let header = &self.pager.header;
+        if self.next_page == 0 || self.pages_read >= header.num_pages {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Overflow chain ends with {} bytes unread", self.remaining),
+            ));
+        }
+        let page = self
+            .pager
+            .get_page(self.next_page)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        self.pages_read += 1;
+        self.next_page = BigEndian::read_u32(&page) as usize;
+        let usable_size = header.page_size - header.reserved_byes_per_page;
+        self.chunk = page.slice(4, cmp::min(usable_size, 4 + self.remaining));
+        Ok(())
+    }
+}
+
+impl Read for PayloadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.chunk.is_empty() {
+            self.next_chunk()?;
+        }
+        let len = cmp::min(buf.len(), cmp::min(self.chunk.len(), self.remaining));
+        buf[..len].copy_from_slice(&self.chunk[..len]);
+        self.chunk.advance(len);
+        self.remaining -= len;
+        Ok(len)
+    }
+}
@@ -2,17 +2,118 @@ use std::marker::PhantomData;
 
 use bytes::{BigEndian, ByteOrder, Bytes};
 
-use crate::Result;
+use crate::errors::*;
 
+/// A cell of a b-tree page, decoded from the bytes starting at its cell
+/// pointer (which run to the end of the page, not just the end of the cell).
+///
+/// Implementing this for a new type lets the b-tree code decode cells some
+/// other way, such as to read only part of each cell:
+///
+/// ```ignore
+/// // The rowid of each cell of a table leaf page, without its record.
+/// struct RowId(i64);
+///
+/// impl Cell for RowId {
+///     type Key = i64;
+///     const KIND: BTreeKind = BTreeKind::Table;
+///
+///     fn from_bytes(bytes: Bytes) -> Result<RowId> {
+///         let (_, len) = read_varint_from(&bytes)?; // payload length
+///         let (row_id, _) = read_varint_from(&bytes[len..])?;
+///         Ok(RowId(row_id as i64))
+///     }
+///
+///     fn key(&self) -> &i64 {
+///         &self.0
+///     }
+/// }
+///
+/// let row_ids = pager.cells::<RowId>(page_num)?;
+/// ```
 pub trait Cell: Sized {
     type Key;
 
+    /// The kind of b-tree the cell is found in.
+    const KIND: BTreeKind;
+
     fn from_bytes(_: Bytes) -> Result<Self>;
     fn key(&self) -> &Self::Key;
+
+    /// The first overflow page of the cell starting at `bytes`, if its
+    /// payload was too big to fit on the page. Cells without a payload never
+    /// overflow.
+    fn overflow_page(_bytes: &Bytes, _usable_size: usize) -> Result<Option<usize>> {
+        Ok(None)
+    }
+}
+
+/// How much of a payload table leaf cells keep on the page.
+pub fn table_max_local(usable_size: usize) -> usize {
+    usable_size - 35
+}
+
+/// How much of a payload index cells keep on the page.
+pub fn index_max_local(usable_size: usize) -> usize {
+    (usable_size - 12) * 64 / 255 - 23
+}
+
+/// How many bytes of a payload of `len` bytes are kept in its cell, with the
+/// rest on overflow pages.
+pub fn local_payload_len(len: usize, max_local: usize, usable_size: usize) -> usize {
+    if len <= max_local {
+        return len;
+    }
+    let min_local = (usable_size - 12) * 32 / 255 - 23;
+    let k = min_local + (len - min_local) % (usable_size - 4);
+    if k <= max_local {
+        k
+    } else {
+        min_local
+    }
+}
+
+/// Finds the overflow page of a payload of `len` bytes starting at `position`
+/// in a cell, following the rules in the file format's description of
+/// "Cell Payload Overflow Pages".
+pub fn overflow_page(
+    bytes: &Bytes,
+    position: usize,
+    len: usize,
+    max_local: usize,
+    usable_size: usize,
+) -> Result<Option<usize>> {
+    if len <= max_local {
+        return Ok(None);
+    }
+    let pointer = position + local_payload_len(len, max_local, usable_size);
+    if pointer + 4 > bytes.len() {
+        bail!(
+            "Overflow page pointer at {} is past the end of the page",
+            pointer
+        );
+    }
+    Ok(Some(BigEndian::read_u32(&bytes[pointer..]) as usize))
 }
 
 pub trait InteriorCell: Cell {
     fn left(&self) -> usize;
+
+    /// The entry the cell holds, laid out as a leaf cell's would be, if it
+    /// holds one. An index b-tree's interior cells are entries in their own
+    /// right, which sort between the cells of their left and right children,
+    /// but a table's only hold the rowid that divides them.
+    fn entry(&self) -> Option<Bytes> {
+        None
+    }
+}
+
+/// Whether a b-tree holds a table, keyed by rowid, or an index (or WITHOUT
+/// ROWID table), keyed by record.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BTreeKind {
+    Table,
+    Index,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -21,15 +122,20 @@ pub enum PageType {
     Leaf,
 }
 
-pub fn get_page_type(bytes: &Bytes, header_offset: usize) -> PageType {
-    if bytes[header_offset] & 0x8 == 0x8 {
-        PageType::Leaf
-    } else {
-        PageType::Interior
+/// The kind of b-tree a page belongs to and its type, from the flag at the
+/// start of its b-tree page header. None if the flag isn't a valid one.
+pub fn get_page_type(bytes: &Bytes, header_offset: usize) -> Option<(BTreeKind, PageType)> {
+    match bytes[header_offset] {
+        0x02 => Some((BTreeKind::Index, PageType::Interior)),
+        0x05 => Some((BTreeKind::Table, PageType::Interior)),
+        0x0a => Some((BTreeKind::Index, PageType::Leaf)),
+        0x0d => Some((BTreeKind::Table, PageType::Leaf)),
+        _ => None,
     }
 }
 
-#[derive(Clone, Debug)]
+/// A b-tree page, whose cells are decoded as `C`s.
+#[derive(Debug)]
 pub struct Page<C: Cell> {
     data: Bytes,
     header_offset: usize,
@@ -40,14 +146,45 @@ pub struct Page<C: Cell> {
     phantom: PhantomData<C>,
 }
 
+// Derived Clone impls would needlessly require C: Clone.
+impl<C: Cell> Clone for Page<C> {
+    fn clone(&self) -> Self {
+        Page {
+            data: self.data.clone(),
+            header_offset: self.header_offset,
+            header_length: self.header_length,
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl<C: Cell> Page<C> {
     pub fn new(data: Bytes, header_offset: usize, header_length: usize) -> Result<Page<C>> {
-        Ok(Page {
+        if data.len() < header_offset + header_length {
+            bail!("Page too short for its header: {} bytes", data.len());
+        }
+        let page = Page {
             data,
             header_offset,
             header_length,
             phantom: PhantomData,
-        })
+        };
+        // The cell pointer array must fit on the page, after the header.
+        let pointers_end = header_offset + header_length + page.len() * 2;
+        if pointers_end > page.data.len() {
+            bail!(
+                "Page has {} cells, too many for its cell pointers to fit in {} bytes",
+                page.len(),
+                page.data.len()
+            );
+        }
+        Ok(page)
+    }
+
+    /// The kind of b-tree the page belongs to and its type, going by its
+    /// header. See `get_page_type()`.
+    pub fn page_type(&self) -> Option<(BTreeKind, PageType)> {
+        get_page_type(&self.data, self.header_offset)
     }
 
     pub fn header(&self) -> &[u8] {
@@ -69,14 +206,19 @@ impl<C: Cell> Page<C> {
         BigEndian::read_u16(&self.header()[3..5]) as usize
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     // "The two-byte integer at offset 5 designates the start of the cell
     //  content area. A zero value for this integer is interpreted as 65536."
+    // 65536 only fits in a 65536-byte page, where it means the content area
+    // is empty. On smaller pages, treat a zero the same way: as the end of
+    // the page.
     pub fn cell_content_offset(&self) -> usize {
-        let start = BigEndian::read_u16(&self.header()[5..7]);
-        if start == 0 {
-            65536
-        } else {
-            start as usize
+        match BigEndian::read_u16(&self.header()[5..7]) {
+            0 => self.data.len(),
+            start => start as usize,
         }
     }
 
@@ -92,14 +234,21 @@ impl<C: Cell> Page<C> {
         &self.data[offset..offset + len]
     }
 
-    pub fn cell(&self, index: usize) -> Bytes {
-        if index > self.len() {
-            panic!("Attempted to access out-of-bounds cell: {}", index);
+    pub fn cell(&self, index: usize) -> Result<Bytes> {
+        if index >= self.len() {
+            bail!(
+                "Cell {} is out of range (page has {} cells)",
+                index,
+                self.len()
+            );
         }
 
         let cell_pointer = &self.cell_pointers()[index * 2..];
         let cell_offset = BigEndian::read_u16(cell_pointer) as usize;
-        self.data.slice_from(cell_offset)
+        if cell_offset >= self.data.len() {
+            bail!("Cell pointer {} is past the end of the page", cell_offset);
+        }
+        Ok(self.data.slice_from(cell_offset))
     }
 
     pub fn iter(self) -> PageIter<C> {
@@ -107,30 +256,92 @@ impl<C: Cell> Page<C> {
     }
 }
 
+/// Decodes each cell of a page in turn, in the order of its cell pointers.
 pub struct PageIter<C: Cell> {
     page: Page<C>,
     idx: usize,
 }
 
-impl<I: InteriorCell> PageIter<I> {
+impl<C: Cell> Clone for PageIter<C> {
+    fn clone(&self) -> Self {
+        PageIter {
+            page: self.page.clone(),
+            idx: self.idx,
+        }
+    }
+}
+
+impl<I: InteriorCell> Page<I> {
     // "The four-byte page number at offset 8 is the right-most pointer. This
     //  value appears in the header of interior b-tree pages only and is omitted
     //  from all other pages."
     pub fn right(&self) -> usize {
-        BigEndian::read_u32(&self.page.header()[8..12]) as usize
+        BigEndian::read_u32(&self.header()[8..12]) as usize
+    }
+}
+
+impl<I: InteriorCell> PageIter<I> {
+    pub fn right(&self) -> usize {
+        self.page.right()
     }
 }
 
 impl<C: Cell> Iterator for PageIter<C> {
-    type Item = C;
+    type Item = Result<C>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.idx == self.page.len() {
             None
         } else {
-            let v = C::from_bytes(self.page.cell(self.idx)).unwrap();
+            let v = self.page.cell(self.idx).and_then(C::from_bytes);
             self.idx += 1;
             Some(v)
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct NoCell;
+
+    impl Cell for NoCell {
+        type Key = ();
+        const KIND: BTreeKind = BTreeKind::Table;
+
+        fn from_bytes(_: Bytes) -> Result<Self> {
+            Ok(NoCell)
+        }
+
+        fn key(&self) -> &() {
+            &()
+        }
+    }
+
+    fn empty_leaf(page_size: usize, content_offset: u16) -> Page<NoCell> {
+        let mut data = vec![0; page_size];
+        data[0] = 0x0d;
+        BigEndian::write_u16(&mut data[5..], content_offset);
+        Page::new(Bytes::from(data), 0, 8).unwrap()
+    }
+
+    #[test]
+    fn test_empty_cell_content_area() {
+        assert_eq!(empty_leaf(4096, 0).cell_content_offset(), 4096);
+        assert_eq!(empty_leaf(4096, 4096).cell_content_offset(), 4096);
+        assert_eq!(empty_leaf(65536, 0).cell_content_offset(), 65536);
+        assert_eq!(empty_leaf(4096, 4000).cell_content_offset(), 4000);
+        assert_eq!(empty_leaf(4096, 0).len(), 0);
+    }
+
+    #[test]
+    fn test_cells_out_of_range() {
+        assert!(empty_leaf(4096, 0).cell(0).is_err());
+
+        let mut data = vec![0; 512];
+        data[0] = 0x0d;
+        BigEndian::write_u16(&mut data[3..], 300);
+        assert!(Page::<NoCell>::new(Bytes::from(data), 0, 8).is_err());
+    }
+}
@@ -24,6 +24,12 @@ impl<K> RangeAll<K> {
     }
 }
 
+impl<K> Default for RangeAll<K> {
+    fn default() -> RangeAll<K> {
+        RangeAll::new()
+    }
+}
+
 impl<K> Range for RangeAll<K> {
     type Key = K;
 
@@ -32,6 +38,7 @@ impl<K> Range for RangeAll<K> {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct RangeOne<K: Ord>(K);
 
 impl<K: Ord> RangeOne<K> {
@@ -52,6 +59,7 @@ impl<K: Ord> Range for RangeOne<K> {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct RangeGtEq<K: Ord>(K);
 
 impl<K: Ord> RangeGtEq<K> {
@@ -70,3 +78,51 @@ impl<K: Ord> Range for RangeGtEq<K> {
         }
     }
 }
+
+/// The keys from `lower` to `upper`, including both.
+#[derive(Clone, Debug)]
+pub struct RangeBetween<K: Ord> {
+    lower: K,
+    upper: K,
+}
+
+impl<K: Ord> RangeBetween<K> {
+    pub fn new(lower: K, upper: K) -> RangeBetween<K> {
+        RangeBetween { lower, upper }
+    }
+}
+
+impl<K: Ord> Range for RangeBetween<K> {
+    type Key = K;
+
+    fn compare(&self, key: &Self::Key) -> RangeComparison {
+        if *key < self.lower {
+            return RangeComparison::Less;
+        }
+        match key.cmp(&self.upper) {
+            Ordering::Less => RangeComparison::InRange,
+            Ordering::Equal => RangeComparison::UpperBoundary,
+            Ordering::Greater => RangeComparison::Greater,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RangeGt<K: Ord>(K);
+
+impl<K: Ord> RangeGt<K> {
+    pub fn new(key: K) -> RangeGt<K> {
+        RangeGt(key)
+    }
+}
+
+impl<K: Ord> Range for RangeGt<K> {
+    type Key = K;
+
+    fn compare(&self, key: &Self::Key) -> RangeComparison {
+        match key.cmp(&self.0) {
+            Ordering::Less | Ordering::Equal => RangeComparison::Less,
+            Ordering::Greater => RangeComparison::InRange,
+        }
+    }
+}
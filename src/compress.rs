@@ -0,0 +1,138 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::errors::*;
+
+/// How a file was compressed, as told by the magic number it starts with.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn detect(data: &[u8]) -> Option<Compression> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Decompresses the whole of `data`.
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => gunzip(data),
+            Compression::Zstd => bail!(ErrorKind::Unsupported(
+                "zstd-compressed databases".to_owned()
+            )),
+        }
+    }
+}
+
+// Flags in the gzip header saying which optional fields follow it.
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+// Decompresses a gzip file of a single member, as RFC 1952 describes:
+//
+// +---+---+---+---+---+---+---+---+---+---+
+// |ID1|ID2|CM |FLG|     MTIME     |XFL|OS | (more-->)
+// +---+---+---+---+---+---+---+---+---+---+
+// ...optional fields...
+// +=======================+
+// |...compressed blocks...| (more-->)
+// +=======================+
+// +---+---+---+---+---+---+---+---+
+// |     CRC32     |     ISIZE     |
+// +---+---+---+---+---+---+---+---+
+fn gunzip(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 18 || Compression::detect(data) != Some(Compression::Gzip) {
+        bail!("Not a gzip file");
+    }
+    // "A compression method (CM) of 8 denotes the "deflate" compression
+    //  method", and there are no others.
+    if data[2] != 8 {
+        bail!("Unknown gzip compression method {}", data[2]);
+    }
+    let flags = data[3];
+    let mut offset = 10;
+    if flags & FEXTRA != 0 {
+        offset += 2 + LittleEndian::read_u16(&data[offset..]) as usize;
+    }
+    for &flag in &[FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let len = data
+                .get(offset..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or("Unterminated string in gzip header")?;
+            offset += len + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        offset += 2;
+    }
+    let trailer = data.len() - 8;
+    if offset > trailer {
+        bail!("gzip header runs into its trailer");
+    }
+
+    let decompressed = miniz_oxide::inflate::decompress_to_vec(&data[offset..trailer])
+        .map_err(|e| format!("Invalid gzip data: {:?}", e.status))?;
+    let crc = LittleEndian::read_u32(&data[trailer..]);
+    let size = LittleEndian::read_u32(&data[trailer + 4..]);
+    // ISIZE is the size of the input modulo 2^32.
+    if size != decompressed.len() as u32 || crc != crc32(&decompressed) {
+        bail!("gzip data doesn't match its checksum: the file may be truncated or have several members");
+    }
+    Ok(decompressed)
+}
+
+// The CRC-32 that gzip uses (ISO 3309), computed a byte at a time.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *entry = c;
+    }
+    !data.iter().fold(!0u32, |c, &b| {
+        table[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gunzip() {
+        let data = std::fs::read("fixtures/large.db.gz").unwrap();
+        assert_eq!(Compression::detect(&data), Some(Compression::Gzip));
+        let decompressed = Compression::Gzip.decompress(&data).unwrap();
+        assert_eq!(decompressed, std::fs::read("fixtures/large.db").unwrap());
+
+        // A corrupted byte is caught by the checksum, if not by inflating.
+        let mut corrupt = data.clone();
+        let middle = corrupt.len() / 2;
+        corrupt[middle] ^= 0x01;
+        assert!(gunzip(&corrupt).is_err());
+        assert!(gunzip(&data[..data.len() - 1]).is_err());
+
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(Compression::detect(b"SQLite format 3\0"), None);
+        match Compression::Zstd.decompress(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Err(Error(ErrorKind::Unsupported(_), _)) => {}
+            other => panic!("Expected Unsupported, got {:?}", other.map(|_| ())),
+        }
+    }
+}
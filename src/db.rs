@@ -1,39 +1,452 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
 use byteorder::{BigEndian, ByteOrder};
 
 use crate::errors::*;
+use crate::pager::{is_valid_page_size, Pager};
+use crate::query::{Params, Statement};
+use crate::record::{json_string, Field};
+use crate::schema::Schema;
+
+/// An open database file, with its schema.
+pub struct Database {
+    pager: Rc<Pager>,
+    schema: Schema,
+}
+
+impl Database {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Database> {
+        let pager = Rc::new(Pager::open(path)?);
+        let schema = Schema::new(pager.clone())?;
+        Ok(Database { pager, schema })
+    }
+
+    pub fn pager(&self) -> &Rc<Pager> {
+        &self.pager
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The schema cookie, which SQLite increments each time the schema
+    /// changes. A schema read earlier is out of date if this has changed
+    /// since.
+    pub fn schema_version(&self) -> u32 {
+        self.pager.header.schema_cookie
+    }
+
+    /// The name and number of rows of each table, leaving out SQLite's own
+    /// tables. Rows are counted without being decoded, so this is cheap even
+    /// for large tables.
+    pub fn table_summary(&self) -> Result<Vec<(String, usize)>> {
+        let mut summary = vec![];
+        for table in self.schema.tables()? {
+            if table.name().starts_with("sqlite_") {
+                continue;
+            }
+            let len = table
+                .count()
+                .chain_err(|| format!("Failed to count rows of table {}", table.name()))?;
+            summary.push((table.name().to_owned(), len));
+        }
+        Ok(summary)
+    }
+
+    /// Runs a query that's expected to return at most one row, such as a
+    /// lookup by primary key. It's an error for more than one row to match.
+    pub fn query_row(
+        &self,
+        sql: &str,
+        params: Params<'_>,
+    ) -> Result<Option<HashMap<String, Field>>> {
+        let mut rows = Statement::prepare(sql)?
+            .bind(params)?
+            .execute(&self.schema)?;
+        if rows.len() > 1 {
+            bail!("Query returned {} rows, not one: {}", rows.len(), sql);
+        }
+        Ok(rows.pop())
+    }
+
+    /// Writes each row of `table` to `writer` as a line holding a JSON
+    /// object, keyed by column name. Rows are read and written one at a
+    /// time, so this works for any size of table. Returns the number of rows
+    /// written.
+    pub fn export_jsonl<W: Write>(&self, table: &str, writer: W) -> Result<usize> {
+        let table = self.schema.table(table)?;
+        let columns = table.column_names();
+        let keys: Vec<String> = columns.iter().map(|c| json_string(c)).collect();
+
+        let mut writer = BufWriter::new(writer);
+        let mut count = 0;
+        for row in table.select_iter(columns.clone())? {
+            let row = row?;
+            let mut line = String::from("{");
+            for (i, (column, key)) in columns.iter().zip(&keys).enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                line.push_str(key);
+                line.push(':');
+                line.push_str(&row[column].to_json()?);
+            }
+            line.push_str("}\n");
+            writer.write_all(line.as_bytes())?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+}
+
+/// The bytes of a field of the database header, which an `InvalidDbHeader`
+/// error points to when a field is to blame.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeaderField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl HeaderField {
+    /// The `len` bytes at `offset` of `data`, or as many of them as there are.
+    pub fn new(name: &'static str, data: &[u8], offset: usize, len: usize) -> HeaderField {
+        let end = cmp::min(data.len(), offset + len);
+        HeaderField {
+            name,
+            offset,
+            bytes: data[cmp::min(offset, end)..end].to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for HeaderField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}:", self.name, self.offset)?;
+        for byte in &self.bytes {
+            write!(f, " {:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
 
 pub struct DbHeader {
     pub page_size: usize,
     pub reserved_byes_per_page: usize,
     pub num_pages: usize,
+    pub first_freelist_trunk: usize,
+    pub freelist_pages: usize,
+    pub largest_root_page: usize,
+    pub incremental_vacuum: bool,
+    pub schema_cookie: u32,
 }
 
 impl DbHeader {
     pub fn parse(data: &[u8]) -> Result<DbHeader> {
-        use crate::ErrorKind::InvalidDbHeader;
+        use crate::errors::ErrorKind::{InvalidDbHeader, Unsupported};
 
-        const HEADER: &'static str = "SQLite format 3\0";
-        if data.len() < HEADER.len() || &data[..HEADER.len()] != HEADER.as_bytes() {
-            bail!(InvalidDbHeader(format!(
-                "Invalid header string: {:?}",
-                &data[..HEADER.len()]
-            ),));
+        const HEADER: &str = "SQLite format 3\0";
+        let magic = HeaderField::new("header string", data, 0, HEADER.len());
+        if magic.bytes != HEADER.as_bytes() {
+            bail!(InvalidDbHeader(
+                format!("Invalid header string: {:?}", magic.bytes),
+                Some(magic),
+            ));
         }
 
         // "The database page size in bytes. Must be a power of two between 512
         //  and 32768 inclusive, or the value 1 representing a page size of 65536."
         let page_size = match BigEndian::read_u16(&data[16..]) {
             1 => 65536,
-            n if n >= 512 && n <= 32768 && (n & (n - 1)) == 0 => n as usize,
-            n => bail!(InvalidDbHeader(format!("Invalid page size: {}", n))),
+            n if is_valid_page_size(n as usize) => n as usize,
+            n => bail!(InvalidDbHeader(
+                format!("Invalid page size: {}", n),
+                Some(HeaderField::new("page size", data, 16, 2)),
+            )),
         };
 
+        // "File format read version. 1 for legacy; 2 for WAL." Anything newer
+        // means we can't safely read the file.
+        match data[18] {
+            1 | 2 => {}
+            n => bail!(Unsupported(format!("file format read version {}", n))),
+        }
+
+        // "The schema format number. Supported schema formats are 1, 2, 3,
+        //  and 4." It's zero for a database that's never had a schema.
+        match BigEndian::read_u32(&data[44..]) {
+            0..=4 => {}
+            n => bail!(Unsupported(format!("schema format {}", n))),
+        }
+
+        // "A value of 1 means UTF-8. A value of 2 means UTF-16le. A value of 3
+        //  means UTF-16be." Again, it's zero if there's no schema yet.
+        match BigEndian::read_u32(&data[56..]) {
+            0 | 1 => {}
+            n => bail!(Unsupported(format!("text encoding {}", n))),
+        }
+
         Ok(DbHeader {
             page_size,
             // "Bytes of unused "reserved" space at the end of each page. Usually 0."
             reserved_byes_per_page: data[20] as usize,
             // "Size of the database file in pages. The "in-header database size"."
             num_pages: BigEndian::read_u32(&data[28..]) as usize,
+            // "Page number of the first freelist trunk page."
+            first_freelist_trunk: BigEndian::read_u32(&data[32..]) as usize,
+            // "Total number of freelist pages."
+            freelist_pages: BigEndian::read_u32(&data[36..]) as usize,
+            // "The schema cookie is a 4-byte big-endian integer at offset 40
+            //  that is incremented whenever the database schema changes."
+            schema_cookie: BigEndian::read_u32(&data[40..]),
+            // "The page number of the largest root b-tree page when in
+            //  auto-vacuum or incremental-vacuum modes, or zero otherwise."
+            largest_root_page: BigEndian::read_u32(&data[52..]) as usize,
+            // "True (non-zero) for incremental-vacuum mode. False (zero)
+            //  otherwise."
+            incremental_vacuum: BigEndian::read_u32(&data[64..]) != 0,
         })
     }
+
+    /// Checks the invariants the file format places on the rest of the
+    /// header, which `parse()` doesn't need in order to read the database.
+    /// The error describes the first one that doesn't hold.
+    pub fn validate(data: &[u8]) -> Result<()> {
+        let header = DbHeader::parse(data)?;
+        let invalid = |s: String, name, offset, len| -> Result<()> {
+            let field = HeaderField::new(name, data, offset, len);
+            bail!(ErrorKind::InvalidDbHeader(s, Some(field)))
+        };
+
+        // "File format write version. 1 for legacy; 2 for WAL."
+        if data[19] != 1 && data[19] != 2 {
+            invalid(
+                format!("write version at offset 19 is {}", data[19]),
+                "write version",
+                19,
+                1,
+            )?;
+        }
+        // "The usable size is not allowed to be less than 480."
+        let usable_size = header.page_size as isize - header.reserved_byes_per_page as isize;
+        if usable_size < 480 {
+            invalid(
+                format!(
+                    "reserved bytes at offset 20 ({}) leave {} usable bytes per page",
+                    header.reserved_byes_per_page, usable_size
+                ),
+                "reserved bytes per page",
+                20,
+                1,
+            )?;
+        }
+        // "Maximum embedded payload fraction. Must be 64.", "Minimum embedded
+        //  payload fraction. Must be 32." and "Leaf payload fraction. Must be
+        //  32."
+        for &(offset, expected) in &[(21, 64), (22, 32), (23, 32)] {
+            if data[offset] != expected {
+                invalid(
+                    format!(
+                        "payload fraction at offset {} is {}, not {}",
+                        offset, data[offset], expected
+                    ),
+                    "payload fraction",
+                    offset,
+                    1,
+                )?;
+            }
+        }
+        // Incremental vacuum is only possible with auto-vacuum, which is on
+        // when the largest root b-tree page is non-zero.
+        if header.largest_root_page == 0 && header.incremental_vacuum {
+            invalid(
+                "incremental vacuum at offset 64 is set without auto-vacuum".to_owned(),
+                "incremental vacuum",
+                64,
+                4,
+            )?;
+        }
+        // "Reserved for expansion. Must be zero."
+        if let Some(i) = data[72..92].iter().position(|&b| b != 0) {
+            invalid(
+                format!("reserved byte at offset {} is not zero", 72 + i),
+                "reserved for expansion",
+                72 + i,
+                1,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::value::Value;
+
+    fn header() -> Vec<u8> {
+        let mut data = vec![0; 100];
+        data[..16].copy_from_slice(b"SQLite format 3\0");
+        BigEndian::write_u16(&mut data[16..], 4096);
+        data[18] = 1;
+        data[19] = 1;
+        data[21] = 64;
+        data[22] = 32;
+        data[23] = 32;
+        BigEndian::write_u32(&mut data[44..], 4);
+        BigEndian::write_u32(&mut data[56..], 1);
+        data
+    }
+
+    #[test]
+    fn test_unsupported_header() {
+        assert!(DbHeader::parse(&header()).is_ok());
+
+        let mut data = header();
+        data[18] = 3;
+        match DbHeader::parse(&data) {
+            Err(Error(ErrorKind::Unsupported(_), _)) => {}
+            other => panic!("Expected Unsupported, got {:?}", other.map(|_| ())),
+        }
+
+        let mut data = header();
+        BigEndian::write_u32(&mut data[56..], 2);
+        match DbHeader::parse(&data) {
+            Err(Error(ErrorKind::Unsupported(ref feature), _)) => {
+                assert_eq!(feature, "text encoding 2")
+            }
+            other => panic!("Expected Unsupported, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_invalid_header_field() {
+        let field = |data: &[u8]| match DbHeader::parse(data) {
+            Err(Error(ErrorKind::InvalidDbHeader(_, field), _)) => field.unwrap(),
+            other => panic!("Expected InvalidDbHeader, got {:?}", other.map(|_| ())),
+        };
+
+        let mut data = header();
+        BigEndian::write_u16(&mut data[16..], 1000);
+        let page_size = field(&data);
+        assert_eq!(
+            page_size,
+            HeaderField {
+                name: "page size",
+                offset: 16,
+                bytes: vec![0x03, 0xe8],
+            }
+        );
+        assert_eq!(page_size.to_string(), "page size at offset 16: 03 e8");
+
+        let mut data = header();
+        data[..6].copy_from_slice(b"SQLit3");
+        let magic = field(&data);
+        assert_eq!((magic.name, magic.offset), ("header string", 0));
+        assert_eq!(magic.bytes, b"SQLit3 format 3\0");
+        // A file too short to hold the header string doesn't panic.
+        assert_eq!(field(b"SQLite").bytes, b"SQLite");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(DbHeader::validate(&header()).is_ok());
+
+        let invalid = |edit: fn(&mut Vec<u8>)| {
+            let mut data = header();
+            edit(&mut data);
+            match DbHeader::validate(&data) {
+                Err(Error(ErrorKind::InvalidDbHeader(s, _), _)) => s,
+                other => panic!("Expected InvalidDbHeader, got {:?}", other.map(|_| ())),
+            }
+        };
+        assert_eq!(
+            invalid(|data| data[19] = 3),
+            "write version at offset 19 is 3"
+        );
+        assert_eq!(
+            invalid(|data| {
+                BigEndian::write_u16(&mut data[16..], 512);
+                data[20] = 40;
+            }),
+            "reserved bytes at offset 20 (40) leave 472 usable bytes per page"
+        );
+        assert_eq!(
+            invalid(|data| data[22] = 20),
+            "payload fraction at offset 22 is 20, not 32"
+        );
+        assert_eq!(
+            invalid(|data| data[67] = 1),
+            "incremental vacuum at offset 64 is set without auto-vacuum"
+        );
+        assert_eq!(
+            invalid(|data| data[80] = 0xff),
+            "reserved byte at offset 80 is not zero"
+        );
+    }
+
+    #[test]
+    fn test_export_jsonl() {
+        let db = Database::open("fixtures/large.db").unwrap();
+        let mut out = Vec::new();
+        let count = db.export_jsonl("numbers", &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(count, 5000);
+        assert_eq!(
+            lines.len(),
+            db.schema().table("numbers").unwrap().len().unwrap()
+        );
+        assert_eq!(lines[0], r#"{"id":1,"value":7,"label":"row-1"}"#);
+    }
+
+    #[test]
+    fn test_schema_version() {
+        assert_eq!(Pager::create_empty(4096).unwrap().header.schema_cookie, 0);
+        // sales.db was made by a single CREATE TABLE, views.db by four
+        // CREATE statements.
+        let version = |name| Database::open(name).unwrap().schema_version();
+        assert_eq!(version("fixtures/sales.db"), 1);
+        assert_eq!(version("fixtures/views.db"), 4);
+    }
+
+    #[test]
+    fn test_table_summary() {
+        let db = Database::open("fixtures/autoincrement.db").unwrap();
+        let summary = db.table_summary().unwrap();
+        let names: Vec<_> = summary.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["jobs", "runs", "plain"]);
+        for (name, len) in summary {
+            let table = db.schema().table(&name).unwrap();
+            assert_eq!(len, table.len().unwrap());
+            let rows = table.select_iter(table.column_names()).unwrap();
+            assert_eq!(len, rows.count());
+        }
+    }
+
+    #[test]
+    fn test_query_row() {
+        let db = Database::open("fixtures/sales.db").unwrap();
+        let query = |id: i64| {
+            db.query_row(
+                "SELECT region, amount FROM sales WHERE id = ?",
+                Params::Positional(&[Value::Integer(id)]),
+            )
+        };
+        let row = query(3).unwrap().unwrap();
+        assert_eq!(row["region"].as_text().unwrap(), "north");
+        assert_eq!(row["amount"].as_integer().unwrap(), 30);
+        assert!(query(100).unwrap().is_none());
+
+        assert!(db
+            .query_row("SELECT id FROM sales WHERE region = 'south'", Params::None)
+            .is_err());
+    }
 }
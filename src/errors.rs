@@ -1,3 +1,8 @@
+// error_chain! tests a cfg flag of its own, which rustc warns is unknown.
+#![allow(unexpected_cfgs)]
+
+use crate::btree::BTreeKind;
+use crate::db::HeaderField;
 use crate::types::Type;
 
 error_chain! {
@@ -10,11 +15,66 @@ error_chain! {
             description("Table does not exist")
             display("Table does not exist: {}", table)
         }
-        InvalidDbHeader(s: String) {
+        ObjectDoesNotExist(name: String) {
+            description("Object does not exist")
+            display("No table, index, view or trigger is called {}", name)
+        }
+        InvalidDbHeader(s: String, field: Option<HeaderField>) {
             description("Invalid sqlite3 database header")
-            display("Invalid sqlite3 database header: {}", s)
+            display(
+                "Invalid sqlite3 database header: {}{}",
+                s,
+                match *field {
+                    Some(ref field) => format!(" ({})", field),
+                    None => String::new(),
+                }
+            )
         }
         InvalidVarint
+        UnboundParameter(name: String) {
+            description("Unbound parameter")
+            display("Unbound parameter: {}", name)
+        }
+        InvalidPageNumber(page: usize) {
+            description("Invalid page number")
+            display("Invalid page number: {}", page)
+        }
+        UnexpectedPageType(page: usize, flag: u8, expected: BTreeKind) {
+            description("Unexpected b-tree page type")
+            display(
+                "Page {} has type 0x{:02x}, which isn't a page of {} b-tree",
+                page,
+                flag,
+                match *expected {
+                    BTreeKind::Table => "a table",
+                    BTreeKind::Index => "an index",
+                }
+            )
+        }
+        ChecksumMismatch(page: usize) {
+            description("Checksum mismatch")
+            display("Checksum mismatch on page {}", page)
+        }
+        Interrupted {
+            description("Interrupted")
+            display("Interrupted")
+        }
+        ParseError(message: String) {
+            description("Error parsing statement")
+            display("{}", message)
+        }
+        UnsupportedStatement(statement: String) {
+            description("Unsupported statement")
+            display("Unsupported statement: {}", statement)
+        }
+        ReadOnly(statement: String) {
+            description("Database is read-only")
+            display("Database is read-only: {} statements can't be run", statement)
+        }
+        Unsupported(feature: String) {
+            description("Unsupported feature")
+            display("Unsupported: {}", feature)
+        }
     }
 
 
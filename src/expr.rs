@@ -0,0 +1,672 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::aggregate::Aggregate;
+use crate::errors::*;
+use crate::function::Function;
+use crate::query::SelectOp;
+use crate::record::{integer_prefix, numeric_prefix, Field, FieldKey, LiteralValue};
+use crate::schema::Schema;
+use crate::types::Type;
+use crate::value::TextDecoding;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    /// Like `Equal`, except that NULL IS NULL.
+    Is,
+    IsNot,
+}
+
+impl CompareOp {
+    /// The operator to use when the operands are swapped, so that
+    /// `a < b` becomes `b > a`.
+    pub fn reverse(self) -> CompareOp {
+        match self {
+            CompareOp::Less => CompareOp::Greater,
+            CompareOp::LessOrEqual => CompareOp::GreaterOrEqual,
+            CompareOp::Greater => CompareOp::Less,
+            CompareOp::GreaterOrEqual => CompareOp::LessOrEqual,
+            op => op,
+        }
+    }
+
+    fn matches(self, ord: Ordering) -> bool {
+        match self {
+            CompareOp::Equal | CompareOp::Is => ord == Ordering::Equal,
+            CompareOp::NotEqual | CompareOp::IsNot => ord != Ordering::Equal,
+            CompareOp::Less => ord == Ordering::Less,
+            CompareOp::LessOrEqual => ord != Ordering::Greater,
+            CompareOp::Greater => ord == Ordering::Greater,
+            CompareOp::GreaterOrEqual => ord != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl ArithOp {
+    fn apply(self, left: &Field, right: &Field) -> Result<Field> {
+        Ok(match (number(left)?, number(right)?) {
+            (Number::Integer(a), Number::Integer(b)) => {
+                let result = match self {
+                    ArithOp::Add => a.checked_add(b),
+                    ArithOp::Subtract => a.checked_sub(b),
+                    ArithOp::Multiply => a.checked_mul(b),
+                    // Dividing by zero gives NULL.
+                    ArithOp::Divide | ArithOp::Remainder if b == 0 => return Ok(null()),
+                    ArithOp::Divide => a.checked_div(b),
+                    ArithOp::Remainder => a.checked_rem(b),
+                };
+                match result {
+                    // XXX Field doesn't know integers are signed yet.
+                    Some(i) => Field::from(i as u64),
+                    // On overflow, SQLite falls back to reals.
+                    None => self.apply_real(a as f64, b as f64),
+                }
+            }
+            (a, b) => self.apply_real(a.as_real(), b.as_real()),
+        })
+    }
+
+    fn apply_real(self, a: f64, b: f64) -> Field {
+        let result = match self {
+            ArithOp::Add => a + b,
+            ArithOp::Subtract => a - b,
+            ArithOp::Multiply => a * b,
+            ArithOp::Divide if b == 0.0 => return null(),
+            ArithOp::Divide => a / b,
+            // SQLite takes the remainder of reals after casting them to
+            // integers.
+            ArithOp::Remainder => {
+                let (a, b) = (a as i64, b as i64);
+                if b == 0 {
+                    return null();
+                }
+                return Field::from(a.wrapping_rem(b) as u64);
+            }
+        };
+        Field::from(result)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Number {
+    Integer(i64),
+    Real(f64),
+}
+
+impl Number {
+    fn as_real(self) -> f64 {
+        match self {
+            Number::Integer(i) => i as f64,
+            Number::Real(f) => f,
+        }
+    }
+}
+
+// Reads a field as a number for arithmetic. Text and blobs are converted
+// using their longest numeric prefix, as SQLite does.
+fn number(field: &Field) -> Result<Number> {
+    Ok(match field.ty() {
+        // XXX Field doesn't know integers are signed yet.
+        Type::Integer => Number::Integer(field.as_integer()? as i64),
+        Type::Float => Number::Real(field.as_float()?),
+        _ => {
+            let text = field.to_text()?;
+            match text.trim().parse::<i64>() {
+                Ok(i) => Number::Integer(i),
+                Err(_) => Number::Real(numeric_prefix(text.as_bytes())),
+            }
+        }
+    })
+}
+
+/// An expression evaluated against each row of a query.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Column(String),
+    Literal(Field),
+    /// A `?` placeholder. Placeholders are numbered in the order they appear
+    /// in the statement, and must be replaced using `bind()` before the
+    /// expression can be evaluated.
+    Placeholder(usize),
+    /// An aggregate, which is looked up by name in a grouped row.
+    Aggregate(Aggregate),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    /// `||`, which joins its operands as text.
+    Concat(Box<Expr>, Box<Expr>),
+    Arithmetic(ArithOp, Box<Expr>, Box<Expr>),
+    /// `expr LIKE pattern`, with an optional ESCAPE character. `NOT LIKE` is
+    /// parsed as `Not(Like(..))`.
+    Like(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    /// `expr IN (...)`. `NOT IN` is parsed as `Not(In(..))`.
+    In(Box<Expr>, InList),
+    /// `CAST(expr AS type)`, where the type is that of the affinity the type
+    /// name has. NUMERIC isn't supported.
+    Cast(Box<Expr>, Type),
+    /// A call of a scalar function, whose number of arguments has been
+    /// checked by `Function::check_arguments()`.
+    Function(Function, Vec<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+/// What's on the right of IN.
+#[derive(Clone, Debug)]
+pub enum InList {
+    Exprs(Vec<Expr>),
+    /// A SELECT of one column, which `resolve()` replaces with `Values`
+    /// before the expression is evaluated.
+    Select(Box<SelectOp>),
+    /// The keys of the values a SELECT gave, and whether any were NULL.
+    Values(HashSet<FieldKey>, bool),
+}
+
+impl Expr {
+    /// Replaces each placeholder with the value at its position in `values`.
+    pub fn bind(&self, values: &[Field]) -> Result<Expr> {
+        Ok(match *self {
+            Expr::Placeholder(n) => Expr::Literal(
+                values
+                    .get(n)
+                    .cloned()
+                    .ok_or_else(|| ErrorKind::UnboundParameter(format!("?{}", n + 1)))?,
+            ),
+            Expr::Compare(op, ref left, ref right) => Expr::Compare(
+                op,
+                Box::new(left.bind(values)?),
+                Box::new(right.bind(values)?),
+            ),
+            Expr::Concat(ref left, ref right) => {
+                Expr::Concat(Box::new(left.bind(values)?), Box::new(right.bind(values)?))
+            }
+            Expr::Arithmetic(op, ref left, ref right) => Expr::Arithmetic(
+                op,
+                Box::new(left.bind(values)?),
+                Box::new(right.bind(values)?),
+            ),
+            Expr::Like(ref expr, ref pattern, ref escape) => Expr::Like(
+                Box::new(expr.bind(values)?),
+                Box::new(pattern.bind(values)?),
+                match *escape {
+                    Some(ref escape) => Some(Box::new(escape.bind(values)?)),
+                    None => None,
+                },
+            ),
+            Expr::In(ref expr, ref list) => Expr::In(
+                Box::new(expr.bind(values)?),
+                match *list {
+                    InList::Exprs(ref exprs) => InList::Exprs(
+                        exprs
+                            .iter()
+                            .map(|expr| expr.bind(values))
+                            .collect::<Result<_>>()?,
+                    ),
+                    InList::Select(ref select) => InList::Select(Box::new(select.bind(values)?)),
+                    InList::Values(..) => list.clone(),
+                },
+            ),
+            Expr::And(ref left, ref right) => {
+                Expr::And(Box::new(left.bind(values)?), Box::new(right.bind(values)?))
+            }
+            Expr::Or(ref left, ref right) => {
+                Expr::Or(Box::new(left.bind(values)?), Box::new(right.bind(values)?))
+            }
+            Expr::Cast(ref expr, ty) => Expr::Cast(Box::new(expr.bind(values)?), ty),
+            Expr::Function(function, ref args) => Expr::Function(
+                function,
+                args.iter()
+                    .map(|arg| arg.bind(values))
+                    .collect::<Result<_>>()?,
+            ),
+            Expr::Not(ref expr) => Expr::Not(Box::new(expr.bind(values)?)),
+            Expr::Column(_) | Expr::Literal(_) | Expr::Aggregate(_) => self.clone(),
+        })
+    }
+
+    /// Runs the subqueries on the right of any INs, replacing them with the
+    /// values they give. Subqueries can't refer to the outer query's
+    /// columns, so each only needs running once. They read text as
+    /// `decoding` says, like the outer query.
+    pub fn resolve(&self, schema: &Schema, decoding: TextDecoding) -> Result<Expr> {
+        let resolve = |expr: &Expr| expr.resolve(schema, decoding).map(Box::new);
+        Ok(match *self {
+            Expr::In(ref expr, InList::Select(ref select)) => {
+                let column = &select.columns[0].name;
+                let mut keys = HashSet::new();
+                let mut null = false;
+                for row in select.with_text_decoding(decoding).execute(schema)? {
+                    let value = &row[column];
+                    if value.ty() == Type::Null {
+                        null = true;
+                    } else {
+                        keys.insert(value.key());
+                    }
+                }
+                Expr::In(resolve(expr)?, InList::Values(keys, null))
+            }
+            Expr::In(ref expr, InList::Exprs(ref exprs)) => Expr::In(
+                resolve(expr)?,
+                InList::Exprs(
+                    exprs
+                        .iter()
+                        .map(|expr| expr.resolve(schema, decoding))
+                        .collect::<Result<_>>()?,
+                ),
+            ),
+            Expr::Compare(op, ref left, ref right) => {
+                Expr::Compare(op, resolve(left)?, resolve(right)?)
+            }
+            Expr::Concat(ref left, ref right) => Expr::Concat(resolve(left)?, resolve(right)?),
+            Expr::Arithmetic(op, ref left, ref right) => {
+                Expr::Arithmetic(op, resolve(left)?, resolve(right)?)
+            }
+            Expr::Like(ref expr, ref pattern, ref escape) => Expr::Like(
+                resolve(expr)?,
+                resolve(pattern)?,
+                match *escape {
+                    Some(ref escape) => Some(resolve(escape)?),
+                    None => None,
+                },
+            ),
+            Expr::And(ref left, ref right) => Expr::And(resolve(left)?, resolve(right)?),
+            Expr::Or(ref left, ref right) => Expr::Or(resolve(left)?, resolve(right)?),
+            Expr::Cast(ref expr, ty) => Expr::Cast(resolve(expr)?, ty),
+            Expr::Function(function, ref args) => Expr::Function(
+                function,
+                args.iter()
+                    .map(|arg| arg.resolve(schema, decoding))
+                    .collect::<Result<_>>()?,
+            ),
+            Expr::Not(ref expr) => Expr::Not(resolve(expr)?),
+            Expr::In(_, InList::Values(..))
+            | Expr::Column(_)
+            | Expr::Literal(_)
+            | Expr::Placeholder(_)
+            | Expr::Aggregate(_) => self.clone(),
+        })
+    }
+
+    /// The names of the columns this expression reads.
+    pub fn columns(&self) -> Vec<&str> {
+        match *self {
+            Expr::Column(ref name) => vec![name],
+            Expr::Compare(_, ref left, ref right)
+            | Expr::Concat(ref left, ref right)
+            | Expr::Arithmetic(_, ref left, ref right)
+            | Expr::And(ref left, ref right)
+            | Expr::Or(ref left, ref right) => {
+                let mut columns = left.columns();
+                columns.extend(right.columns());
+                columns
+            }
+            Expr::Like(ref expr, ref pattern, ref escape) => {
+                let mut columns = expr.columns();
+                columns.extend(pattern.columns());
+                if let Some(ref escape) = *escape {
+                    columns.extend(escape.columns());
+                }
+                columns
+            }
+            Expr::In(ref expr, ref list) => {
+                let mut columns = expr.columns();
+                if let InList::Exprs(ref exprs) = *list {
+                    columns.extend(exprs.iter().flat_map(|expr| expr.columns()));
+                }
+                columns
+            }
+            Expr::Not(ref expr) | Expr::Cast(ref expr, _) => expr.columns(),
+            Expr::Function(_, ref args) => args.iter().flat_map(|arg| arg.columns()).collect(),
+            Expr::Literal(_) | Expr::Placeholder(_) | Expr::Aggregate(_) => vec![],
+        }
+    }
+
+    /// The aggregates this expression reads.
+    pub fn aggregates(&self) -> Vec<&Aggregate> {
+        match *self {
+            Expr::Aggregate(ref aggregate) => vec![aggregate],
+            Expr::Compare(_, ref left, ref right)
+            | Expr::Concat(ref left, ref right)
+            | Expr::Arithmetic(_, ref left, ref right)
+            | Expr::And(ref left, ref right)
+            | Expr::Or(ref left, ref right) => {
+                let mut aggregates = left.aggregates();
+                aggregates.extend(right.aggregates());
+                aggregates
+            }
+            Expr::Like(ref expr, ref pattern, ref escape) => {
+                let mut aggregates = expr.aggregates();
+                aggregates.extend(pattern.aggregates());
+                if let Some(ref escape) = *escape {
+                    aggregates.extend(escape.aggregates());
+                }
+                aggregates
+            }
+            Expr::In(ref expr, ref list) => {
+                let mut aggregates = expr.aggregates();
+                if let InList::Exprs(ref exprs) = *list {
+                    aggregates.extend(exprs.iter().flat_map(|expr| expr.aggregates()));
+                }
+                aggregates
+            }
+            Expr::Not(ref expr) | Expr::Cast(ref expr, _) => expr.aggregates(),
+            Expr::Function(_, ref args) => args.iter().flat_map(|arg| arg.aggregates()).collect(),
+            Expr::Column(_) | Expr::Literal(_) | Expr::Placeholder(_) => vec![],
+        }
+    }
+
+    /// The terms of this expression when it's read as `a AND b AND ...`.
+    pub fn conjuncts(&self) -> Vec<&Expr> {
+        match *self {
+            Expr::And(ref left, ref right) => {
+                let mut conjuncts = left.conjuncts();
+                conjuncts.extend(right.conjuncts());
+                conjuncts
+            }
+            _ => vec![self],
+        }
+    }
+
+    pub fn eval(&self, row: &HashMap<String, Field>) -> Result<Field> {
+        Ok(match *self {
+            Expr::Column(ref name) => row
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Unknown column: {}", name))?,
+            Expr::Literal(ref field) => field.clone(),
+            Expr::Aggregate(ref aggregate) => row
+                .get(&aggregate.name())
+                .cloned()
+                .ok_or_else(|| format!("Misuse of aggregate: {}", aggregate.name()))?,
+            Expr::Placeholder(n) => bail!(ErrorKind::UnboundParameter(format!("?{}", n + 1))),
+            Expr::Compare(op, ref left, ref right) => {
+                let (left, right) = (left.eval(row)?, right.eval(row)?);
+                match (left.ty(), right.ty()) {
+                    (Type::Null, _) | (_, Type::Null) => match op {
+                        CompareOp::Is => from_bool(left.ty() == right.ty()),
+                        CompareOp::IsNot => from_bool(left.ty() != right.ty()),
+                        _ => null(),
+                    },
+                    _ => from_bool(op.matches(compare(&left, &right))),
+                }
+            }
+            Expr::Concat(ref left, ref right) => {
+                let (left, right) = (left.eval(row)?, right.eval(row)?);
+                if left.ty() == Type::Null || right.ty() == Type::Null {
+                    null()
+                } else {
+                    Field::from(left.to_text()? + &right.to_text()?)
+                }
+            }
+            Expr::Arithmetic(op, ref left, ref right) => {
+                let (left, right) = (left.eval(row)?, right.eval(row)?);
+                if left.ty() == Type::Null || right.ty() == Type::Null {
+                    null()
+                } else {
+                    op.apply(&left, &right)?
+                }
+            }
+            Expr::Like(ref expr, ref pattern, ref escape) => {
+                let (expr, pattern) = (expr.eval(row)?, pattern.eval(row)?);
+                let escape = match *escape {
+                    Some(ref escape) => Some(escape.eval(row)?),
+                    None => None,
+                };
+                if expr.ty() == Type::Null
+                    || pattern.ty() == Type::Null
+                    || escape.as_ref().is_some_and(|e| e.ty() == Type::Null)
+                {
+                    null()
+                } else {
+                    let escape = match escape {
+                        Some(escape) => {
+                            let escape: Vec<char> = escape.to_text()?.chars().collect();
+                            if escape.len() != 1 {
+                                bail!("ESCAPE expression must be a single character");
+                            }
+                            Some(escape[0])
+                        }
+                        None => None,
+                    };
+                    let pattern: Vec<char> = pattern.to_text()?.chars().collect();
+                    let text: Vec<char> = expr.to_text()?.chars().collect();
+                    from_bool(like(&pattern, &text, escape))
+                }
+            }
+            Expr::In(ref expr, ref list) => {
+                let value = expr.eval(row)?;
+                // As for `=`, except that it's only NULL if nothing matches
+                // and something was NULL.
+                let (empty, found, has_null) = match *list {
+                    InList::Exprs(ref exprs) => {
+                        let (mut found, mut has_null) = (false, false);
+                        for expr in exprs {
+                            let item = expr.eval(row)?;
+                            if item.ty() == Type::Null {
+                                has_null = true;
+                            } else if value.ty() != Type::Null {
+                                found |= compare(&value, &item) == Ordering::Equal;
+                            }
+                        }
+                        (exprs.is_empty(), found, has_null)
+                    }
+                    InList::Values(ref keys, has_null) => (
+                        keys.is_empty() && !has_null,
+                        keys.contains(&value.key()),
+                        has_null,
+                    ),
+                    InList::Select(_) => bail!("Subquery in IN has not been run"),
+                };
+                if empty {
+                    from_bool(false)
+                } else if value.ty() == Type::Null {
+                    null()
+                } else if found {
+                    from_bool(true)
+                } else if has_null {
+                    null()
+                } else {
+                    from_bool(false)
+                }
+            }
+            Expr::Cast(ref expr, ty) => cast(expr.eval(row)?, ty)?,
+            // These only evaluate their arguments until one isn't NULL.
+            Expr::Function(Function::Coalesce, ref args)
+            | Expr::Function(Function::IfNull, ref args) => {
+                let mut value = null();
+                for arg in args {
+                    value = arg.eval(row)?;
+                    if value.ty() != Type::Null {
+                        break;
+                    }
+                }
+                value
+            }
+            Expr::Function(function, ref args) => {
+                let args: Vec<Field> = args
+                    .iter()
+                    .map(|arg| arg.eval(row))
+                    .collect::<Result<_>>()?;
+                function.call(&args)?
+            }
+            Expr::And(ref left, ref right) => {
+                match (truth(&left.eval(row)?)?, truth(&right.eval(row)?)?) {
+                    (Some(false), _) | (_, Some(false)) => from_bool(false),
+                    (Some(true), Some(true)) => from_bool(true),
+                    _ => null(),
+                }
+            }
+            Expr::Or(ref left, ref right) => {
+                match (truth(&left.eval(row)?)?, truth(&right.eval(row)?)?) {
+                    (Some(true), _) | (_, Some(true)) => from_bool(true),
+                    (Some(false), Some(false)) => from_bool(false),
+                    _ => null(),
+                }
+            }
+            Expr::Not(ref expr) => match truth(&expr.eval(row)?)? {
+                Some(b) => from_bool(!b),
+                None => null(),
+            },
+        })
+    }
+
+    /// Whether a row should be kept by a WHERE clause. NULL (unknown) is
+    /// treated as false.
+    pub fn matches(&self, row: &HashMap<String, Field>) -> Result<bool> {
+        Ok(truth(&self.eval(row)?)? == Some(true))
+    }
+}
+
+// Whether `text` matches a LIKE `pattern`, in which `%` matches any run of
+// characters and `_` any one character. As in SQLite, ASCII letters match
+// regardless of case. The escape character makes the character after it
+// match only itself.
+fn like(pattern: &[char], text: &[char], escape: Option<char>) -> bool {
+    let literal = |c: char, rest: &[char]| match text.split_first() {
+        Some((t, text)) => t.eq_ignore_ascii_case(&c) && like(rest, text, escape),
+        None => false,
+    };
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((&c, rest)) if Some(c) == escape => match rest.split_first() {
+            Some((&c, rest)) => literal(c, rest),
+            // A pattern ending in the escape character matches nothing.
+            None => false,
+        },
+        Some(('%', rest)) => (0..=text.len()).any(|i| like(rest, &text[i..], escape)),
+        Some(('_', rest)) => !text.is_empty() && like(rest, &text[1..], escape),
+        Some((&c, rest)) => literal(c, rest),
+    }
+}
+
+// Converts a field to another storage class, as CAST does. Text and blobs
+// become numbers using their longest numeric prefix, and reals become
+// integers by truncating them. NULL stays NULL.
+fn cast(field: Field, ty: Type) -> Result<Field> {
+    Ok(match (field.ty(), ty) {
+        (Type::Null, _) => field,
+        (from, to) if from == to => field,
+        // `as` truncates towards zero, and saturates reals too large for an
+        // integer, as SQLite does.
+        // XXX Field doesn't know integers are signed yet.
+        (Type::Float, Type::Integer) => Field::from(field.as_float()? as i64 as u64),
+        (Type::Integer, Type::Float) => Field::from(field.as_integer()? as i64 as f64),
+        (_, Type::Integer) => Field::from(integer_prefix(&bytes(&field)?) as u64),
+        (_, Type::Float) => Field::from(numeric_prefix(&bytes(&field)?)),
+        (_, Type::Text) => Field::from(field.to_text()?),
+        (_, Type::Blob) => Field::Literal(LiteralValue::Blob(bytes(&field)?)),
+        (_, Type::Null) => null(),
+    })
+}
+
+// The bytes of a field, as a blob would hold them. Numbers are written as
+// text first.
+fn bytes(field: &Field) -> Result<Vec<u8>> {
+    Ok(match field.ty() {
+        Type::Text => field.as_text_bytes()?.to_vec(),
+        Type::Blob => field.as_blob()?.to_vec(),
+        _ => field.to_text()?.into_bytes(),
+    })
+}
+
+fn null() -> Field {
+    Field::Literal(LiteralValue::Null)
+}
+
+fn from_bool(b: bool) -> Field {
+    Field::from(b as u64)
+}
+
+fn truth(field: &Field) -> Result<Option<bool>> {
+    match field.ty() {
+        Type::Null => Ok(None),
+        _ => field.as_bool().map(Some),
+    }
+}
+
+/// How text is compared, as named by COLLATE. The other storage classes
+/// compare the same way under every collation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Collation {
+    /// Byte by byte.
+    Binary,
+    /// Folding ASCII letters to lower case first.
+    NoCase,
+    /// Ignoring trailing spaces.
+    RTrim,
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Result<Collation> {
+        match name.to_ascii_uppercase().as_str() {
+            "BINARY" => Ok(Collation::Binary),
+            "NOCASE" => Ok(Collation::NoCase),
+            "RTRIM" => Ok(Collation::RTrim),
+            _ => bail!("No such collation sequence: {}", name),
+        }
+    }
+
+    pub fn compare(self, left: &Field, right: &Field) -> Ordering {
+        let (l, r) = match (left.as_text_bytes(), right.as_text_bytes()) {
+            (Ok(l), Ok(r)) => (l, r),
+            _ => return compare(left, right),
+        };
+        match self {
+            Collation::Binary => l.cmp(r),
+            Collation::NoCase => {
+                let fold = |bytes: &[u8]| bytes.to_ascii_lowercase();
+                fold(l).cmp(&fold(r))
+            }
+            Collation::RTrim => {
+                fn trim(bytes: &[u8]) -> &[u8] {
+                    let len = bytes.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+                    &bytes[..len]
+                }
+                trim(l).cmp(trim(r))
+            }
+        }
+    }
+}
+
+// SQLite orders values of different storage classes as:
+//   NULL < INTEGER/REAL < TEXT < BLOB
+// with integers and reals compared numerically.
+pub fn compare(left: &Field, right: &Field) -> Ordering {
+    fn rank(ty: Type) -> u8 {
+        match ty {
+            Type::Null => 0,
+            Type::Integer | Type::Float => 1,
+            Type::Text => 2,
+            Type::Blob => 3,
+        }
+    }
+
+    match (left.ty(), right.ty()) {
+        (Type::Integer, Type::Float) | (Type::Float, Type::Integer) => {
+            let as_f64 = |f: &Field| {
+                f.as_float()
+                    .or_else(|_| f.as_signed_integer().map(|i| i as f64))
+            };
+            as_f64(left)
+                .unwrap()
+                .partial_cmp(&as_f64(right).unwrap())
+                .unwrap_or(Ordering::Equal)
+        }
+        (l, r) if l == r => left.partial_cmp(right).unwrap_or(Ordering::Equal),
+        (l, r) => rank(l).cmp(&rank(r)),
+    }
+}
@@ -0,0 +1,96 @@
+use crate::errors::*;
+use crate::record::{Field, LiteralValue};
+use crate::types::Type;
+
+/// A scalar function, which gives a value for each row from the values of
+/// its arguments.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Function {
+    /// `typeof(x)`: the name of the storage class of `x`.
+    TypeOf,
+    /// `length(x)`: the number of characters in text, or of bytes in a
+    /// blob. Numbers are measured as text.
+    Length,
+    /// `lower(x)` and `upper(x)`: text with its ASCII letters changed to
+    /// lower or upper case. Other letters are left alone.
+    Lower,
+    Upper,
+    /// `coalesce(x, y, ...)`: the first of its arguments that isn't NULL,
+    /// or NULL if they all are.
+    Coalesce,
+    /// `ifnull(x, y)`: `coalesce()` of two arguments.
+    IfNull,
+}
+
+impl Function {
+    /// The scalar function called `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Function> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "typeof" => Function::TypeOf,
+            "length" => Function::Length,
+            "lower" => Function::Lower,
+            "upper" => Function::Upper,
+            "coalesce" => Function::Coalesce,
+            "ifnull" => Function::IfNull,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::TypeOf => "typeof",
+            Function::Length => "length",
+            Function::Lower => "lower",
+            Function::Upper => "upper",
+            Function::Coalesce => "coalesce",
+            Function::IfNull => "ifnull",
+        }
+    }
+
+    /// Checks that the function takes `n` arguments.
+    pub fn check_arguments(self, n: usize) -> Result<()> {
+        let ok = match self {
+            Function::TypeOf | Function::Length | Function::Lower | Function::Upper => n == 1,
+            Function::Coalesce => n >= 2,
+            Function::IfNull => n == 2,
+        };
+        if !ok {
+            bail!("wrong number of arguments to function {}()", self.name());
+        }
+        Ok(())
+    }
+
+    pub fn call(self, args: &[Field]) -> Result<Field> {
+        let null = Field::Literal(LiteralValue::Null);
+        let is_null = |arg: &Field| arg.ty() == Type::Null;
+        match self {
+            Function::Length | Function::Lower | Function::Upper if args.iter().any(is_null) => {
+                return Ok(null);
+            }
+            _ => {}
+        }
+        Ok(match self {
+            Function::TypeOf => Field::from(match args[0].ty() {
+                Type::Null => "null",
+                Type::Integer => "integer",
+                Type::Float => "real",
+                Type::Text => "text",
+                Type::Blob => "blob",
+            }),
+            Function::Length => {
+                let len = match args[0].ty() {
+                    Type::Blob => args[0].as_blob()?.len(),
+                    _ => args[0].to_text()?.chars().count(),
+                };
+                Field::from(len as u64)
+            }
+            Function::Lower => Field::from(args[0].to_text()?.to_ascii_lowercase()),
+            Function::Upper => Field::from(args[0].to_text()?.to_ascii_uppercase()),
+            Function::Coalesce | Function::IfNull => args
+                .iter()
+                .find(|arg| !is_null(arg))
+                .cloned()
+                .unwrap_or(null),
+        })
+    }
+}
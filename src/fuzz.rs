@@ -0,0 +1,98 @@
+// Feeds arbitrary bytes to the cell and record parsers, which must return
+// `Err` rather than panic: the files we read are untrusted.
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+
+use bytes::Bytes;
+
+use crate::btree::Cell;
+use crate::index::IndexLeafCell;
+use crate::record::Record;
+use crate::table::TableLeafCell;
+
+const CORPUS_DIR: &str = "fixtures/corpus";
+const ITERATIONS: usize = 20_000;
+
+// xorshift64*, so that failures are reproducible without pulling in a
+// dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.below(max_len + 1);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+fn corpus() -> Vec<Vec<u8>> {
+    let mut entries: Vec<_> = fs::read_dir(CORPUS_DIR)
+        .expect("Missing seed corpus")
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    entries
+        .into_iter()
+        .map(|path| fs::read(path).unwrap())
+        .collect()
+}
+
+// A parser under test, by name.
+type Parser = (&'static str, fn(Bytes));
+
+fn check(input: &[u8]) {
+    let parsers: [Parser; 3] = [
+        ("Record", |b| drop(Record::from_bytes(b))),
+        ("TableLeafCell", |b| drop(TableLeafCell::from_bytes(b))),
+        ("IndexLeafCell", |b| drop(IndexLeafCell::from_bytes(b))),
+    ];
+    for &(name, parse) in parsers.iter() {
+        let bytes = Bytes::from(input);
+        if panic::catch_unwind(AssertUnwindSafe(|| parse(bytes))).is_err() {
+            panic!("{}::from_bytes panicked on input: {:?}", name, input);
+        }
+    }
+}
+
+#[test]
+fn test_corpus_does_not_panic() {
+    for input in corpus() {
+        check(&input);
+    }
+}
+
+#[test]
+fn test_random_input_does_not_panic() {
+    let mut rng = Rng(0x5EED_1234_ABCD_0001);
+    for _ in 0..ITERATIONS {
+        check(&rng.bytes(64));
+    }
+}
+
+#[test]
+fn test_mutated_corpus_does_not_panic() {
+    let mut rng = Rng(0x5EED_1234_ABCD_0002);
+    let corpus = corpus();
+    for _ in 0..ITERATIONS {
+        let mut input = corpus[rng.below(corpus.len())].clone();
+        if input.is_empty() {
+            continue;
+        }
+        for _ in 0..=rng.below(4) {
+            let i = rng.below(input.len());
+            input[i] = rng.next() as u8;
+        }
+        check(&input);
+    }
+}
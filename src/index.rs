@@ -1,4 +1,4 @@
-use std::cmp::{Ordering, PartialOrd};
+use std::cmp::Ordering;
 use std::fmt;
 use std::io::Cursor;
 use std::rc::Rc;
@@ -7,26 +7,44 @@ use std::result;
 use byteorder::{BigEndian, ByteOrder};
 use bytes::Bytes;
 
-use crate::btree::{BTree, Cell, InteriorCell, Range, RangeComparison};
+use crate::btree::{
+    index_max_local, overflow_page, BTree, BTreeKind, Cell, InteriorCell, Range, RangeComparison,
+};
 use crate::errors::*;
+use crate::expr::compare;
 use crate::pager::Pager;
-use crate::record::Record;
+use crate::record::{Field, Record};
+use crate::types::Type;
 use crate::util::read_varint;
+use crate::value::Value;
+
+// Slices out a cell's payload, checking the length read from the cell
+// actually fits in the bytes we have.
+fn slice_payload(bytes: Bytes, position: usize, len: usize) -> Result<Bytes> {
+    match position.checked_add(len) {
+        Some(end) if end <= bytes.len() => Ok(bytes.slice(position, end)),
+        _ => bail!(
+            "Index cell payload of {} bytes extends past end of page",
+            len
+        ),
+    }
+}
 
 #[derive(Debug)]
-struct IndexLeafCell {
+pub struct IndexLeafCell {
     record: Record,
 }
 
 impl Cell for IndexLeafCell {
     type Key = Record;
+    const KIND: BTreeKind = BTreeKind::Index;
 
     fn from_bytes(bytes: Bytes) -> Result<Self> {
         let mut cursor = Cursor::new(bytes);
         // XXX See questions about len in IndexInteriorCell.
         let len = read_varint(&mut cursor)? as usize;
         let position = cursor.position() as usize;
-        let bytes = cursor.into_inner().slice(position, position + len);
+        let bytes = slice_payload(cursor.into_inner(), position, len)?;
         let record = Record::from_bytes(bytes)?;
 
         Ok(IndexLeafCell { record })
@@ -35,19 +53,39 @@ impl Cell for IndexLeafCell {
     fn key(&self) -> &Self::Key {
         &self.record
     }
+
+    fn overflow_page(bytes: &Bytes, usable_size: usize) -> Result<Option<usize>> {
+        let mut cursor = Cursor::new(bytes);
+        let len = read_varint(&mut cursor)? as usize;
+        let position = cursor.position() as usize;
+        overflow_page(
+            bytes,
+            position,
+            len,
+            index_max_local(usable_size),
+            usable_size,
+        )
+    }
 }
 
 #[derive(Debug)]
 struct IndexInteriorCell {
     left: usize,
     record: Record,
+    // Everything after the left-pointer, which is an IndexLeafCell.
+    entry: Bytes,
 }
 
 impl Cell for IndexInteriorCell {
     type Key = Record;
+    const KIND: BTreeKind = BTreeKind::Index;
 
     fn from_bytes(bytes: Bytes) -> Result<Self> {
+        if bytes.len() < 4 {
+            bail!("Index interior cell too short: {} bytes", bytes.len());
+        }
         let left = BigEndian::read_u32(&bytes) as usize;
+        let entry = bytes.slice_from(4);
         let mut cursor = Cursor::new(bytes);
         cursor.set_position(4);
         // XXX Why does sqlite3 bother with len? The length in the record header
@@ -55,23 +93,46 @@ impl Cell for IndexInteriorCell {
         //     something to do with the overflow which we're ignoring.
         let len = read_varint(&mut cursor)? as usize;
         let position = cursor.position() as usize;
-        let bytes = cursor.into_inner().slice(position, position + len);
+        let bytes = slice_payload(cursor.into_inner(), position, len)?;
         let record = Record::from_bytes(bytes)?;
 
-        Ok(IndexInteriorCell { left, record })
+        Ok(IndexInteriorCell {
+            left,
+            record,
+            entry,
+        })
     }
 
     fn key(&self) -> &Self::Key {
         &self.record
     }
+
+    fn overflow_page(bytes: &Bytes, usable_size: usize) -> Result<Option<usize>> {
+        let mut cursor = Cursor::new(bytes);
+        cursor.set_position(4);
+        let len = read_varint(&mut cursor)? as usize;
+        let position = cursor.position() as usize;
+        overflow_page(
+            bytes,
+            position,
+            len,
+            index_max_local(usable_size),
+            usable_size,
+        )
+    }
 }
 
 impl InteriorCell for IndexInteriorCell {
     fn left(&self) -> usize {
         self.left
     }
+
+    fn entry(&self) -> Option<Bytes> {
+        Some(self.entry.clone())
+    }
 }
 
+#[derive(Clone, Debug)]
 struct IndexRange(Record);
 
 impl IndexRange {
@@ -84,33 +145,156 @@ impl Range for IndexRange {
     type Key = Record;
 
     fn compare(&self, other: &Self::Key) -> RangeComparison {
-        if self.0.len() > other.len() {
-            panic!(
-                "Attempted to compare records with mis-matched sizes: {:?} {:?}",
-                self.0, other
-            );
-        }
         for (this, that) in self.0.iter().zip(other.iter()) {
-            let ord = that.partial_cmp(this).unwrap();
-            match ord {
+            // NULLs sort before everything else, and together.
+            match compare(that, this) {
                 // If Equal, move onto comparing next field.
                 Ordering::Equal => {}
                 Ordering::Less => return RangeComparison::Less,
                 Ordering::Greater => return RangeComparison::Greater,
             }
         }
+        // An entry with fewer fields than the probe can't match all of it.
+        // It sorts before the entries that go on to have the probe's other
+        // values, as a prefix does.
+        if other.len() < self.0.len() {
+            return RangeComparison::Less;
+        }
         // If we got this far, it must be equal.
         RangeComparison::InRange
     }
 }
 
+/// One end of a range of values of an index column.
+#[derive(Clone, Debug)]
+pub struct Bound {
+    pub value: Field,
+    pub inclusive: bool,
+}
+
+// The entries whose leading fields equal `prefix`, and whose next field lies
+// between two (optional) bounds. Entries with a NULL in any of these fields
+// never match, as comparisons with NULL are never true.
+#[derive(Clone, Debug)]
+struct IndexBounds {
+    prefix: Vec<Field>,
+    lower: Option<Bound>,
+    upper: Option<Bound>,
+}
+
+impl Range for IndexBounds {
+    type Key = Record;
+
+    fn compare(&self, other: &Self::Key) -> RangeComparison {
+        for (i, value) in self.prefix.iter().enumerate() {
+            if other.len() <= i || other[i].ty() == Type::Null {
+                return RangeComparison::Less;
+            }
+            match compare(&other[i], value) {
+                Ordering::Less => return RangeComparison::Less,
+                Ordering::Greater => return RangeComparison::Greater,
+                Ordering::Equal => {}
+            }
+        }
+        if self.lower.is_none() && self.upper.is_none() {
+            return RangeComparison::InRange;
+        }
+
+        let n = self.prefix.len();
+        if other.len() <= n || other[n].ty() == Type::Null {
+            return RangeComparison::Less;
+        }
+        if let Some(ref lower) = self.lower {
+            match compare(&other[n], &lower.value) {
+                Ordering::Less => return RangeComparison::Less,
+                Ordering::Equal if !lower.inclusive => return RangeComparison::Less,
+                _ => {}
+            }
+        }
+        if let Some(ref upper) = self.upper {
+            match compare(&other[n], &upper.value) {
+                Ordering::Greater => return RangeComparison::Greater,
+                Ordering::Equal if !upper.inclusive => return RangeComparison::Greater,
+                _ => {}
+            }
+        }
+        RangeComparison::InRange
+    }
+}
+
+// Picks the indexed columns out of a CREATE INDEX statement, which nom_sql
+// can't parse. Each column may be followed by COLLATE or ASC/DESC, which we
+// ignore.
+pub fn index_columns(sql: &str) -> Vec<String> {
+    let (start, end) = match (sql.find('('), sql.rfind(')')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return vec![],
+    };
+    sql[start + 1..end]
+        .split(',')
+        .filter_map(|column| column.split_whitespace().next())
+        .map(|name| {
+            name.trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']')
+                .to_owned()
+        })
+        .collect()
+}
+
 type IndexBTree = BTree<Record, IndexInteriorCell, IndexLeafCell>;
 
+/// What an index's entries hold, which depends on the table it's for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IndexKind {
+    /// A secondary index on an ordinary table. Each entry is the indexed
+    /// columns followed by the rowid of the row.
+    Rowid,
+    /// A secondary index on a WITHOUT ROWID table. Each entry is the indexed
+    /// columns followed by the table's primary key.
+    WithoutRowid,
+    /// The b-tree of a WITHOUT ROWID table itself. Each entry is a whole
+    /// row, keyed by its primary key. There's no rowid.
+    PrimaryKey,
+}
+
+/// The estimates for an index that ANALYZE stores in `sqlite_stat1`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexStats {
+    /// The number of entries in the index.
+    pub rows: u64,
+    /// The average number of entries sharing a value of the index's first
+    /// column, then of its first two columns, and so on.
+    pub rows_per_value: Vec<u64>,
+}
+
+impl IndexStats {
+    /// Parses the `stat` column of `sqlite_stat1`, such as `"1000 10"`.
+    /// Any keywords SQLite adds after the numbers (like `unordered`) are
+    /// ignored.
+    pub fn parse(stat: &str) -> Option<IndexStats> {
+        let mut numbers = stat
+            .split_whitespace()
+            .map_while(|word| word.parse::<u64>().ok());
+        Some(IndexStats {
+            rows: numbers.next()?,
+            rows_per_value: numbers.collect(),
+        })
+    }
+}
+
+#[derive(Clone)]
 pub struct Index {
     pager: Rc<Pager>,
     page_num: usize,
     tbl_name: String,
     name: String,
+    kind: IndexKind,
+    columns: Vec<String>,
+    // The declared type of each of `columns`, which is applied to probes so
+    // that they compare with entries as SQLite stored them.
+    affinities: Vec<Type>,
+    // The CREATE INDEX statement, which SQLite doesn't record for the indices
+    // it creates itself.
+    sql: Option<String>,
 }
 
 impl Index {
@@ -119,6 +303,9 @@ impl Index {
         page_num: usize,
         tbl_name: S,
         name: S,
+        kind: IndexKind,
+        columns: Vec<String>,
+        sql: Option<String>,
     ) -> Result<Index> {
         let tbl_name = tbl_name.into();
         let name = name.into();
@@ -127,20 +314,197 @@ impl Index {
             page_num,
             tbl_name,
             name,
+            kind,
+            columns,
+            affinities: Vec::new(),
+            sql,
         })
     }
 
+    /// The index, with the declared types of its columns (as given by
+    /// `Table::affinities()`), so that `scan()` can convert its probes as
+    /// the values in the index were converted when they were stored.
+    pub fn with_affinities(mut self, affinities: Vec<Type>) -> Index {
+        self.affinities = affinities;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tbl_name(&self) -> &str {
+        &self.tbl_name
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        self.kind
+    }
+
+    /// The CREATE INDEX statement, if the index was made by one rather than
+    /// by SQLite for a UNIQUE or PRIMARY KEY constraint.
+    pub fn sql(&self) -> Option<&str> {
+        self.sql.as_deref()
+    }
+
+    /// The indexed columns, in key order. This is empty for the indices
+    /// SQLite creates for UNIQUE constraints, as it doesn't record their SQL.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Whether the index is UNIQUE. The indices SQLite creates itself are
+    /// all for UNIQUE or PRIMARY KEY constraints.
+    pub fn unique(&self) -> bool {
+        match self.sql {
+            Some(ref sql) => sql
+                .split_whitespace()
+                .nth(1)
+                .is_some_and(|word| word.eq_ignore_ascii_case("UNIQUE")),
+            None => true,
+        }
+    }
+
+    /// Whether the index is partial: whether it only has entries for the rows
+    /// matching its WHERE clause.
+    pub fn partial(&self) -> bool {
+        match self.sql {
+            Some(ref sql) => sql.rfind(')').is_some_and(|end| {
+                sql[end..]
+                    .split_whitespace()
+                    .any(|word| word.eq_ignore_ascii_case("WHERE"))
+            }),
+            None => false,
+        }
+    }
+
+    /// Splits an index entry into its key and the rowid of the row it refers
+    /// to. Only entries of `IndexKind::Rowid` indexes have a rowid: for the
+    /// other kinds the whole entry is returned as the key.
+    pub fn split_rowid(&self, record: Record) -> Result<(Record, Option<i64>)> {
+        if self.kind != IndexKind::Rowid {
+            return Ok((record, None));
+        }
+        let mut fields = record.into_fields();
+        let rowid = match fields.pop() {
+            // XXX Field doesn't know integers are signed yet.
+            Some(field) => field.as_integer()? as i64,
+            None => bail!("Empty entry in index {}", self.name),
+        };
+        Ok((Record::new(fields), Some(rowid)))
+    }
+
+    /// The rowids of the rows whose entries match `record`.
+    pub fn scan_rowids(&self, record: Record) -> Result<Vec<i64>> {
+        if self.kind != IndexKind::Rowid {
+            bail!("Index {} is on a WITHOUT ROWID table", self.name);
+        }
+        self.scan(record)?
+            .into_iter()
+            .map(|entry| Ok(self.split_rowid(entry)?.1.unwrap()))
+            .collect()
+    }
+
+    /// Every page the index uses. See `BTree::pages()`.
+    pub fn pages(&self) -> Result<Vec<usize>> {
+        IndexBTree::new(self.pager.clone(), self.page_num)?.pages()
+    }
+
+    /// The number of levels in the index's b-tree.
+    pub fn depth(&self) -> Result<usize> {
+        IndexBTree::new(self.pager.clone(), self.page_num)?.depth()
+    }
+
     pub fn dump(&self) -> Result<Vec<Record>> {
+        self.dump_iter()?.collect()
+    }
+
+    /// Like `dump()`, but reads entries as the iterator is advanced, so that
+    /// taking the first few only reads the pages they're on.
+    pub fn dump_iter(&self) -> Result<impl Iterator<Item = Result<Record>>> {
+        let btree = IndexBTree::new(self.pager.clone(), self.page_num)?;
+        Ok(btree.iter().map(|cell| cell.map(|c| c.record)))
+    }
+
+    /// Like `dump_iter()`, but gives the entries in descending order, so that
+    /// taking the first few gives the largest values of the index's columns.
+    pub fn dump_rev(&self) -> Result<impl Iterator<Item = Result<Record>>> {
         let btree = IndexBTree::new(self.pager.clone(), self.page_num)?;
-        Ok(btree.iter().map(|cell| cell.record).collect())
+        Ok(btree.iter_rev().map(|cell| cell.map(|c| c.record)))
     }
 
     pub fn scan(&self, record: Record) -> Result<Vec<Record>> {
+        self.scan_iter(record)?.collect()
+    }
+
+    /// Like `scan()`, but reads entries as the iterator is advanced.
+    pub fn scan_iter(&self, record: Record) -> Result<impl Iterator<Item = Result<Record>>> {
         let btree = IndexBTree::new(self.pager.clone(), self.page_num)?;
         Ok(btree
-            .iter_range(IndexRange::new(record))
-            .map(|cell| cell.record)
-            .collect())
+            .iter_range(self.probe(record))
+            .map(|cell| cell.map(|c| c.record)))
+    }
+
+    /// Like `scan_iter()`, but gives the matching entries in descending
+    /// order. The entries after them are read first, as there's no seeking
+    /// from the end of an index yet, so this is cheapest for the largest
+    /// values.
+    pub fn scan_rev(&self, record: Record) -> Result<impl Iterator<Item = Result<Record>>> {
+        let range = self.probe(record);
+        let after = range.clone();
+        Ok(self
+            .dump_rev()?
+            .skip_while(move |entry| match *entry {
+                Ok(ref entry) => after.compare(entry) == RangeComparison::Greater,
+                Err(_) => false,
+            })
+            .take_while(move |entry| match *entry {
+                Ok(ref entry) => range.compare(entry) == RangeComparison::InRange,
+                Err(_) => true,
+            }))
+    }
+
+    // The range of entries matching `record`, once it's been converted as
+    // the index's values were when they were stored: e.g. the text '74' is
+    // stored as the integer 74 in an INTEGER column, so that's what it must
+    // be to find entries with 74.
+    fn probe(&self, record: Record) -> IndexRange {
+        let fields = record
+            .into_fields()
+            .into_iter()
+            .enumerate()
+            .map(|(i, field)| match self.affinities.get(i) {
+                Some(&affinity) => field.with_affinity(affinity),
+                None => field,
+            })
+            .collect();
+        IndexRange::new(Record::new(fields))
+    }
+
+    /// Like `scan()`, but probes the index with plain values rather than a
+    /// `Record`, matching entries whose leading columns equal `values`.
+    pub fn scan_values(&self, values: &[Value]) -> Result<Vec<Record>> {
+        let fields = values.iter().cloned().map(Field::from).collect();
+        self.scan(Record::new(fields))
+    }
+
+    /// The entries whose leading columns equal `prefix`, and whose next
+    /// column lies between `lower` and `upper`, in index order.
+    pub fn scan_bounds(
+        &self,
+        prefix: Vec<Field>,
+        lower: Option<Bound>,
+        upper: Option<Bound>,
+    ) -> Result<Vec<Record>> {
+        let btree = IndexBTree::new(self.pager.clone(), self.page_num)?;
+        btree
+            .iter_range(IndexBounds {
+                prefix,
+                lower,
+                upper,
+            })
+            .map(|cell| cell.map(|c| c.record))
+            .collect()
     }
 }
 
@@ -148,8 +512,349 @@ impl fmt::Debug for Index {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
         write!(
             f,
-            "Index for {:?} {{ name: {:?}, page_num: {:?} }}",
-            self.tbl_name, self.name, self.page_num,
+            "Index for {:?} {{ name: {:?}, page_num: {:?}, kind: {:?} }}",
+            self.tbl_name, self.name, self.page_num, self.kind,
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::schema::Schema;
+    use crate::testing::open_fixture;
+
+    #[test]
+    fn test_split_rowid_secondary_index() {
+        let schema = open_fixture("without_rowid.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "pairs_b")
+            .unwrap();
+        assert_eq!(index.kind(), IndexKind::Rowid);
+
+        let entries = index.scan(Record::new(vec![Field::from("c")])).unwrap();
+        assert_eq!(entries.len(), 1);
+        let (key, rowid) = index.split_rowid(entries[0].clone()).unwrap();
+        assert_eq!(key.len(), 1);
+        assert_eq!(key[0].as_text().unwrap(), "c");
+        assert_eq!(rowid, Some(3));
+        assert_eq!(
+            index
+                .scan_rowids(Record::new(vec![Field::from("c")]))
+                .unwrap(),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn test_split_rowid_without_rowid_primary_key() {
+        let schema = open_fixture("without_rowid.db");
+        let table = schema.table("kv").unwrap();
+        let index = table.primary_key_index().unwrap().unwrap();
+        assert_eq!(index.kind(), IndexKind::PrimaryKey);
+
+        let entries = index.scan(Record::new(vec![Field::from("c")])).unwrap();
+        assert_eq!(entries.len(), 1);
+        let (key, rowid) = index.split_rowid(entries[0].clone()).unwrap();
+        assert_eq!(key.len(), 2);
+        assert_eq!(key[0].as_text().unwrap(), "c");
+        assert_eq!(key[1].as_integer().unwrap(), 2);
+        assert_eq!(rowid, None);
+        assert!(index
+            .scan_rowids(Record::new(vec![Field::from("c")]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_without_rowid_secondary_index_has_no_rowids() {
+        let schema = open_fixture("without_rowid.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "kv_v")
+            .unwrap();
+        assert_eq!(index.kind(), IndexKind::WithoutRowid);
+
+        let entries = index.scan(Record::new(vec![Field::from(1)])).unwrap();
+        let (key, rowid) = index.split_rowid(entries[0].clone()).unwrap();
+        // The entry is (v, k): the indexed column followed by the primary key.
+        assert_eq!(key[1].as_text().unwrap(), "b");
+        assert_eq!(rowid, None);
+    }
+
+    #[test]
+    fn test_index_columns() {
+        assert_eq!(index_columns("CREATE INDEX pairs_b ON pairs(b)"), vec!["b"]);
+        assert_eq!(
+            index_columns("CREATE INDEX i ON t (\"a\" DESC, b COLLATE nocase)"),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_scan_bounds() {
+        let schema = open_fixture("indexed.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "items_price")
+            .unwrap();
+        assert_eq!(index.columns(), &["price".to_owned()]);
+        assert_eq!(index.depth().unwrap(), 1);
+
+        let bound = |value: u64, inclusive| {
+            Some(Bound {
+                value: Field::from(value),
+                inclusive,
+            })
+        };
+        let prices = |lower, upper| {
+            index
+                .scan_bounds(vec![], lower, upper)
+                .unwrap()
+                .iter()
+                .map(|entry| entry[0].as_integer().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let between = prices(bound(100, true), bound(110, false));
+        assert!(!between.is_empty());
+        assert!(between.iter().all(|p| (100..110).contains(p)));
+        assert!(between.windows(2).all(|w| w[0] <= w[1]));
+        assert!(prices(bound(499, false), None).is_empty());
+    }
+
+    #[test]
+    fn test_scan_values() {
+        let schema = open_fixture("indexed.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "items_price")
+            .unwrap();
+        let row_ids: Vec<_> = index
+            .scan_values(&[Value::Integer(74)])
+            .unwrap()
+            .iter()
+            .map(|entry| entry[1].as_integer().unwrap())
+            .collect();
+        let expected: Vec<_> = (1..=300u64).filter(|n| n * 37 % 500 == 74).collect();
+        assert!(!expected.is_empty());
+        assert_eq!(row_ids, expected);
+
+        // On a composite index, the values match its leading columns.
+        let schema = open_fixture("composite.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "visits_site_day")
+            .unwrap();
+        let entries = index.scan_values(&[Value::from("c")]).unwrap();
+        assert_eq!(entries.len(), 30);
+        assert!(entries.iter().all(|e| e[0].as_text().unwrap() == "c"));
+        let days: Vec<_> = entries.iter().map(|e| e[1].as_integer().unwrap()).collect();
+        assert_eq!(days, (1..=30).collect::<Vec<_>>());
+
+        let entries = index
+            .scan_values(&[Value::from("c"), Value::Integer(7)])
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0][2].as_integer().unwrap(), 33);
+        assert!(index
+            .scan_values(&[Value::from("c"), Value::Integer(31)])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_scan_numeric_text() {
+        // price is an INTEGER column, so text that looks like a number finds
+        // the entries for that number, as SQLite stored it as one.
+        let schema = open_fixture("indexed.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "items_price")
+            .unwrap();
+        let scan = |field: Field| index.scan_rowids(Record::new(vec![field])).unwrap();
+        let expected = scan(Field::from(74u64));
+        assert!(!expected.is_empty());
+        assert_eq!(scan(Field::from("74")), expected);
+        assert_eq!(scan(Field::from(" 74.0 ")), expected);
+        assert!(scan(Field::from("74 apples")).is_empty());
+
+        // The same goes for an index more than a page deep, whose interior
+        // pages hold some of the entries.
+        let schema = open_fixture("analyzed.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "people_age")
+            .unwrap();
+        assert!(index.depth().unwrap() > 1);
+        for age in 0..100u64 {
+            let scan = |field: Field| index.scan_rowids(Record::new(vec![field])).unwrap();
+            let expected = scan(Field::from(age));
+            assert_eq!(expected.len(), 10, "age {}", age);
+            assert_eq!(scan(Field::from(age.to_string().as_str())), expected);
+        }
+
+        assert_eq!(
+            Field::from("2")
+                .with_affinity(Type::Float)
+                .as_float()
+                .unwrap(),
+            2.0
+        );
+        assert_eq!(
+            Field::from("2.5")
+                .with_affinity(Type::Integer)
+                .as_float()
+                .unwrap(),
+            2.5
+        );
+        assert_eq!(Field::from("2").with_affinity(Type::Text).ty(), Type::Text);
+    }
+
+    #[test]
+    fn test_descending() {
+        let schema = open_fixture("analyzed.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "people_age")
+            .unwrap();
+        let ages = |entries: Vec<Record>| -> Vec<u64> {
+            entries.iter().map(|e| e[0].as_integer().unwrap()).collect()
+        };
+
+        // The index is more than one page deep, so some of its entries are
+        // in its interior page. Every row must still have an entry.
+        assert!(index.depth().unwrap() > 1);
+        let rows = schema.table("people").unwrap().len().unwrap();
+        let mut forward = index.dump().unwrap();
+        let backward: Vec<_> = index.dump_rev().unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(forward.len(), rows);
+        assert_eq!(backward.len(), rows);
+        forward.reverse();
+        assert_eq!(ages(backward.clone()), ages(forward));
+        assert!(ages(backward).windows(2).all(|w| w[0] >= w[1]));
+
+        let rowids = |entries: &[Record]| -> Vec<u64> {
+            entries.iter().map(|e| e[1].as_integer().unwrap()).collect()
+        };
+        for age in 0..100u64 {
+            let probe = || Record::new(vec![Field::from(age)]);
+            let mut forward = index.scan(probe()).unwrap();
+            let backward: Vec<_> = index
+                .scan_rev(probe())
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(backward.len(), 10, "age {}", age);
+            forward.reverse();
+            assert_eq!(rowids(&backward), rowids(&forward));
+        }
+    }
+
+    #[test]
+    fn test_take_from_dump_iter() {
+        let pager = Rc::new(Pager::open("fixtures/analyzed.db").unwrap());
+        let schema = Schema::new(pager.clone()).unwrap();
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "people_age")
+            .unwrap();
+
+        let before = pager.pages_read();
+        let first: Vec<_> = index
+            .dump_iter()
+            .unwrap()
+            .take(5)
+            .collect::<Result<_>>()
+            .unwrap();
+        let taken = pager.pages_read() - before;
+        assert_eq!(first.len(), 5);
+        assert!(first
+            .iter()
+            .all(|entry| entry[0].as_integer().unwrap() == 0));
+
+        let before = pager.pages_read();
+        let entries = index.dump_iter().unwrap().count();
+        let dumped = pager.pages_read() - before;
+        assert_eq!(entries, schema.table("people").unwrap().len().unwrap());
+        assert_eq!(taken, index.depth().unwrap());
+        assert!(taken < dumped, "{} pages read of {}", taken, dumped);
+    }
+
+    #[test]
+    fn test_scan_null_keys() {
+        let schema = open_fixture("nullable.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "contacts_email")
+            .unwrap();
+
+        let entries = index
+            .scan_values(&[Value::from("user-5@example.com")])
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(index.split_rowid(entries[0].clone()).unwrap().1, Some(5));
+
+        // The index is more than a page deep, but has an entry for every row.
+        assert!(index.depth().unwrap() > 1);
+        let rows = schema.table("contacts").unwrap().len().unwrap();
+        assert_eq!(index.dump().unwrap().len(), rows);
+
+        // Every third row has a NULL email, and they all sit together at the
+        // start of the index.
+        let nulls = index.scan_values(&[Value::Null]).unwrap();
+        assert_eq!(nulls.len(), 100);
+        assert!(nulls.iter().all(|entry| entry[0].ty() == Type::Null));
+        assert!(index
+            .scan_values(&[Value::from("nobody")])
+            .unwrap()
+            .is_empty());
+
+        // A probe with more values than the entries have fields matches
+        // nothing, rather than panicking.
+        assert!(index
+            .scan_values(&[
+                Value::from("user-5@example.com"),
+                Value::Integer(5),
+                Value::Null
+            ])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unique_and_partial() {
+        let pager = Rc::new(Pager::open("fixtures/sales.db").unwrap());
+        let index = |sql: Option<&str>| {
+            let columns = sql.map(index_columns).unwrap_or_default();
+            let sql = sql.map(|s| s.to_owned());
+            Index::new(pager.clone(), 2, "t", "i", IndexKind::Rowid, columns, sql).unwrap()
+        };
+        let plain = index(Some("CREATE INDEX i ON t(a)"));
+        assert!(!plain.unique() && !plain.partial());
+        let unique = index(Some("CREATE UNIQUE INDEX i ON t(a) WHERE a > 0"));
+        assert!(unique.unique() && unique.partial());
+        // A WHERE inside the column list isn't the index's.
+        assert!(!index(Some("CREATE INDEX i ON t(\"where\")")).partial());
+        assert!(index(None).unique());
+    }
+}
@@ -0,0 +1,30 @@
+//! Reading (and maybe writing) SQLite files.
+
+#[macro_use]
+extern crate error_chain;
+
+pub mod aggregate;
+pub mod btree;
+pub mod compress;
+pub mod db;
+pub mod errors;
+pub mod expr;
+pub mod function;
+#[cfg(test)]
+mod fuzz;
+pub mod index;
+pub mod output;
+pub mod pager;
+pub mod parser;
+pub mod planner;
+pub mod query;
+pub mod record;
+pub mod schema;
+pub mod source;
+pub mod table;
+#[cfg(test)]
+mod testing;
+pub mod types;
+pub mod util;
+pub mod value;
+pub mod wal;
@@ -1,84 +1,164 @@
 #[macro_use]
 extern crate error_chain;
 
-mod btree;
-mod db;
-mod errors;
-mod index;
-mod pager;
-mod record;
-mod schema;
-mod table;
-mod types;
-mod util;
-
+use std::env;
 use std::io::{self, Write};
 use std::rc::Rc;
 
-use crate::errors::*;
-use crate::pager::Pager;
-use crate::record::{Field, Record};
-use crate::schema::Schema;
+use byteorder::{BigEndian, ByteOrder};
 
-use nom_sql::{FieldExpression, SelectStatement, SqlQuery};
+use rqlite::errors::*;
+use rqlite::output::{self, Format};
+use rqlite::pager::Pager;
+use rqlite::parser::{split_statements, StatementBuffer};
+use rqlite::query::{Params, Statement};
+use rqlite::record::{Field, Record};
+use rqlite::schema::Schema;
+use rqlite::util::hexdump;
 
-#[derive(Debug)]
-struct SelectOp {
-    table: String,
-    columns: Vec<String>,
+fn run_query(schema: &Schema, query: &str, format: Format) -> Result<()> {
+    let statement = Statement::prepare(query)?;
+    let rows = statement.execute(schema, Params::None)?;
+    let stdout = io::stdout();
+    output::write_rows(format, &statement.column_names(), &rows, stdout.lock())
 }
 
-impl SelectOp {
-    fn from_stmt(stmt: SelectStatement) -> Result<SelectOp> {
-        let (mut tables, fields) = (stmt.tables, stmt.fields);
-
-        if tables.len() != 1 {
-            bail!("Expected 1 table to appear in SELECT statement");
+// Runs each of the statements in `sql`, which may also hold only comments.
+fn run_statements(schema: &Schema, sql: &str, format: Format) {
+    match split_statements(sql) {
+        Ok(statements) => {
+            for statement in statements {
+                if let Err(e) = run_query(schema, statement, format) {
+                    println!("Error running query: {}", e)
+                }
+            }
         }
-        let table = tables.pop().unwrap().name;
-
-        let columns: Result<Vec<_>> = fields
-            .into_iter()
-            .map(|field| match field {
-                FieldExpression::Col(nom_sql::Column { name, .. }) => Ok(name),
-                _ => bail!("Not implemented: non-column fields in SELECT"),
-            })
-            .collect();
-
-        Ok(SelectOp {
-            table,
-            columns: columns?,
-        })
+        Err(e) => println!("Error running query: {}", e),
     }
 }
 
-fn run_query(schema: &Schema, query: &str) -> Result<()> {
-    let stmt = nom_sql::parser::parse_query(&query)
-        .map_err(|_| format!("Error parsing statement: {}", query))?;
-    match stmt {
-        SqlQuery::Select(select) => {
-            let op =
-                SelectOp::from_stmt(select).chain_err(|| format!("Error processing statement:"))?;
-            let table = schema.table(op.table)?;
-            let result = table
-                .select(op.columns)
-                .chain_err(|| format!("Error running query:"));
-            println!("{:?}", result)
-        }
-        _ => bail!("Unsupported statement - SELECT only please"),
+// Prints a page's bytes, after a summary of where its b-tree page header and
+// cell pointers are.
+fn dump_page(pager: &Pager, page_num: usize) -> Result<()> {
+    if page_num < 1 || page_num > pager.header.num_pages {
+        bail!(
+            "Page {} out of range (database has {} pages)",
+            page_num,
+            pager.header.num_pages
+        );
+    }
+    let bytes = pager.get_page(page_num)?;
+    println!("Page {} ({} bytes)", page_num, bytes.len());
+
+    // Pointer-map pages are a list of entries, with no header of their own.
+    if pager.is_ptrmap_page(page_num) {
+        println!("  page type:      pointer map");
+        print!("{}", hexdump(&bytes, 0));
+        return Ok(());
+    }
+
+    // Page 1 starts with the database header, before its b-tree page header.
+    let header_offset = if page_num == 1 { 100 } else { 0 };
+    let ty = bytes[header_offset];
+    let (description, header_len) = match ty {
+        0x02 => ("index interior", 12),
+        0x05 => ("table interior", 12),
+        0x0a => ("index leaf", 8),
+        0x0d => ("table leaf", 8),
+        _ => ("not a b-tree page", 8),
     };
+    let num_cells = BigEndian::read_u16(&bytes[header_offset + 3..]) as usize;
+    let pointers = header_offset + header_len;
+    println!("  header offset:  {}", header_offset);
+    println!("  page type:      0x{:02x} ({})", ty, description);
+    println!("  cells:          {}", num_cells);
+    println!(
+        "  cell pointers:  0x{:x}..0x{:x}",
+        pointers,
+        pointers + num_cells * 2
+    );
+    print!("{}", hexdump(&bytes, 0));
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let pager = Rc::new(Pager::open("aFile.db")?);
+// The command line: `rqlite [--format table|json|csv] [FILE [SQL]]`, where
+// `--json` and `--csv` are short for `--format json` and `--format csv`.
+// Given SQL, its statements are run and we exit; otherwise the REPL runs.
+struct Args {
+    path: String,
+    sql: Option<String>,
+    format: Format,
+}
+
+const USAGE: &str = "Usage: rqlite [--format table|json|csv] [FILE [SQL]]";
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args> {
+    let mut format = Format::Table;
+    let mut positional = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => format = Format::Json,
+            "--csv" => format = Format::Csv,
+            "--format" => {
+                let name = args.next().ok_or(USAGE)?;
+                format = Format::from_name(&name)?;
+            }
+            _ if arg.starts_with("--format=") => {
+                format = Format::from_name(&arg["--format=".len()..])?;
+            }
+            _ if arg.starts_with('-') => bail!("Unknown option: {}\n{}", arg, USAGE),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() > 2 {
+        bail!(USAGE);
+    }
+    let mut positional = positional.into_iter();
+    Ok(Args {
+        path: positional.next().unwrap_or_else(|| "aFile.db".to_owned()),
+        sql: positional.next(),
+        format,
+    })
+}
+
+// Opens the database at `path` and reads its schema, failing for anything
+// that isn't a database we can read.
+fn open(path: &str) -> Result<(Rc<Pager>, Schema)> {
+    let pager =
+        Rc::new(Pager::open(path).chain_err(|| format!("Failed to open database {}", path))?);
+    let schema = Schema::new(pager.clone())
+        .chain_err(|| format!("Failed to read schema of database {}", path))?;
+    Ok((pager, schema))
+}
+
+// Errors from opening the database are fatal, and `quick_main!` prints them
+// and exits non-zero. Errors from each query are printed and the REPL goes on.
+quick_main!(run);
+
+fn run() -> Result<()> {
+    let args = parse_args(env::args().skip(1))?;
+    let (pager, schema) = open(&args.path)?;
+    if let Some(sql) = args.sql {
+        for statement in split_statements(&sql)? {
+            run_query(&schema, statement, args.format)?;
+        }
+        return Ok(());
+    }
+
     println!(
         "Page Size: {}, Reserved Bytes Per Page: {}, Num Pages: {}",
         pager.header.page_size, pager.header.reserved_byes_per_page, pager.header.num_pages
     );
 
-    let schema = Schema::new(pager)?;
-    println!("Tables: {:#?}", schema.tables()?);
+    let tables = schema.tables()?;
+    println!("Tables: {:#?}", tables);
+    // Counting every table's rows could take a while, so they're estimated.
+    for table in &tables {
+        match table.estimated_row_count() {
+            Ok(rows) => println!("{}: about {} rows", table.name(), rows),
+            Err(e) => println!("{}: failed to estimate rows: {}", table.name(), e),
+        }
+    }
     let indices = schema.indices()?;
     for (i, index) in indices.iter().enumerate() {
         println!("{:?}", index);
@@ -94,11 +174,22 @@ fn main() -> Result<()> {
         }
     }
 
+    let mut statements = StatementBuffer::new();
     loop {
-        print!("> ");
+        // Like sqlite3, prompt differently while a statement is unfinished.
+        print!("{}", if statements.is_empty() { "> " } else { "...> " });
         io::stdout().flush()?;
         let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer)?;
+        if io::stdin().read_line(&mut buffer)? == 0 {
+            break;
+        }
+
+        if !statements.is_empty() {
+            if let Some(sql) = statements.push_line(&buffer) {
+                run_statements(&schema, &sql, args.format);
+            }
+            continue;
+        }
 
         if buffer.trim() == ".quit" {
             break;
@@ -116,10 +207,66 @@ fn main() -> Result<()> {
             continue;
         }
 
-        if let Err(e) = run_query(&schema, &buffer) {
-            println!("Error running query: {}", e)
+        if buffer.starts_with(".schema ") {
+            let (_, name) = buffer.split_at(8);
+            match schema.object(name.trim()) {
+                Ok(object) => println!("{:#?}", object),
+                Err(e) => println!("Failed to read {}: {}", name.trim(), e),
+            }
+            continue;
+        }
+
+        if buffer.starts_with(".hexdump ") {
+            let (_, page_num) = buffer.split_at(9);
+            match page_num.trim().parse() {
+                Ok(page_num) => {
+                    if let Err(e) = dump_page(&pager, page_num) {
+                        println!("Failed to dump page {}: {}", page_num, e);
+                    }
+                }
+                Err(_) => println!("Invalid page number: {}", page_num.trim()),
+            }
+            continue;
+        }
+
+        if let Some(sql) = statements.push_line(&buffer) {
+            run_statements(&schema, &sql, args.format);
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_open_invalid_file() {
+        assert!(open("fixtures/missing.db").is_err());
+        assert!(open("fixtures/generate.py").is_err());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parse = |args: &[&str]| parse_args(args.iter().map(|&arg| arg.to_owned()));
+        let args = parse(&[]).unwrap();
+        assert_eq!(
+            (args.path.as_str(), args.sql, args.format),
+            ("aFile.db", None, Format::Table)
+        );
+        let args = parse(&["--csv", "a.db", "SELECT 1"]).unwrap();
+        assert_eq!(args.path, "a.db");
+        assert_eq!(args.sql.as_deref(), Some("SELECT 1"));
+        assert_eq!(args.format, Format::Csv);
+        assert_eq!(
+            parse(&["a.db", "--format", "json"]).unwrap().format,
+            Format::Json
+        );
+        assert_eq!(parse(&["--format=json"]).unwrap().format, Format::Json);
+        assert!(parse(&["--format"]).is_err());
+        assert!(parse(&["--format", "xml"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert!(parse(&["a.db", "SELECT 1", "extra"]).is_err());
+    }
+}
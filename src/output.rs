@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::errors::*;
+use crate::record::{json_string, Field};
+use crate::types::Type;
+
+/// How query results are printed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Columns padded to line up, under a header.
+    Table,
+    /// A JSON array with an object for each row, keyed by column name.
+    Json,
+    /// A header line, then a line for each row, quoted as RFC 4180 describes.
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format> {
+        match name {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => bail!(
+                "Unknown output format: {} (expected table, json or csv)",
+                name
+            ),
+        }
+    }
+}
+
+/// Writes `rows` to `writer` in `format`, with their values in the order of
+/// `columns`.
+pub fn write_rows<W: Write>(
+    format: Format,
+    columns: &[String],
+    rows: &[HashMap<String, Field>],
+    mut writer: W,
+) -> Result<()> {
+    match format {
+        Format::Table => {
+            let mut lines = vec![columns.to_vec()];
+            for row in rows {
+                let line = values(columns, row)?
+                    .into_iter()
+                    .map(text)
+                    .collect::<Result<_>>()?;
+                lines.push(line);
+            }
+            let widths: Vec<usize> = (0..columns.len())
+                .map(|i| {
+                    lines
+                        .iter()
+                        .map(|l| l[i].chars().count())
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+            lines.insert(1, rule);
+            for line in lines {
+                let cells: Vec<String> = line
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+                    .collect();
+                writeln!(writer, "{}", cells.join("  ").trim_end())?;
+            }
+        }
+        Format::Json => {
+            let keys: Vec<String> = columns.iter().map(|c| json_string(c)).collect();
+            write!(writer, "[")?;
+            for (i, row) in rows.iter().enumerate() {
+                let fields: Vec<String> = keys
+                    .iter()
+                    .zip(values(columns, row)?)
+                    .map(|(key, field)| Ok(format!("{}:{}", key, field.to_json()?)))
+                    .collect::<Result<_>>()?;
+                let separator = if i > 0 { ",\n" } else { "" };
+                write!(writer, "{}{{{}}}", separator, fields.join(","))?;
+            }
+            writeln!(writer, "]")?;
+        }
+        Format::Csv => {
+            let header: Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+            writeln!(writer, "{}", header.join(","))?;
+            for row in rows {
+                let line: Vec<String> = values(columns, row)?
+                    .into_iter()
+                    .map(|field| Ok(csv_field(&text(field)?)))
+                    .collect::<Result<_>>()?;
+                writeln!(writer, "{}", line.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The row's values, in the order of `columns`.
+fn values<'a>(columns: &[String], row: &'a HashMap<String, Field>) -> Result<Vec<&'a Field>> {
+    columns
+        .iter()
+        .map(|column| {
+            row.get(column)
+                .ok_or_else(|| format!("Unknown column: {}", column).into())
+        })
+        .collect()
+}
+
+// A field as text, with NULL as the empty string, as sqlite3 shows it.
+fn text(field: &Field) -> Result<String> {
+    match field.ty() {
+        Type::Null => Ok(String::new()),
+        _ => field.to_text(),
+    }
+}
+
+// "Fields containing line breaks (CRLF), double quotes, and commas should be
+//  enclosed in double-quotes. ... a double-quote appearing inside a field
+//  must be escaped by preceding it with another double quote."
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::LiteralValue;
+
+    fn write(format: Format) -> String {
+        let columns = vec!["id".to_owned(), "note".to_owned()];
+        let mut rows = vec![];
+        for &(id, note) in &[
+            (1, Some("plain")),
+            (22, Some("say \"hi\", twice")),
+            (3, None),
+        ] {
+            let mut row = HashMap::new();
+            row.insert("id".to_owned(), Field::from(id as u64));
+            let note = note.map_or(Field::Literal(LiteralValue::Null), Field::from);
+            row.insert("note".to_owned(), note);
+            rows.push(row);
+        }
+        let mut out = Vec::new();
+        write_rows(format, &columns, &rows, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            write(Format::Table),
+            "id  note\n--  ---------------\n1   plain\n22  say \"hi\", twice\n3\n"
+        );
+        assert_eq!(
+            write(Format::Json),
+            "[{\"id\":1,\"note\":\"plain\"},\n\
+             {\"id\":22,\"note\":\"say \\\"hi\\\", twice\"},\n\
+             {\"id\":3,\"note\":null}]\n"
+        );
+        assert_eq!(
+            write(Format::Csv),
+            "id,note\n1,plain\n22,\"say \"\"hi\"\", twice\"\n3,\n"
+        );
+        assert!(Format::from_name("xml").is_err());
+    }
+}
@@ -1,42 +1,722 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+#[cfg(any(test, feature = "tracing"))]
+use std::env;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::SeekFrom;
+use std::io::{Cursor, SeekFrom};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use bytes::Bytes;
 
-use crate::db::DbHeader;
+use crate::btree::{self, read_page, PageIter};
+use crate::compress::Compression;
+use crate::db::{DbHeader, HeaderField};
 use crate::errors::*;
+use crate::wal::Wal;
+
+// How many pages a full scan reads ahead by default.
+const DEFAULT_READAHEAD: usize = 16;
+
+// The page containing the byte at offset 2^30, which SQLite uses for locking
+// and never stores anything in.
+const PENDING_BYTE: usize = 0x4000_0000;
+
+// The checksum VFS keeps an 8-byte checksum in the reserved space at the end
+// of each page.
+const CHECKSUM_LEN: usize = 8;
+
+/// Something that happened while reading the database, passed to the function
+/// given to `Pager::set_tracer()`. Tracing is only built with the `tracing`
+/// feature, so that it costs nothing otherwise.
+#[cfg(any(test, feature = "tracing"))]
+#[derive(Debug, PartialEq)]
+pub enum TraceEvent<'a> {
+    /// A query on `table` has started.
+    Query { table: &'a str },
+    /// The planner chose how to read a table: using `index`, or a full scan
+    /// if there's none.
+    Plan {
+        table: &'a str,
+        index: Option<&'a str>,
+    },
+    /// A query on `table` returned `rows` rows.
+    Rows { table: &'a str, rows: usize },
+    /// A page was read. `page_type` is the first byte of its b-tree page
+    /// header, which means nothing for pages that aren't in a b-tree.
+    PageRead { number: usize, page_type: u8 },
+}
+
+#[cfg(any(test, feature = "tracing"))]
+type Tracer = Box<dyn Fn(&TraceEvent<'_>)>;
+
+// With the `tracing` feature, running with `RUST_LOG=rqlite=debug` (or any
+// other setting that asks for our debug messages) prints each event.
+#[cfg(any(test, feature = "tracing"))]
+fn env_tracer() -> Option<Tracer> {
+    match env::var("RUST_LOG") {
+        Ok(ref filter) if cfg!(feature = "tracing") && logs_debug(filter) => {
+            Some(Box::new(|event| eprintln!("rqlite: {:?}", event)))
+        }
+        _ => None,
+    }
+}
+
+// Whether a `RUST_LOG` filter, a list of `target=level` directives, enables
+// debug messages from us. A directive without a target applies to every
+// target, and one without a level enables every level.
+#[cfg(any(test, feature = "tracing"))]
+fn logs_debug(filter: &str) -> bool {
+    const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+    filter.split(',').any(|directive| {
+        let directive = directive.trim().to_lowercase();
+        if directive.is_empty() {
+            return false;
+        }
+        let (target, level) = match directive.find('=') {
+            Some(i) => (&directive[..i], &directive[i + 1..]),
+            None if LEVELS.contains(&directive.as_str()) => ("", directive.as_str()),
+            None => (directive.as_str(), "trace"),
+        };
+        let ours = target.is_empty() || target == "rqlite" || target.starts_with("rqlite::");
+        ours && (level == "debug" || level == "trace")
+    })
+}
+
+// Where a pager reads pages from: a file, or a buffer in memory.
+trait Storage: Read + Seek {}
+
+impl<T: Read + Seek> Storage for T {}
 
 pub struct Pager {
-    file: RefCell<File>,
+    file: RefCell<Box<dyn Storage>>,
+    wal: Option<Wal>,
     pub header: DbHeader,
+    // Pages that have been read ahead of being asked for. Each is dropped
+    // once it has been handed out by `get_page()`, so this never holds more
+    // than one run of prefetched pages.
+    prefetched: RefCell<HashMap<usize, Bytes>>,
+    readahead: Cell<usize>,
+    pages_read: Cell<usize>,
+    file_reads: Cell<usize>,
+    verify_checksums: bool,
+    interrupt: RefCell<Option<Arc<AtomicBool>>>,
+    #[cfg(any(test, feature = "tracing"))]
+    tracer: RefCell<Option<Tracer>>,
 }
 
 impl Pager {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Pager> {
-        let mut file = File::open(path)?;
+        Pager::open_inner(path, false, false)
+    }
+
+    /// Opens a database, refusing it unless its header meets every invariant
+    /// the file format lists. See `DbHeader::validate()`.
+    pub fn open_strict<P: AsRef<Path>>(path: P) -> Result<Pager> {
+        Pager::open_inner(path, false, true)
+    }
+
+    /// Opens a database, optionally checking each page against the checksum
+    /// that SQLite's checksum VFS stores in its reserved bytes. Not every
+    /// database with reserved bytes uses them for this, so it's opt-in.
+    pub fn open_with<P: AsRef<Path>>(path: P, verify_checksums: bool) -> Result<Pager> {
+        Pager::open_inner(path, verify_checksums, false)
+    }
+
+    /// Reads a database from a buffer holding its image, rather than a file.
+    /// The image may be compressed, as a file given to `open()` may be.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Pager> {
+        let data = match Compression::detect(&data) {
+            Some(compression) => compression.decompress(&data)?,
+            None => data,
+        };
+        Pager::new(Box::new(Cursor::new(data)), None, false, false)
+    }
 
+    /// An empty database, with no tables, held in memory. Its page 1 holds
+    /// an empty sqlite_master, as a freshly created database's does.
+    pub fn create_empty(page_size: usize) -> Result<Pager> {
+        if !is_valid_page_size(page_size) {
+            bail!("Invalid page size: {}", page_size);
+        }
+        let mut data = vec![0; page_size];
+        data[..16].copy_from_slice(b"SQLite format 3\0");
+        // A page size of 65536 is stored as 1.
+        let stored_page_size = if page_size == 65536 {
+            1
+        } else {
+            page_size as u16
+        };
+        BigEndian::write_u16(&mut data[16..], stored_page_size);
+        // Legacy read and write versions, no reserved bytes, and the payload
+        // fractions the format requires.
+        data[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+        // One page, schema format 4 and UTF-8.
+        BigEndian::write_u32(&mut data[28..], 1);
+        BigEndian::write_u32(&mut data[44..], 4);
+        BigEndian::write_u32(&mut data[56..], 1);
+        // Page 1's b-tree header: a table leaf with no cells, whose cell
+        // content area starts at the end of the page (0 meaning 65536).
+        data[100] = 0x0d;
+        BigEndian::write_u16(&mut data[105..], page_size as u16);
+        Pager::from_bytes(data)
+    }
+
+    fn open_inner<P: AsRef<Path>>(path: P, verify_checksums: bool, strict: bool) -> Result<Pager> {
+        let mut file = File::open(&path)?;
+        // An archived database may be compressed. Pages can't be read from
+        // the middle of a compressed stream, so the whole file is
+        // decompressed into memory and read from there.
+        let mut magic = Vec::new();
+        (&mut file).take(4).read_to_end(&mut magic)?;
+        if let Some(compression) = Compression::detect(&magic) {
+            let mut data = magic;
+            file.read_to_end(&mut data)?;
+            let data = compression.decompress(&data)?;
+            return Pager::new(Box::new(Cursor::new(data)), None, verify_checksums, strict);
+        }
+        file.seek(SeekFrom::Start(0))?;
+        // A database in WAL mode may have committed changes that are still
+        // only in its -wal file.
+        let wal = Wal::open(&path)?;
+        Pager::new(Box::new(file), wal, verify_checksums, strict)
+    }
+
+    fn new(
+        mut file: Box<dyn Storage>,
+        wal: Option<Wal>,
+        verify_checksums: bool,
+        strict: bool,
+    ) -> Result<Pager> {
         let mut buffer = [0; 100];
-        file.read_exact(&mut buffer)
-            .chain_err(|| ErrorKind::InvalidDbHeader("Error reading header".to_owned()))?;
-        let header = DbHeader::parse(&buffer)?;
+        match wal.as_ref().and_then(|wal| wal.page(1)) {
+            Some(page) => buffer.copy_from_slice(&page[..100]),
+            None => file.read_exact(&mut buffer).chain_err(|| {
+                ErrorKind::InvalidDbHeader("Error reading header".to_owned(), None)
+            })?,
+        }
+        if strict {
+            DbHeader::validate(&buffer)?;
+        }
+        let mut header = DbHeader::parse(&buffer)?;
+        // Versions of SQLite before 3.7.0 didn't keep the in-header database
+        // size up to date, so fall back to the size of the file.
+        if header.num_pages == 0 {
+            header.num_pages = file.seek(SeekFrom::End(0))? as usize / header.page_size;
+        }
+        if let Some(ref wal) = wal {
+            if let Some(num_pages) = wal.num_pages() {
+                if wal.page_size != header.page_size {
+                    bail!(
+                        "Write-ahead log has {}-byte pages, but the database has {}-byte pages",
+                        wal.page_size,
+                        header.page_size
+                    );
+                }
+                // The last commit in the log says how big the database is.
+                header.num_pages = num_pages;
+            }
+        }
+        if header.largest_root_page > header.num_pages {
+            bail!(ErrorKind::InvalidDbHeader(
+                format!(
+                    "largest root page {} is past the end of the database ({} pages)",
+                    header.largest_root_page, header.num_pages
+                ),
+                Some(HeaderField::new("largest root page", &buffer, 52, 4)),
+            ));
+        }
+        if verify_checksums && header.reserved_byes_per_page != CHECKSUM_LEN {
+            bail!(
+                "Can't verify checksums: pages have {} reserved bytes, not {}",
+                header.reserved_byes_per_page,
+                CHECKSUM_LEN
+            );
+        }
 
         Ok(Pager {
             file: RefCell::new(file),
+            wal,
             header,
+            prefetched: RefCell::new(HashMap::new()),
+            readahead: Cell::new(DEFAULT_READAHEAD),
+            pages_read: Cell::new(0),
+            file_reads: Cell::new(0),
+            verify_checksums,
+            interrupt: RefCell::new(None),
+            #[cfg(any(test, feature = "tracing"))]
+            tracer: RefCell::new(env_tracer()),
         })
     }
 
     pub fn get_page(&self, number: usize) -> Result<Bytes> {
         // SQLite counts pages from 1.
-        let number = number - 1;
+        if number < 1 || number > self.header.num_pages {
+            bail!(ErrorKind::InvalidPageNumber(number));
+        }
+        if let Some(ref flag) = *self.interrupt.borrow() {
+            if flag.load(Ordering::Relaxed) {
+                bail!(ErrorKind::Interrupted);
+            }
+        }
+        self.pages_read.set(self.pages_read.get() + 1);
+        // A page's latest version is in the log, if it's there at all.
+        let prefetched = self.prefetched.borrow_mut().remove(&number);
+        let page = match (
+            self.wal.as_ref().and_then(|wal| wal.page(number)),
+            prefetched,
+        ) {
+            (Some(page), _) | (None, Some(page)) => page,
+            (None, None) => self.read_pages(number, 1)?.pop().unwrap(),
+        };
+
+        if self.verify_checksums {
+            let (data, expected) = page.split_at(page.len() - CHECKSUM_LEN);
+            if checksum(data) != expected {
+                bail!(ErrorKind::ChecksumMismatch(number));
+            }
+        }
+        #[cfg(any(test, feature = "tracing"))]
+        {
+            if let Some(ref tracer) = *self.tracer.borrow() {
+                let header_offset = if number == 1 { 100 } else { 0 };
+                tracer(&TraceEvent::PageRead {
+                    number,
+                    page_type: page[header_offset],
+                });
+            }
+        }
+        Ok(page)
+    }
+
+    /// The cells of a b-tree page, decoded as `C`s. See `btree::read_page()`.
+    pub fn cells<C: btree::Cell>(&self, number: usize) -> Result<PageIter<C>> {
+        Ok(read_page::<C>(self, number)?.iter())
+    }
+
+    /// Reads `count` pages starting at `start` in one go, so that subsequent
+    /// calls to `get_page()` for them don't have to go to the file. Pages past
+    /// the end of the database are ignored.
+    pub fn prefetch(&self, start: usize, count: usize) -> Result<()> {
+        let end = (start + count).min(self.header.num_pages + 1);
+        if start >= end || self.prefetched.borrow().contains_key(&start) {
+            return Ok(());
+        }
+
+        let pages = self.read_pages(start, end - start)?;
+        let mut prefetched = self.prefetched.borrow_mut();
+        for (number, page) in (start..end).zip(pages) {
+            prefetched.entry(number).or_insert(page);
+        }
+        Ok(())
+    }
+
+    /// Whether `page_num` is a pointer-map page. These only exist in
+    /// auto-vacuum databases, where they record the parent of every other
+    /// page so that pages can be moved.
+    pub fn is_ptrmap_page(&self, page_num: usize) -> bool {
+        if self.header.largest_root_page == 0 || page_num < 2 {
+            return false;
+        }
+        // The first pointer-map page is page 2, and each is followed by the
+        // pages it describes: as many as it has room for 5-byte entries.
+        let usable_size = self.header.page_size - self.header.reserved_byes_per_page;
+        let pages_per_map = usable_size / 5 + 1;
+        let mut ptrmap = (page_num - 2) / pages_per_map * pages_per_map + 2;
+        if ptrmap == PENDING_BYTE / self.header.page_size + 1 {
+            ptrmap += 1;
+        }
+        ptrmap == page_num
+    }
+
+    /// The pages on the freelist, which are unused but still part of the
+    /// file until the database is vacuumed. This includes the trunk pages
+    /// that list the others. It's an error for the freelist to hold a
+    /// different number of pages than the header says it does.
+    pub fn free_pages(&self) -> Result<Vec<usize>> {
+        // "A freelist trunk page consists of an array of 4-byte big-endian
+        //  integers. ... The first integer on a freelist trunk page is the
+        //  page number of the next freelist trunk page in the list or zero if
+        //  this is the last freelist trunk page. The second integer on a
+        //  freelist trunk page is the number of leaf page pointers to follow."
+        let usable_size = self.header.page_size - self.header.reserved_byes_per_page;
+        let max_leaves = usable_size / 4 - 2;
+        let mut pages = vec![];
+        let mut trunk = self.header.first_freelist_trunk;
+        while trunk != 0 {
+            // Every free page is listed once, so a longer list has a cycle.
+            if pages.len() >= self.header.freelist_pages {
+                bail!(
+                    "Freelist has more than the {} pages the header says it has",
+                    self.header.freelist_pages
+                );
+            }
+            pages.push(trunk);
+            let page = self.get_page(trunk)?;
+            let leaves = BigEndian::read_u32(&page[4..]) as usize;
+            if leaves > max_leaves {
+                bail!("Freelist trunk page {} lists {} pages", trunk, leaves);
+            }
+            for i in 0..leaves {
+                let leaf = BigEndian::read_u32(&page[8 + 4 * i..]) as usize;
+                if leaf < 2 || leaf > self.header.num_pages {
+                    bail!(ErrorKind::InvalidPageNumber(leaf));
+                }
+                pages.push(leaf);
+            }
+            trunk = BigEndian::read_u32(&page) as usize;
+        }
+        if pages.len() != self.header.freelist_pages {
+            bail!(
+                "Freelist has {} pages, but the header says it has {}",
+                pages.len(),
+                self.header.freelist_pages
+            );
+        }
+        Ok(pages)
+    }
+
+    /// Has every page read fail with `ErrorKind::Interrupted` while `flag`
+    /// is set, so that another thread (or a signal handler) can stop a long
+    /// scan. Scans notice the next time they move onto a new page.
+    pub fn set_interrupt(&self, flag: Arc<AtomicBool>) {
+        *self.interrupt.borrow_mut() = Some(flag);
+    }
+
+    /// Calls `tracer` with each page read, and with the steps of queries on
+    /// this database, instead of any tracer set by `RUST_LOG`.
+    #[cfg(any(test, feature = "tracing"))]
+    pub fn set_tracer<F: Fn(&TraceEvent<'_>) + 'static>(&self, tracer: F) {
+        *self.tracer.borrow_mut() = Some(Box::new(tracer));
+    }
+
+    #[cfg(any(test, feature = "tracing"))]
+    pub fn trace(&self, event: TraceEvent<'_>) {
+        if let Some(ref tracer) = *self.tracer.borrow() {
+            tracer(&event);
+        }
+    }
+
+    /// The number of pages full scans should read ahead. Zero disables
+    /// readahead.
+    pub fn readahead(&self) -> usize {
+        self.readahead.get()
+    }
+
+    pub fn set_readahead(&self, pages: usize) {
+        self.readahead.set(pages);
+    }
+
+    /// The number of pages that have been asked for via `get_page()`.
+    pub fn pages_read(&self) -> usize {
+        self.pages_read.get()
+    }
+
+    /// The number of reads that have been made against the file.
+    pub fn file_reads(&self) -> usize {
+        self.file_reads.get()
+    }
+
+    fn read_pages(&self, start: usize, count: usize) -> Result<Vec<Bytes>> {
+        let offset = (start - 1) * self.header.page_size;
 
         let mut file = self.file.borrow_mut();
-        file.seek(SeekFrom::Start((number * self.header.page_size) as u64))?;
-        let mut buffer = vec![0; self.header.page_size];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let mut buffer = vec![0; count * self.header.page_size];
         file.read_exact(&mut buffer)?;
-        Ok(buffer.into())
+        self.file_reads.set(self.file_reads.get() + 1);
+
+        let buffer = Bytes::from(buffer);
+        Ok((0..count)
+            .map(|i| buffer.slice(i * self.header.page_size, (i + 1) * self.header.page_size))
+            .collect())
+    }
+}
+
+// The checksum VFS's checksum: a Fletcher-like sum over the page as
+// little-endian 32-bit words, stored as two little-endian words.
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let (mut s1, mut s2) = (0u32, 0u32);
+    for words in data.chunks(8) {
+        s1 = s1
+            .wrapping_add(LittleEndian::read_u32(&words[..4]))
+            .wrapping_add(s2);
+        s2 = s2
+            .wrapping_add(LittleEndian::read_u32(&words[4..]))
+            .wrapping_add(s1);
+    }
+    let mut sum = [0; CHECKSUM_LEN];
+    LittleEndian::write_u32(&mut sum[..4], s1);
+    LittleEndian::write_u32(&mut sum[4..], s2);
+    sum
+}
+
+/// Whether SQLite can use pages of `page_size` bytes: a power of two from
+/// 512 to 65536.
+pub fn is_valid_page_size(page_size: usize) -> bool {
+    (512..=65536).contains(&page_size) && page_size.is_power_of_two()
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use byteorder::{BigEndian, ByteOrder};
+
+    use super::Pager;
+    use crate::db::DbHeader;
+    use crate::errors::*;
+    use crate::schema::Schema;
+    use crate::table::Table;
+    use crate::testing::open_doctored_fixture_with;
+
+    fn scan(readahead: usize) -> (usize, usize, usize) {
+        let pager = Rc::new(Pager::open("fixtures/large.db").unwrap());
+        pager.set_readahead(readahead);
+        let sql = "CREATE TABLE numbers(id integer primary key, value int, label text)";
+        let table = Table::new(pager.clone(), 2, "numbers", sql).unwrap();
+        let len = table.len().unwrap();
+        (len, pager.pages_read(), pager.file_reads())
+    }
+
+    #[test]
+    fn test_readahead_reduces_file_reads() {
+        let (len, pages_read, file_reads) = scan(0);
+        let (len_ra, pages_read_ra, file_reads_ra) = scan(16);
+        assert_eq!(len, 5000);
+        assert_eq!(len, len_ra);
+        assert_eq!(pages_read, pages_read_ra);
+        assert_eq!(file_reads, pages_read);
+        assert!(file_reads_ra < file_reads / 4);
+    }
+
+    #[test]
+    fn test_prefetch_matches_get_page() {
+        let pager = Pager::open("fixtures/large.db").unwrap();
+        let direct = pager.get_page(3).unwrap();
+        pager.prefetch(2, 4).unwrap();
+        let reads = pager.file_reads();
+        assert_eq!(pager.get_page(3).unwrap(), direct);
+        assert_eq!(pager.file_reads(), reads);
+    }
+
+    fn count_checksummed(edit: fn(&mut Vec<u8>)) -> Result<usize> {
+        let pager =
+            open_doctored_fixture_with("checksum.db", edit, |path| Pager::open_with(path, true));
+        let sql = "CREATE TABLE notes(id integer primary key, body text)";
+        Table::new(pager, 2, "notes", sql)?.len()
+    }
+
+    #[test]
+    fn test_verify_checksums() {
+        assert_eq!(count_checksummed(|_| {}).unwrap(), 200);
+
+        // Change a byte of one of the rows on page 3.
+        match count_checksummed(|bytes| bytes[3 * 1024 - 100] ^= 1) {
+            Err(Error(ErrorKind::ChecksumMismatch(page), _)) => assert_eq!(page, 3),
+            other => panic!("Expected ChecksumMismatch, got {:?}", other),
+        }
+
+        // Only opening with verification notices.
+        let pager = Pager::open("fixtures/large.db").unwrap();
+        assert_eq!(pager.header.reserved_byes_per_page, 0);
+        assert!(Pager::open_with("fixtures/large.db", true).is_err());
+    }
+
+    #[test]
+    fn test_free_pages() {
+        let pager = Pager::open("fixtures/freelist.db").unwrap();
+        let mut pages = pager.free_pages().unwrap();
+        assert_eq!(pages.len(), 603);
+        pages.sort();
+        pages.dedup();
+        assert_eq!(pages.len(), 603);
+        assert!(Pager::open("fixtures/sales.db")
+            .unwrap()
+            .free_pages()
+            .unwrap()
+            .is_empty());
+
+        // A header that disagrees with the freelist, either way.
+        for &count in &[602, 604] {
+            let pager = open_doctored_fixture_with(
+                "freelist.db",
+                |bytes| BigEndian::write_u32(&mut bytes[36..], count),
+                |path| Pager::open(path),
+            );
+            assert!(pager.free_pages().is_err());
+        }
+        // A trunk that points back to the first.
+        let pager = open_doctored_fixture_with(
+            "freelist.db",
+            |bytes| {
+                let first = BigEndian::read_u32(&bytes[32..]) as usize;
+                let trunk = &mut bytes[(first - 1) * 1024..];
+                BigEndian::write_u32(trunk, first as u32);
+            },
+            |path| Pager::open(path),
+        );
+        assert!(pager.free_pages().is_err());
+    }
+
+    #[test]
+    fn test_open_strict() {
+        for fixture in &["large.db", "deep.db", "checksum.db"] {
+            Pager::open_strict(format!("fixtures/{}", fixture)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_ptrmap_pages() {
+        let pager = Pager::open("fixtures/large.db").unwrap();
+        assert_eq!(pager.header.largest_root_page, 0);
+        assert!(!pager.is_ptrmap_page(2));
+
+        let pager = Rc::new(Pager::open("fixtures/vacuum.db").unwrap());
+        assert_eq!(pager.header.largest_root_page, 4);
+        assert!(pager.header.incremental_vacuum);
+        let ptrmaps: Vec<_> = (1..=pager.header.num_pages)
+            .filter(|&page| pager.is_ptrmap_page(page))
+            .collect();
+        assert_eq!(ptrmaps, vec![2, 207]);
+
+        // Every other page belongs to the schema or one of the tables.
+        let mut pages = vec![1];
+        pages.extend(ptrmaps);
+        let logs = "CREATE TABLE logs(id integer primary key, line text)";
+        pages.extend(
+            Table::new(pager.clone(), 3, "logs", logs)
+                .unwrap()
+                .pages()
+                .unwrap(),
+        );
+        let levels = "CREATE TABLE levels(id integer primary key, name text)";
+        pages.extend(
+            Table::new(pager.clone(), 4, "levels", levels)
+                .unwrap()
+                .pages()
+                .unwrap(),
+        );
+        pages.sort();
+        assert_eq!(pages, (1..=pager.header.num_pages).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_interrupt_scan() {
+        let pager = Rc::new(Pager::open("fixtures/large.db").unwrap());
+        let flag = Arc::new(AtomicBool::new(false));
+        pager.set_interrupt(flag.clone());
+        let sql = "CREATE TABLE numbers(id integer primary key, value int, label text)";
+        let table = Table::new(pager.clone(), 2, "numbers", sql).unwrap();
+
+        let mut rows = table.select_iter(vec!["id"]).unwrap();
+        for _ in 0..1000 {
+            rows.next().unwrap().unwrap();
+        }
+        flag.store(true, Ordering::Relaxed);
+        let pages_read = pager.pages_read();
+
+        // The rest of the current leaf can still be read, but no more. Each
+        // leaf holds around two hundred rows.
+        let mut read = 0;
+        loop {
+            match rows.next() {
+                Some(Ok(_)) => read += 1,
+                Some(Err(Error(ErrorKind::Interrupted, _))) => break,
+                other => panic!("Expected Interrupted, got {:?}", other),
+            }
+        }
+        assert!(read < 250, "read {} rows after interrupting", read);
+        assert!(rows.next().is_none());
+        assert_eq!(pager.pages_read(), pages_read);
+    }
+
+    #[test]
+    fn test_create_empty() {
+        for &page_size in &[512, 4096, 65536] {
+            let pager = Rc::new(Pager::create_empty(page_size).unwrap());
+            assert_eq!(pager.header.page_size, page_size);
+            assert_eq!(pager.header.num_pages, 1);
+            DbHeader::validate(&pager.get_page(1).unwrap()[..100]).unwrap();
+            let schema = Schema::new(pager).unwrap();
+            assert!(schema.tables().unwrap().is_empty());
+            assert!(schema.indices().unwrap().is_empty());
+        }
+        assert!(Pager::create_empty(1000).is_err());
+        assert!(Pager::create_empty(256).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let data = std::fs::read("fixtures/sales.db").unwrap();
+        let schema = Schema::new(Rc::new(Pager::from_bytes(data).unwrap())).unwrap();
+        assert_eq!(schema.table("sales").unwrap().len().unwrap(), 11);
+    }
+
+    #[test]
+    fn test_open_compressed() {
+        let pager = Rc::new(Pager::open("fixtures/large.db.gz").unwrap());
+        let schema = Schema::new(pager).unwrap();
+        assert_eq!(schema.table("numbers").unwrap().len().unwrap(), 5000);
+        Pager::open_strict("fixtures/large.db.gz").unwrap();
+
+        let data = std::fs::read("fixtures/large.db.gz").unwrap();
+        let schema = Schema::new(Rc::new(Pager::from_bytes(data).unwrap())).unwrap();
+        assert_eq!(schema.table("numbers").unwrap().len().unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_open_zstd() {
+        // A zstd frame is rejected for what it is, rather than as a database
+        // with a bad header.
+        let frame = vec![0x28, 0xb5, 0x2f, 0xfd, 0x24, 0x00, 0x01, 0x00, 0x00];
+        let path = std::env::temp_dir().join(format!("rqlite-{}-zstd.db", std::process::id()));
+        std::fs::write(&path, &frame).unwrap();
+        let opened = Pager::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        let check = |result: Result<Pager>| match result {
+            Err(Error(ErrorKind::Unsupported(feature), _)) => {
+                assert_eq!(feature, "zstd-compressed databases")
+            }
+            other => panic!("Expected Unsupported, got {:?}", other.map(|_| ())),
+        };
+        check(opened);
+        check(Pager::from_bytes(frame));
+    }
+
+    #[test]
+    fn test_wal_without_shm() {
+        assert!(!std::path::Path::new("fixtures/wal.db-shm").exists());
+        let pager = Rc::new(Pager::open("fixtures/wal.db").unwrap());
+        let schema = Schema::new(pager).unwrap();
+        let table = schema.table("events").unwrap();
+        let rows = table.select(vec!["id", "name"]).unwrap();
+        assert_eq!(rows.len(), 500);
+        assert_eq!(rows[0]["name"].as_text().unwrap(), "changed");
+        assert_eq!(rows[10]["name"].as_text().unwrap(), "event 11");
+
+        // Everything but the header is still only in the log.
+        let data = std::fs::read("fixtures/wal.db").unwrap();
+        let schema = Schema::new(Rc::new(Pager::from_bytes(data).unwrap())).unwrap();
+        assert!(schema.table("events").is_err());
+    }
+
+    #[test]
+    fn test_rust_log() {
+        use super::logs_debug;
+
+        assert!(logs_debug("rqlite=debug"));
+        assert!(logs_debug("trace"));
+        assert!(logs_debug("warn,rqlite::pager=TRACE"));
+        assert!(logs_debug("rqlite"));
+        assert!(!logs_debug("rqlite=info"));
+        assert!(!logs_debug("other=debug"));
+        assert!(!logs_debug(""));
     }
 }
@@ -0,0 +1,1232 @@
+use crate::aggregate::{Aggregate, AggregateFunction};
+use crate::errors::*;
+use crate::expr::{ArithOp, Collation, CompareOp, Expr, InList};
+use crate::function::Function;
+use crate::query::{GroupBy, Operation, OrderBy, Parameter, Pragma, ProjItem, SelectOp, Union};
+use crate::record::{Field, LiteralValue};
+use crate::types::Type;
+use crate::value::TextDecoding;
+
+// nom_sql's SELECT support doesn't stretch to the expressions SQLite allows
+// (`||`, placeholders other than `?`, ...), so we parse queries ourselves.
+// nom_sql is still used for CREATE TABLE.
+
+// Words that can't be used as identifiers or aliases without quoting them.
+const RESERVED: &[&str] = &[
+    "ALL",
+    "AND",
+    "AS",
+    "ASC",
+    "BETWEEN",
+    "BY",
+    "CASE",
+    "CAST",
+    "COLLATE",
+    "DESC",
+    "DISTINCT",
+    "ELSE",
+    "END",
+    "ESCAPE",
+    "EXCEPT",
+    "EXISTS",
+    "FROM",
+    "GLOB",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "INTERSECT",
+    "IS",
+    "JOIN",
+    "LIKE",
+    "LIMIT",
+    "MATCH",
+    "NOT",
+    "NULL",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "REGEXP",
+    "SELECT",
+    "THEN",
+    "UNION",
+    "WHEN",
+    "WHERE",
+];
+
+// The first words of statements that would change the database, which we
+// can't do.
+const WRITES: &[&str] = &[
+    "ALTER", "CREATE", "DELETE", "DROP", "INSERT", "REINDEX", "REPLACE", "UPDATE",
+];
+
+// Operators, longest first so that `<=` isn't read as `<` then `=`.
+const OPERATORS: &[&str] = &[
+    "||", "<=", ">=", "<>", "!=", "==", "<<", ">>", "(", ")", ",", ".", "*", "+", "-", "/", "%",
+    "=", "<", ">", ";", "&", "|", "~",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A bare identifier or keyword.
+    Word(String),
+    /// An identifier quoted with "", `` or [].
+    Quoted(String),
+    String(String),
+    Blob(Vec<u8>),
+    Integer(i64),
+    Float(f64),
+    Parameter(Parameter),
+    Operator(&'static str),
+}
+
+#[derive(Debug)]
+struct Spanned {
+    token: Token,
+    start: usize,
+    end: usize,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+// Reads a quoted string starting at `start`, where a doubled `close` stands
+// for itself. Returns the contents and the offset just past the closing quote.
+fn quoted(sql: &str, start: usize, close: char) -> Result<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = sql[start + 1..].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == close {
+            if chars.peek().map(|&(_, c)| c) == Some(close) {
+                chars.next();
+            } else {
+                return Ok((value, start + 1 + i + c.len_utf8()));
+            }
+        }
+        value.push(c);
+    }
+    bail!(ErrorKind::ParseError(format!(
+        "Unterminated quote at offset {}",
+        start
+    )))
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Spanned>> {
+    let mut tokens = Vec::new();
+    // "?" on its own is one more than the largest parameter number so far.
+    let mut next_positional = 0;
+    let mut pos = 0;
+
+    while let Some(c) = sql[pos..].chars().next() {
+        let rest = &sql[pos..];
+        let start = pos;
+        let token = if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        } else if rest.starts_with("--") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        } else if rest.starts_with("/*") {
+            pos += rest.find("*/").map_or(rest.len(), |end| end + 2);
+            continue;
+        } else if c == '\'' {
+            let (value, end) = quoted(sql, pos, '\'')?;
+            pos = end;
+            Token::String(value)
+        } else if c == '"' || c == '`' {
+            let (value, end) = quoted(sql, pos, c)?;
+            pos = end;
+            Token::Quoted(value)
+        } else if c == '[' {
+            let end = rest
+                .find(']')
+                .ok_or_else(|| format!("Unterminated quote at offset {}", pos))?;
+            pos += end + 1;
+            Token::Quoted(rest[1..end].to_owned())
+        } else if (c == 'x' || c == 'X') && rest[1..].starts_with('\'') {
+            let (hex, end) = quoted(sql, pos + 1, '\'')?;
+            pos = end;
+            if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!(ErrorKind::ParseError(format!(
+                    "Malformed blob literal: x'{}'",
+                    hex
+                )));
+            }
+            let blob = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect();
+            Token::Blob(blob)
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let mut end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            if rest[end..].starts_with(['e', 'E']) {
+                let exponent = rest[end + 1..].trim_start_matches(['+', '-']);
+                let digits = exponent
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(exponent.len());
+                end = rest.len() - exponent.len() + digits;
+            }
+            let number = &rest[..end];
+            pos += end;
+            match number.parse::<i64>() {
+                Ok(i) => Token::Integer(i),
+                // Integers too big for 64 bits become reals, as in SQLite.
+                Err(_) => Token::Float(
+                    number
+                        .parse()
+                        .map_err(|_| format!("Malformed number: {}", number))?,
+                ),
+            }
+        } else if c == '?' {
+            let digits = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - 1);
+            pos += 1 + digits;
+            let n = if digits == 0 {
+                next_positional
+            } else {
+                match rest[1..1 + digits].parse::<usize>() {
+                    Ok(n) if n >= 1 => n - 1,
+                    _ => bail!(ErrorKind::ParseError(format!(
+                        "Invalid parameter: {}",
+                        &rest[..1 + digits]
+                    ))),
+                }
+            };
+            next_positional = next_positional.max(n + 1);
+            Token::Parameter(Parameter::Positional(n))
+        } else if (c == ':' || c == '@' || c == '$') && rest[1..].starts_with(is_ident_char) {
+            let len = rest[1..]
+                .find(|c| !is_ident_char(c))
+                .unwrap_or(rest.len() - 1);
+            pos += 1 + len;
+            Token::Parameter(Parameter::Named(rest[..1 + len].to_owned()))
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len());
+            pos += len;
+            Token::Word(rest[..len].to_owned())
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    pos += op.len();
+                    Token::Operator(op)
+                }
+                None => bail!(ErrorKind::ParseError(format!(
+                    "Unrecognised token near \"{}\"",
+                    rest
+                ))),
+            }
+        };
+        tokens.push(Spanned {
+            token,
+            start,
+            end: pos,
+        });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    sql: &'a str,
+    tokens: Vec<Spanned>,
+    pos: usize,
+    // Each placeholder in the order they appear. `Expr::Placeholder(n)`
+    // refers to the nth.
+    parameters: Vec<Parameter>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(sql: &'a str) -> Result<Parser<'a>> {
+        Ok(Parser {
+            sql,
+            tokens: tokenize(sql)?,
+            pos: 0,
+            parameters: Vec::new(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|t| &t.token)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|t| t.token.clone());
+        self.pos += 1;
+        token
+    }
+
+    // Where the next token starts, or the end of the input if there isn't one.
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.sql.len(), |t| t.start)
+    }
+
+    // Where the last token we consumed ended.
+    fn last_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .and_then(|pos| self.tokens.get(pos))
+            .map_or(0, |t| t.end)
+    }
+
+    fn syntax_error<T>(&self) -> Result<T> {
+        match self.tokens.get(self.pos) {
+            Some(t) => bail!(ErrorKind::ParseError(format!(
+                "Syntax error near \"{}\"",
+                &self.sql[t.start..]
+            ))),
+            None => bail!(ErrorKind::ParseError(
+                "Syntax error: unexpected end of statement".to_owned()
+            )),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => word.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    // Whether the next tokens are `NOT keyword`.
+    fn is_negated_keyword(&self, keyword: &str) -> bool {
+        self.is_keyword("NOT")
+            && match self.peek_at(1) {
+                Some(Token::Word(word)) => word.eq_ignore_ascii_case(keyword),
+                _ => false,
+            }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if !self.eat_keyword(keyword) {
+            return self.syntax_error();
+        }
+        Ok(())
+    }
+
+    fn is_operator(&self, op: &str) -> bool {
+        match self.peek() {
+            Some(Token::Operator(found)) => *found == op || (op == "=" && *found == "=="),
+            _ => false,
+        }
+    }
+
+    fn eat_operator(&mut self, op: &str) -> bool {
+        let found = self.is_operator(op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_operator(&mut self, op: &str) -> Result<()> {
+        if !self.eat_operator(op) {
+            return self.syntax_error();
+        }
+        Ok(())
+    }
+
+    // Whether the next token can be read as an identifier.
+    fn at_identifier(&self) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => !RESERVED.iter().any(|r| word.eq_ignore_ascii_case(r)),
+            Some(Token::Quoted(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        if !self.at_identifier() {
+            return self.syntax_error();
+        }
+        match self.advance() {
+            Some(Token::Word(name)) | Some(Token::Quoted(name)) => Ok(name),
+            _ => unreachable!(),
+        }
+    }
+
+    // `PRAGMA [schema.]name(argument)` or `PRAGMA [schema.]name = argument`.
+    fn pragma(&mut self) -> Result<Pragma> {
+        self.expect_keyword("PRAGMA")?;
+        let mut name = self.identifier()?;
+        if self.eat_operator(".") {
+            name = self.identifier()?;
+        }
+        let argument = if self.eat_operator("(") {
+            let argument = self.pragma_argument()?;
+            self.expect_operator(")")?;
+            Some(argument)
+        } else if self.eat_operator("=") {
+            Some(self.pragma_argument()?)
+        } else {
+            None
+        };
+
+        self.eat_operator(";");
+        if self.peek().is_some() {
+            return self.syntax_error();
+        }
+
+        match (name.to_lowercase().as_str(), argument) {
+            ("table_info", Some(table)) => Ok(Pragma::TableInfo(table)),
+            ("index_list", Some(table)) => Ok(Pragma::IndexList(table)),
+            ("index_info", Some(index)) => Ok(Pragma::IndexInfo(index)),
+            _ => bail!(ErrorKind::Unsupported(format!("PRAGMA {}", name))),
+        }
+    }
+
+    fn pragma_argument(&mut self) -> Result<String> {
+        if let Some(Token::String(_)) = self.peek() {
+            match self.advance() {
+                Some(Token::String(s)) => return Ok(s),
+                _ => unreachable!(),
+            }
+        }
+        self.identifier()
+    }
+
+    // `CREATE [TEMP] VIEW [IF NOT EXISTS] name AS SELECT ...`, as a view is
+    // stored in sqlite_master.
+    fn create_view(&mut self) -> Result<SelectOp> {
+        self.expect_keyword("CREATE")?;
+        if !self.eat_keyword("TEMP") {
+            self.eat_keyword("TEMPORARY");
+        }
+        self.expect_keyword("VIEW")?;
+        if self.eat_keyword("IF") {
+            self.expect_keyword("NOT")?;
+            self.expect_keyword("EXISTS")?;
+        }
+        self.identifier()?;
+        if self.eat_operator(".") {
+            self.identifier()?;
+        }
+        if self.is_operator("(") {
+            bail!(ErrorKind::Unsupported("views with column names".to_owned()));
+        }
+        self.expect_keyword("AS")?;
+        self.select()
+    }
+
+    // A whole statement's SELECT.
+    fn select(&mut self) -> Result<SelectOp> {
+        let select = self.compound_select()?;
+        self.eat_operator(";");
+        if self.peek().is_some() {
+            return self.syntax_error();
+        }
+        Ok(select)
+    }
+
+    // A SELECT, which may be compounded with others using UNION. Any ORDER BY
+    // and LIMIT apply to the results of the whole compound.
+    fn compound_select(&mut self) -> Result<SelectOp> {
+        let mut select = self.select_core()?;
+        while self.eat_keyword("UNION") {
+            let all = self.eat_keyword("ALL");
+            let arm = self.select_core()?;
+            if arm.columns.len() != select.columns.len() {
+                bail!("SELECTs to the left and right of UNION do not have the same number of result columns");
+            }
+            select.unions.push(Union { all, select: arm });
+        }
+        if self.is_keyword("INTERSECT") || self.is_keyword("EXCEPT") {
+            bail!(ErrorKind::Unsupported("INTERSECT and EXCEPT".to_owned()));
+        }
+
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let column = self.column_name("ORDER BY")?;
+                let collation = if self.eat_keyword("COLLATE") {
+                    Some(Collation::from_name(&self.identifier()?)?)
+                } else {
+                    None
+                };
+                let descending = self.eat_keyword("DESC");
+                if !descending {
+                    self.eat_keyword("ASC");
+                }
+                order_by.push(OrderBy {
+                    column,
+                    descending,
+                    collation,
+                });
+                if !self.eat_operator(",") {
+                    break;
+                }
+            }
+        }
+
+        let (mut limit, mut offset) = (None, 0);
+        if self.eat_keyword("LIMIT") {
+            let first = self.integer("LIMIT")?;
+            if self.eat_keyword("OFFSET") {
+                limit = Some(first);
+                offset = self.integer("OFFSET")?;
+            } else if self.eat_operator(",") {
+                // `LIMIT <offset>, <limit>`
+                offset = first;
+                limit = Some(self.integer("LIMIT")?);
+            } else {
+                limit = Some(first);
+            }
+        }
+
+        select.order_by = order_by;
+        select.limit = limit;
+        select.offset = offset;
+        Ok(select)
+    }
+
+    // A SELECT up to its HAVING clause.
+    fn select_core(&mut self) -> Result<SelectOp> {
+        match self.peek() {
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("SELECT") => {}
+            Some(Token::Word(ref word)) => {
+                let statement = word.to_ascii_uppercase();
+                if WRITES.contains(&statement.as_str()) {
+                    bail!(ErrorKind::ReadOnly(statement));
+                }
+                bail!(ErrorKind::UnsupportedStatement(statement))
+            }
+            _ => return self.syntax_error(),
+        }
+        self.pos += 1;
+
+        let distinct = self.eat_keyword("DISTINCT");
+        if !distinct {
+            self.eat_keyword("ALL");
+        }
+
+        let mut columns = vec![self.result_column()?];
+        while self.eat_operator(",") {
+            columns.push(self.result_column()?);
+        }
+
+        self.expect_keyword("FROM")?;
+        let table = self.identifier()?;
+        // A table alias doesn't mean anything when there's only one table.
+        if self.eat_keyword("AS") || self.at_identifier() {
+            self.identifier()?;
+        }
+        if self.is_operator(",") || self.is_keyword("JOIN") {
+            bail!(ErrorKind::Unsupported("joins".to_owned()));
+        }
+
+        let where_clause = if self.eat_keyword("WHERE") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+
+        let group_by = if self.eat_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            let mut columns = vec![self.column_name("GROUP BY")?];
+            while self.eat_operator(",") {
+                columns.push(self.column_name("GROUP BY")?);
+            }
+            let having = if self.eat_keyword("HAVING") {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            Some(GroupBy { columns, having })
+        } else {
+            None
+        };
+
+        Ok(SelectOp {
+            table,
+            distinct,
+            columns,
+            where_clause,
+            group_by,
+            order_by: Vec::new(),
+            limit: None,
+            offset: 0,
+            unions: Vec::new(),
+            text_decoding: TextDecoding::Strict,
+        })
+    }
+
+    fn result_column(&mut self) -> Result<ProjItem> {
+        if self.is_operator("*")
+            || (self.peek_at(1) == Some(&Token::Operator("."))
+                && self.peek_at(2) == Some(&Token::Operator("*")))
+        {
+            bail!(ErrorKind::Unsupported("* in SELECT".to_owned()));
+        }
+
+        let start = self.offset();
+        let expr = self.expr()?;
+        let text = self.sql[start..self.last_end()].to_owned();
+
+        let alias = if self.eat_keyword("AS") {
+            match self.peek() {
+                Some(Token::String(_)) => match self.advance() {
+                    Some(Token::String(alias)) => Some(alias),
+                    _ => unreachable!(),
+                },
+                _ => Some(self.identifier()?),
+            }
+        } else if self.at_identifier() {
+            Some(self.identifier()?)
+        } else {
+            None
+        };
+
+        let name = match (alias, &expr) {
+            (Some(alias), _) => alias,
+            (None, Expr::Column(name)) => name.clone(),
+            (None, Expr::Aggregate(aggregate)) => aggregate.name(),
+            (None, _) => text,
+        };
+        Ok(ProjItem { name, expr })
+    }
+
+    fn column_name(&mut self, clause: &str) -> Result<String> {
+        match self.expr()? {
+            Expr::Column(name) => Ok(name),
+            _ => bail!(ErrorKind::Unsupported(format!("expressions in {}", clause))),
+        }
+    }
+
+    fn integer(&mut self, clause: &str) -> Result<usize> {
+        match self.advance() {
+            Some(Token::Integer(i)) if i >= 0 => Ok(i as usize),
+            _ => bail!(ErrorKind::Unsupported(format!(
+                "{} other than a non-negative integer",
+                clause
+            ))),
+        }
+    }
+
+    // Expressions are parsed by precedence, lowest first:
+    //   OR
+    //   AND
+    //   NOT
+    //   = == != <> IS [NOT]
+    //   < <= > >=
+    //   ||
+    //   unary - +
+    fn expr(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.eat_keyword("OR") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.eat_keyword("AND") {
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.equality()
+    }
+
+    fn equality(&mut self) -> Result<Expr> {
+        let mut left = self.comparison()?;
+        loop {
+            let op = if self.eat_operator("=") {
+                CompareOp::Equal
+            } else if self.eat_operator("!=") || self.eat_operator("<>") {
+                CompareOp::NotEqual
+            } else if self.eat_keyword("IS") {
+                if self.eat_keyword("NOT") {
+                    CompareOp::IsNot
+                } else {
+                    CompareOp::Is
+                }
+            } else if self.is_keyword("LIKE") || self.is_negated_keyword("LIKE") {
+                let negated = self.eat_keyword("NOT");
+                self.expect_keyword("LIKE")?;
+                let pattern = Box::new(self.comparison()?);
+                let escape = if self.eat_keyword("ESCAPE") {
+                    Some(Box::new(self.comparison()?))
+                } else {
+                    None
+                };
+                left = Expr::Like(Box::new(left), pattern, escape);
+                if negated {
+                    left = Expr::Not(Box::new(left));
+                }
+                continue;
+            } else if self.is_keyword("IN") || self.is_negated_keyword("IN") {
+                let negated = self.eat_keyword("NOT");
+                self.expect_keyword("IN")?;
+                left = Expr::In(Box::new(left), self.in_list()?);
+                if negated {
+                    left = Expr::Not(Box::new(left));
+                }
+                continue;
+            } else if self.is_keyword("BETWEEN") || self.is_negated_keyword("BETWEEN") {
+                let negated = self.eat_keyword("NOT");
+                self.expect_keyword("BETWEEN")?;
+                let low = self.comparison()?;
+                self.expect_keyword("AND")?;
+                let high = self.comparison()?;
+                // `x BETWEEN a AND b` is `x >= a AND x <= b`, which is also
+                // what the planner knows how to turn into a range.
+                left = Expr::And(
+                    Box::new(Expr::Compare(
+                        CompareOp::GreaterOrEqual,
+                        Box::new(left.clone()),
+                        Box::new(low),
+                    )),
+                    Box::new(Expr::Compare(
+                        CompareOp::LessOrEqual,
+                        Box::new(left),
+                        Box::new(high),
+                    )),
+                );
+                if negated {
+                    left = Expr::Not(Box::new(left));
+                }
+                continue;
+            } else {
+                for &keyword in &["GLOB", "MATCH", "REGEXP"] {
+                    if self.is_keyword(keyword) || self.is_negated_keyword(keyword) {
+                        bail!(ErrorKind::Unsupported(format!(
+                            "{} in expressions",
+                            keyword
+                        )));
+                    }
+                }
+                return Ok(left);
+            };
+            left = Expr::Compare(op, Box::new(left), Box::new(self.comparison()?));
+        }
+    }
+
+    // The right of IN: a list of expressions, or a SELECT of one column, in
+    // parentheses.
+    fn in_list(&mut self) -> Result<InList> {
+        self.expect_operator("(")?;
+        let list = if self.is_keyword("SELECT") {
+            let select = self.compound_select()?;
+            if select.columns.len() != 1 {
+                bail!(
+                    "Sub-select returns {} columns - expected 1",
+                    select.columns.len()
+                );
+            }
+            InList::Select(Box::new(select))
+        } else {
+            let mut exprs = Vec::new();
+            if !self.is_operator(")") {
+                loop {
+                    exprs.push(self.expr()?);
+                    if !self.eat_operator(",") {
+                        break;
+                    }
+                }
+            }
+            InList::Exprs(exprs)
+        };
+        self.expect_operator(")")?;
+        Ok(list)
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let mut left = self.arithmetic()?;
+        loop {
+            let op = if self.eat_operator("<=") {
+                CompareOp::LessOrEqual
+            } else if self.eat_operator(">=") {
+                CompareOp::GreaterOrEqual
+            } else if self.eat_operator("<") {
+                CompareOp::Less
+            } else if self.eat_operator(">") {
+                CompareOp::Greater
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Compare(op, Box::new(left), Box::new(self.arithmetic()?));
+        }
+    }
+
+    fn arithmetic(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        for &op in &["&", "|", "<<", ">>"] {
+            if self.is_operator(op) {
+                bail!(ErrorKind::Unsupported(format!("operator {}", op)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = if self.eat_operator("+") {
+                ArithOp::Add
+            } else if self.eat_operator("-") {
+                ArithOp::Subtract
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Arithmetic(op, Box::new(left), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut left = self.concat()?;
+        loop {
+            let op = if self.eat_operator("*") {
+                ArithOp::Multiply
+            } else if self.eat_operator("/") {
+                ArithOp::Divide
+            } else if self.eat_operator("%") {
+                ArithOp::Remainder
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Arithmetic(op, Box::new(left), Box::new(self.concat()?));
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        while self.eat_operator("||") {
+            left = Expr::Concat(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_operator("+") {
+            return self.unary();
+        }
+        if self.eat_operator("-") {
+            return match self.peek().cloned() {
+                // XXX Field doesn't know integers are signed yet.
+                Some(Token::Integer(i)) => {
+                    self.pos += 1;
+                    Ok(Expr::Literal(Field::from(i.wrapping_neg() as u64)))
+                }
+                Some(Token::Float(f)) => {
+                    self.pos += 1;
+                    Ok(Expr::Literal(Field::from(-f)))
+                }
+                _ => Ok(Expr::Arithmetic(
+                    ArithOp::Subtract,
+                    Box::new(Expr::Literal(Field::from(0))),
+                    Box::new(self.unary()?),
+                )),
+            };
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let literal = |value| Ok(Expr::Literal(Field::Literal(value)));
+        match self.peek().cloned() {
+            Some(Token::Integer(i)) => {
+                self.pos += 1;
+                // XXX Field doesn't know integers are signed yet.
+                Ok(Expr::Literal(Field::from(i as u64)))
+            }
+            Some(Token::Float(f)) => {
+                self.pos += 1;
+                literal(LiteralValue::Float(f))
+            }
+            Some(Token::String(s)) => {
+                self.pos += 1;
+                literal(LiteralValue::Str(s))
+            }
+            Some(Token::Blob(b)) => {
+                self.pos += 1;
+                literal(LiteralValue::Blob(b))
+            }
+            Some(Token::Parameter(param)) => {
+                self.pos += 1;
+                self.parameters.push(param);
+                Ok(Expr::Placeholder(self.parameters.len() - 1))
+            }
+            Some(Token::Operator("(")) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect_operator(")")?;
+                Ok(expr)
+            }
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("NULL") => {
+                self.pos += 1;
+                literal(LiteralValue::Null)
+            }
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("CAST") => {
+                self.pos += 1;
+                self.expect_operator("(")?;
+                let expr = self.expr()?;
+                self.expect_keyword("AS")?;
+                let ty = self.type_name()?;
+                self.expect_operator(")")?;
+                Ok(Expr::Cast(Box::new(expr), ty))
+            }
+            Some(Token::Word(_)) | Some(Token::Quoted(_)) => {
+                let name = self.identifier()?;
+                if self.eat_operator("(") {
+                    return self.call(&name);
+                }
+                // We only ever read from one table, so can ignore which
+                // table a column is qualified with.
+                if self.eat_operator(".") {
+                    return Ok(Expr::Column(self.identifier()?));
+                }
+                Ok(Expr::Column(name))
+            }
+            _ => self.syntax_error(),
+        }
+    }
+
+    // A type name, such as `INTEGER` or `VARCHAR(10)`, as the type of the
+    // affinity it has.
+    fn type_name(&mut self) -> Result<Type> {
+        let mut words = Vec::new();
+        while let Some(Token::Word(word)) = self.peek().cloned() {
+            self.pos += 1;
+            words.push(word);
+        }
+        if words.is_empty() {
+            return self.syntax_error();
+        }
+        // Any sizes are ignored, as they are by SQLite.
+        if self.eat_operator("(") {
+            while !self.eat_operator(")") {
+                if self.advance().is_none() {
+                    return self.syntax_error();
+                }
+            }
+        }
+        let name = words.join(" ");
+        affinity(&name).ok_or_else(|| ErrorKind::Unsupported(format!("CAST AS {}", name)).into())
+    }
+
+    // A function call, after the opening bracket.
+    fn call(&mut self, name: &str) -> Result<Expr> {
+        if let Some(function) = Function::from_name(name) {
+            let mut args = vec![];
+            if !self.eat_operator(")") {
+                loop {
+                    args.push(self.expr()?);
+                    if !self.eat_operator(",") {
+                        break;
+                    }
+                }
+                self.expect_operator(")")?;
+            }
+            function.check_arguments(args.len())?;
+            return Ok(Expr::Function(function, args));
+        }
+        let function = AggregateFunction::from_name(name)
+            .ok_or_else(|| ErrorKind::Unsupported(format!("function {}()", name)))?;
+
+        if function == AggregateFunction::Count && self.eat_operator("*") {
+            self.expect_operator(")")?;
+            return Ok(Expr::Aggregate(Aggregate::new(
+                AggregateFunction::CountStar,
+                None,
+                false,
+            )));
+        }
+        let distinct = self.eat_keyword("DISTINCT");
+        let column = match self.expr()? {
+            Expr::Column(column) => column,
+            _ => bail!(ErrorKind::Unsupported(
+                "aggregates of expressions".to_owned()
+            )),
+        };
+        self.expect_operator(")")?;
+        Ok(Expr::Aggregate(Aggregate::new(
+            function,
+            Some(column),
+            distinct,
+        )))
+    }
+}
+
+// The affinity of a column declared with the type `name`, by the rules in
+// section 3.1 of https://www.sqlite.org/datatype3.html, or None for NUMERIC.
+pub fn affinity(name: &str) -> Option<Type> {
+    let name = name.to_ascii_uppercase();
+    if name.contains("INT") {
+        Some(Type::Integer)
+    } else if name.contains("CHAR") || name.contains("CLOB") || name.contains("TEXT") {
+        Some(Type::Text)
+    } else if name.contains("BLOB") {
+        Some(Type::Blob)
+    } else if name.contains("REAL") || name.contains("FLOA") || name.contains("DOUB") {
+        Some(Type::Float)
+    } else {
+        None
+    }
+}
+
+/// Splits input holding any number of `;`-separated statements into the text
+/// of each one, leaving out comments and empty statements.
+pub fn split_statements(sql: &str) -> Result<Vec<&str>> {
+    let mut statements = Vec::new();
+    let mut span: Option<(usize, usize)> = None;
+    for spanned in tokenize(sql)? {
+        if spanned.token == Token::Operator(";") {
+            statements.extend(span.take().map(|(start, end)| &sql[start..end]));
+        } else {
+            let start = span.map_or(spanned.start, |(start, _)| start);
+            span = Some((start, spanned.end));
+        }
+    }
+    statements.extend(span.map(|(start, end)| &sql[start..end]));
+    Ok(statements)
+}
+
+// Whether `sql` ends with a `;` outside of any quote or comment, so that
+// it holds only whole statements.
+fn is_complete(sql: &str) -> bool {
+    let mut complete = false;
+    let mut pos = 0;
+    while let Some(c) = sql[pos..].chars().next() {
+        let rest = &sql[pos..];
+        let end = match c {
+            '\'' | '"' | '`' => rest[1..].find(c).map(|end| end + 2),
+            '[' => rest.find(']').map(|end| end + 1),
+            '-' if rest.starts_with("--") => Some(rest.find('\n').unwrap_or(rest.len())),
+            '/' if rest.starts_with("/*") => rest.find("*/").map(|end| end + 2),
+            _ => Some(c.len_utf8()),
+        };
+        match end {
+            Some(end) => pos += end,
+            None => return false,
+        }
+        if c == ';' {
+            complete = true;
+        } else if !c.is_whitespace() && !rest.starts_with("--") && !rest.starts_with("/*") {
+            complete = false;
+        }
+    }
+    complete
+}
+
+/// Collects lines of input until they make up whole statements, so that a
+/// statement can be split across lines.
+#[derive(Debug, Default)]
+pub struct StatementBuffer {
+    sql: String,
+}
+
+impl StatementBuffer {
+    pub fn new() -> StatementBuffer {
+        StatementBuffer::default()
+    }
+
+    /// Whether no part of a statement is waiting for more lines.
+    pub fn is_empty(&self) -> bool {
+        self.sql.trim().is_empty()
+    }
+
+    /// Adds a line, returning the buffered input once it ends with a `;`.
+    pub fn push_line(&mut self, line: &str) -> Option<String> {
+        self.sql.push_str(line);
+        if !self.sql.ends_with('\n') {
+            self.sql.push('\n');
+        }
+        if self.is_empty() {
+            self.sql.clear();
+            None
+        } else if is_complete(&self.sql) {
+            Some(std::mem::take(&mut self.sql))
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a statement: a SELECT or a PRAGMA.
+pub fn parse_statement(sql: &str) -> Result<(Operation, Vec<Parameter>)> {
+    let mut parser = Parser::new(sql)?;
+    let operation = if parser.is_keyword("PRAGMA") {
+        Operation::Pragma(parser.pragma()?)
+    } else {
+        Operation::Select(Box::new(parser.select()?))
+    };
+    Ok((operation, parser.parameters))
+}
+
+/// Parses the CREATE VIEW statement of a view, returning its SELECT.
+pub fn parse_view(sql: &str) -> Result<SelectOp> {
+    let mut parser = Parser::new(sql)?;
+    let select = parser.create_view()?;
+    if !parser.parameters.is_empty() {
+        bail!("Parameters in view: {}", sql);
+    }
+    Ok(select)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_select(sql: &str) -> Result<(SelectOp, Vec<Parameter>)> {
+        match parse_statement(sql)? {
+            (Operation::Select(select), parameters) => Ok((*select, parameters)),
+            (other, _) => panic!("Expected a SELECT, got {:?}", other),
+        }
+    }
+
+    fn tokens(sql: &str) -> Vec<Token> {
+        tokenize(sql)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokens("SELECT \"a b\", 'it''s', x'0aff', 1.5e3, 12 -- comment\nFROM [t]"),
+            vec![
+                Token::Word("SELECT".to_owned()),
+                Token::Quoted("a b".to_owned()),
+                Token::Operator(","),
+                Token::String("it's".to_owned()),
+                Token::Operator(","),
+                Token::Blob(vec![0x0a, 0xff]),
+                Token::Operator(","),
+                Token::Float(1500.0),
+                Token::Operator(","),
+                Token::Integer(12),
+                Token::Word("FROM".to_owned()),
+                Token::Quoted("t".to_owned()),
+            ]
+        );
+        assert_eq!(
+            tokens("a<=b||c"),
+            vec![
+                Token::Word("a".to_owned()),
+                Token::Operator("<="),
+                Token::Word("b".to_owned()),
+                Token::Operator("||"),
+                Token::Word("c".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert!(split_statements("-- just a comment\n").unwrap().is_empty());
+        assert_eq!(
+            split_statements("SELECT a FROM t; SELECT ';' /* ; */ FROM u -- ;\n;;").unwrap(),
+            vec!["SELECT a FROM t", "SELECT ';' /* ; */ FROM u"]
+        );
+        assert_eq!(
+            split_statements("SELECT 1 FROM t\n").unwrap(),
+            vec!["SELECT 1 FROM t"]
+        );
+        assert!(split_statements("SELECT 'a;").is_err());
+    }
+
+    #[test]
+    fn test_statement_buffer() {
+        let mut buffer = StatementBuffer::new();
+        assert_eq!(buffer.push_line("SELECT a\n"), None);
+        assert!(!buffer.is_empty());
+        assert_eq!(buffer.push_line("FROM t -- not done;\n"), None);
+        assert_eq!(buffer.push_line("WHERE b = 'x;\n"), None);
+        assert_eq!(
+            buffer.push_line("y'; /* done */\n"),
+            Some("SELECT a\nFROM t -- not done;\nWHERE b = 'x;\ny'; /* done */\n".to_owned())
+        );
+        assert!(buffer.is_empty());
+
+        assert_eq!(buffer.push_line("\n"), None);
+        assert!(buffer.is_empty());
+        assert_eq!(
+            buffer.push_line("SELECT 1 FROM t; SELECT 2 FROM t;"),
+            Some("SELECT 1 FROM t; SELECT 2 FROM t;\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parameters_ignore_quoted() {
+        let (_, params) =
+            parse_select("SELECT a FROM t WHERE b = ':x' AND c = @y AND d = ?3 AND e = ?").unwrap();
+        assert_eq!(
+            params,
+            vec![
+                Parameter::Named("@y".to_owned()),
+                Parameter::Positional(2),
+                Parameter::Positional(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let (select, _) = parse_select(
+            "SELECT DISTINCT region AS r, count(*), first || last FROM sales s \
+             WHERE amount > 10 AND NOT region IS NULL \
+             GROUP BY region HAVING count(*) > 1 ORDER BY r DESC, amount LIMIT 5 OFFSET 2;",
+        )
+        .unwrap();
+        assert_eq!(select.table, "sales");
+        assert!(select.distinct);
+        let names: Vec<_> = select.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["r", "count(*)", "first || last"]);
+        assert!(select.where_clause.is_some());
+        assert_eq!(select.group_by.unwrap().columns, vec!["region"]);
+        assert_eq!(select.order_by.len(), 2);
+        assert!(select.order_by[0].descending);
+        assert_eq!((select.limit, select.offset), (Some(5), 2));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(parse_select("SELECT a FROM").is_err());
+        assert!(parse_select("SELECT a FROM t WHERE").is_err());
+        assert!(parse_select("SELECT a FROM t junk junk").is_err());
+        match parse_select("SELECT a FROM t WHERE a = 'unterminated") {
+            Err(Error(ErrorKind::ParseError(_), _)) => {}
+            other => panic!("Expected ParseError, got {:?}", other.map(|_| ())),
+        }
+        match parse_select("SELECT a FROM t WHERE") {
+            Err(Error(ErrorKind::ParseError(ref message), _)) => {
+                assert_eq!(message, "Syntax error: unexpected end of statement")
+            }
+            other => panic!("Expected ParseError, got {:?}", other.map(|_| ())),
+        }
+        match parse_select("explain SELECT a FROM t") {
+            Err(Error(ErrorKind::UnsupportedStatement(ref statement), _)) => {
+                assert_eq!(statement, "EXPLAIN")
+            }
+            other => panic!("Expected UnsupportedStatement, got {:?}", other.map(|_| ())),
+        }
+    }
+}
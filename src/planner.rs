@@ -0,0 +1,634 @@
+use std::cmp::{Ordering, Reverse};
+
+use crate::errors::*;
+use crate::expr::{compare, Collation, CompareOp, Expr};
+use crate::index::{Bound, Index, IndexKind, IndexStats};
+use crate::query::SelectOp;
+use crate::record::Field;
+use crate::schema::Schema;
+use crate::source::{IndexEntries, IndexLookup, IndexScan, RowSource, TableRange, TableScan};
+use crate::table::Table;
+use crate::types::Type;
+use crate::value::TextDecoding;
+
+// What SQLite assumes about an index when ANALYZE hasn't been run.
+const DEFAULT_ROWS: u64 = 1_000_000;
+const DEFAULT_ROWS_PER_VALUE: u64 = 10;
+
+/// How a SELECT reads the rows of its table.
+#[derive(Debug)]
+pub enum Access {
+    FullScan,
+    /// Reads the rows whose values of the index's leading columns equal
+    /// `prefix`, and whose values of the column after those lie between
+    /// `lower` and `upper`, in index order. The WHERE clause must still be
+    /// applied to them, as it may say more than the bounds do.
+    IndexRange {
+        index: Box<Index>,
+        prefix: Vec<Field>,
+        lower: Option<Bound>,
+        upper: Option<Bound>,
+    },
+    /// Reads the rows whose rowids lie between `lower` and `upper`, including
+    /// both, straight from the table's b-tree, in rowid order. As with an
+    /// index range, the WHERE clause must still be applied to them.
+    RowidRange {
+        lower: Option<i64>,
+        upper: Option<i64>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Plan {
+    pub access: Access,
+    /// Whether the rows need sorting to satisfy ORDER BY, or whether they're
+    /// already read in the right order.
+    pub sort: bool,
+}
+
+impl Plan {
+    pub fn new(op: &SelectOp, schema: &Schema) -> Result<Plan> {
+        let conjuncts = match op.where_clause {
+            Some(ref expr) => expr.conjuncts(),
+            None => vec![],
+        };
+
+        // An index can serve the ORDER BY if one of its columns is the only
+        // thing being sorted on, in binary order, every column before it is fixed by an
+        // equality, and the rows aren't being grouped or added to by a UNION.
+        let ordered_by = match op.order_by.as_slice() {
+            [order]
+                if !order.descending
+                    && order.collation.unwrap_or(Collation::Binary) == Collation::Binary
+                    && !op.is_grouped()
+                    && op.unions.is_empty() =>
+            {
+                Some(order.column.as_str())
+            }
+            _ => None,
+        };
+        let ordered = |index: &Index, prefix: &[Field]| {
+            index
+                .columns()
+                .iter()
+                .take(prefix.len() + 1)
+                .any(|column| Some(column.as_str()) == ordered_by)
+        };
+
+        // The table's own b-tree is keyed by rowid, so a range of rowids can
+        // be read without an index. Views have no rowids.
+        let table = match schema.table(&op.table) {
+            Ok(table) => Some(table),
+            Err(Error(ErrorKind::TableDoesNotExist(_), _)) => None,
+            Err(e) => return Err(e),
+        };
+        let rowid_range = table
+            .as_ref()
+            .filter(|table| !table.without_rowid())
+            .and_then(|table| rowid_bounds(&conjuncts, table));
+
+        let mut candidates = Vec::new();
+        for index in schema.indices()? {
+            if index.tbl_name() != op.table || index.kind() != IndexKind::Rowid {
+                continue;
+            }
+            // Use equalities on as many of the index's leading columns as we
+            // can, then any bounds on the column after them.
+            let mut prefix = Vec::new();
+            let (mut lower, mut upper) = (None, None);
+            for column in index.columns() {
+                let (l, u) = bounds(&conjuncts, |name| name == column);
+                match (l, u) {
+                    (Some(l), Some(u)) if is_equality(&l, &u) => prefix.push(l.value),
+                    (l, u) => {
+                        lower = l;
+                        upper = u;
+                        break;
+                    }
+                }
+            }
+            if !prefix.is_empty() || lower.is_some() || upper.is_some() {
+                candidates.push((index, prefix, lower, upper));
+            }
+        }
+
+        // Read the fewest rows we can. Between indices that look as good as
+        // each other, prefer one that matches more columns, and then one that
+        // also gives us the rows in order.
+        let stats = schema.index_stats()?;
+        let best = candidates
+            .iter()
+            .enumerate()
+            .map(|(position, (index, prefix, lower, upper))| {
+                let rows = estimate_rows(stats.get(index.name()), prefix, lower, upper);
+                (
+                    position,
+                    (rows, Reverse(prefix.len()), !ordered(index, prefix)),
+                )
+            })
+            .min_by_key(|&(_, key)| key);
+
+        // A rowid range is cheaper than an index range that reads as many
+        // rows, as there's no need to look each row up in the table.
+        if let Some((lower, upper)) = rowid_range {
+            if best.is_none_or(|(_, (rows, _, _))| estimate_rowid_rows(lower, upper) <= rows) {
+                let ordered = ordered_by.is_some_and(|column| {
+                    table.as_ref().is_some_and(|table| table.is_rowid(column))
+                });
+                return Ok(Plan {
+                    access: Access::RowidRange { lower, upper },
+                    sort: !op.order_by.is_empty() && !ordered,
+                });
+            }
+        }
+
+        let position = match best {
+            Some((position, _)) => position,
+            None => {
+                return Ok(Plan {
+                    access: Access::FullScan,
+                    sort: !op.order_by.is_empty(),
+                })
+            }
+        };
+        let (index, prefix, lower, upper) = candidates.swap_remove(position);
+        let sort = !op.order_by.is_empty() && !ordered(&index, &prefix);
+        Ok(Plan {
+            access: Access::IndexRange {
+                index: Box::new(index),
+                prefix,
+                lower,
+                upper,
+            },
+            sort,
+        })
+    }
+
+    /// Where to read the rows of `table` from, giving each row `columns`.
+    /// An index range is read from the index alone if it has all the
+    /// columns, and otherwise each entry's row is looked up in the table.
+    pub fn source(
+        &self,
+        table: Table,
+        columns: Vec<String>,
+        decoding: TextDecoding,
+    ) -> Result<Box<dyn RowSource>> {
+        Ok(match self.access {
+            Access::FullScan => Box::new(TableScan::new(table, columns, decoding)),
+            Access::RowidRange { lower, upper } => {
+                Box::new(TableRange::new(table, lower, upper, columns, decoding))
+            }
+            Access::IndexRange {
+                ref index,
+                ref prefix,
+                ref lower,
+                ref upper,
+            } => {
+                let entries = IndexEntries {
+                    index: Index::clone(index),
+                    prefix: prefix.clone(),
+                    lower: lower.clone(),
+                    upper: upper.clone(),
+                };
+                if IndexScan::covers(index, &columns) {
+                    Box::new(IndexScan::new(entries, columns, decoding)?)
+                } else {
+                    Box::new(IndexLookup::new(entries, table, columns, decoding))
+                }
+            }
+        })
+    }
+}
+
+fn is_equality(lower: &Bound, upper: &Bound) -> bool {
+    lower.inclusive && upper.inclusive && compare(&lower.value, &upper.value) == Ordering::Equal
+}
+
+// Estimates how many rows an index range will read, using ANALYZE's
+// statistics if there are any.
+fn estimate_rows(
+    stats: Option<&IndexStats>,
+    prefix: &[Field],
+    lower: &Option<Bound>,
+    upper: &Option<Bound>,
+) -> u64 {
+    let rows = if prefix.is_empty() {
+        stats.map_or(DEFAULT_ROWS, |stats| stats.rows)
+    } else {
+        stats
+            .and_then(|stats| stats.rows_per_value.get(prefix.len() - 1).cloned())
+            .unwrap_or(DEFAULT_ROWS_PER_VALUE)
+    };
+    // Like SQLite, guess that each bound of a range rules out three quarters
+    // of the rows.
+    let bounds = lower.is_some() as u32 + upper.is_some() as u32;
+    rows / 4u64.pow(bounds)
+}
+
+// Like `estimate_rows()`, for a range of rowids, which are unique.
+fn estimate_rowid_rows(lower: Option<i64>, upper: Option<i64>) -> u64 {
+    if lower.is_some() && lower == upper {
+        return 1;
+    }
+    let bounds = lower.is_some() as u32 + upper.is_some() as u32;
+    DEFAULT_ROWS / 4u64.pow(bounds)
+}
+
+// Works out the bounds the WHERE clause's terms place on the rowid, under any
+// of its names, as an inclusive range. Only integer bounds are used: others
+// are left for the WHERE clause to apply.
+fn rowid_bounds(conjuncts: &[&Expr], table: &Table) -> Option<(Option<i64>, Option<i64>)> {
+    let (lower, upper) = bounds(conjuncts, |name| table.is_rowid(name));
+    let integer = |bound: Bound, step: i64| match bound.value.ty() {
+        // XXX Field doesn't know integers are signed yet.
+        Type::Integer if bound.inclusive => Some(bound.value.as_integer().ok()? as i64),
+        Type::Integer => (bound.value.as_integer().ok()? as i64).checked_add(step),
+        _ => None,
+    };
+    let lower = lower.and_then(|bound| integer(bound, 1));
+    let upper = upper.and_then(|bound| integer(bound, -1));
+    if lower.is_none() && upper.is_none() {
+        return None;
+    }
+    Some((lower, upper))
+}
+
+// Works out the bounds the WHERE clause's terms place on the column for which
+// `is_column` returns true. Only terms comparing the column against a literal
+// are considered, and the first of them to give each bound wins.
+fn bounds<F: Fn(&str) -> bool>(
+    conjuncts: &[&Expr],
+    is_column: F,
+) -> (Option<Bound>, Option<Bound>) {
+    let (mut lower, mut upper) = (None, None);
+    for conjunct in conjuncts {
+        let (op, value) = match **conjunct {
+            Expr::Compare(op, ref left, ref right) => match (&**left, &**right) {
+                (Expr::Column(ref name), Expr::Literal(ref value)) if is_column(name) => {
+                    (op, value)
+                }
+                (Expr::Literal(ref value), Expr::Column(ref name)) if is_column(name) => {
+                    (op.reverse(), value)
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        // Nothing is `= NULL`, and a range can't express `IS NULL`.
+        if value.ty() == Type::Null {
+            continue;
+        }
+
+        let bound = |inclusive| {
+            Some(Bound {
+                value: value.clone(),
+                inclusive,
+            })
+        };
+        let (new_lower, new_upper) = match op {
+            CompareOp::Equal | CompareOp::Is => (bound(true), bound(true)),
+            CompareOp::Greater => (bound(false), None),
+            CompareOp::GreaterOrEqual => (bound(true), None),
+            CompareOp::Less => (None, bound(false)),
+            CompareOp::LessOrEqual => (None, bound(true)),
+            CompareOp::NotEqual | CompareOp::IsNot => (None, None),
+        };
+        lower = lower.or(new_lower);
+        upper = upper.or(new_upper);
+    }
+    (lower, upper)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::query::{Params, Statement};
+    use crate::testing::open_fixture;
+
+    fn prepare(sql: &str) -> SelectOp {
+        Statement::prepare(sql).unwrap().bind(Params::None).unwrap()
+    }
+
+    #[test]
+    fn test_index_satisfies_order_by() {
+        let schema = open_fixture("indexed.db");
+        let op = prepare("SELECT name, price FROM items WHERE price >= 400 ORDER BY price");
+
+        let plan = Plan::new(&op, &schema).unwrap();
+        match plan.access {
+            Access::IndexRange { ref index, .. } => assert_eq!(index.name(), "items_price"),
+            ref other => panic!("Expected an index range, got {:?}", other),
+        }
+        assert!(!plan.sort);
+
+        let rows = op.execute(&schema).unwrap();
+        assert_eq!(rows.len(), 63);
+        assert!(rows
+            .windows(2)
+            .all(|w| compare(&w[0]["price"], &w[1]["price"]) != std::cmp::Ordering::Greater));
+        assert!(rows
+            .iter()
+            .all(|row| row["price"].as_integer().unwrap() >= 400));
+    }
+
+    #[test]
+    fn test_descending_order_by_still_sorts() {
+        let schema = open_fixture("indexed.db");
+        let op = prepare("SELECT price FROM items WHERE price < 50 ORDER BY price DESC");
+
+        let plan = Plan::new(&op, &schema).unwrap();
+        assert!(plan.sort);
+
+        let prices: Vec<_> = op
+            .execute(&schema)
+            .unwrap()
+            .iter()
+            .map(|row| row["price"].as_integer().unwrap())
+            .collect();
+        let mut expected = prices.clone();
+        expected.sort_by(|a, b| b.cmp(a));
+        assert_eq!(prices, expected);
+    }
+
+    #[test]
+    fn test_full_scan_without_usable_index() {
+        let schema = open_fixture("indexed.db");
+        let op = prepare("SELECT name FROM items WHERE name = 'item-7'");
+
+        let plan = Plan::new(&op, &schema).unwrap();
+        match plan.access {
+            Access::FullScan => {}
+            ref other => panic!("Expected a full scan, got {:?}", other),
+        }
+        assert_eq!(op.execute(&schema).unwrap().len(), 1);
+    }
+
+    fn index_name(plan: &Plan) -> &str {
+        match plan.access {
+            Access::IndexRange { ref index, .. } => index.name(),
+            ref other => panic!("Expected an index range, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_index_stats() {
+        let schema = open_fixture("analyzed.db");
+        let stats = schema.index_stats().unwrap();
+        assert_eq!(
+            stats["people_city"],
+            IndexStats {
+                rows: 1000,
+                rows_per_value: vec![500],
+            }
+        );
+        assert!(!stats.contains_key("pets_legs"));
+        assert!(open_fixture("indexed.db").index_stats().unwrap().is_empty());
+        assert_eq!(
+            IndexStats::parse("10 2 1 unordered"),
+            Some(IndexStats {
+                rows: 10,
+                rows_per_value: vec![2, 1],
+            })
+        );
+    }
+
+    #[test]
+    fn test_prefers_selective_index() {
+        let schema = open_fixture("analyzed.db");
+        // There are only two cities, but a hundred ages.
+        let op = prepare("SELECT id FROM people WHERE city = 'york' AND age = 42");
+        let plan = Plan::new(&op, &schema).unwrap();
+        assert_eq!(index_name(&plan), "people_age");
+        assert_eq!(op.execute(&schema).unwrap().len(), 10);
+
+        // people_age is more than a page deep, and the rows it finds are
+        // the ones a full scan would.
+        let all = schema
+            .table("people")
+            .unwrap()
+            .select(vec!["id", "city", "age"])
+            .unwrap();
+        for age in 0..100 {
+            let op = prepare(&format!(
+                "SELECT id FROM people WHERE city = 'york' AND age = {}",
+                age
+            ));
+            assert_eq!(index_name(&Plan::new(&op, &schema).unwrap()), "people_age");
+            let expected = all
+                .iter()
+                .filter(|row| {
+                    row["city"].as_text().unwrap() == "york"
+                        && row["age"].as_integer().unwrap() == age
+                })
+                .count();
+            assert_eq!(op.execute(&schema).unwrap().len(), expected, "age {}", age);
+        }
+    }
+
+    #[test]
+    fn test_multi_level_index() {
+        // people_age is too big for a single page, so some of its entries
+        // are kept in its interior page rather than its leaves.
+        let schema = open_fixture("analyzed.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name() == "people_age")
+            .unwrap();
+        assert!(index.depth().unwrap() > 1);
+        let people = schema.table("people").unwrap();
+        let mut all = people.select(vec!["id", "age"]).unwrap();
+        all.sort_by_key(|row| {
+            (
+                row["age"].as_integer().unwrap(),
+                row["id"].as_integer().unwrap(),
+            )
+        });
+        assert_eq!(all.len(), 1000);
+
+        let ids = |rows: &[HashMap<String, Field>]| -> Vec<u64> {
+            rows.iter()
+                .map(|row| row["id"].as_integer().unwrap())
+                .collect()
+        };
+        for age in 0..100 {
+            let op = prepare(&format!("SELECT id FROM people WHERE age = {}", age));
+            assert_eq!(index_name(&Plan::new(&op, &schema).unwrap()), "people_age");
+            let expected: Vec<_> = all
+                .iter()
+                .filter(|row| row["age"].as_integer().unwrap() == age)
+                .cloned()
+                .collect();
+            assert_eq!(
+                ids(&op.execute(&schema).unwrap()),
+                ids(&expected),
+                "age {}",
+                age
+            );
+        }
+
+        let op = prepare("SELECT id FROM people WHERE age >= 0 ORDER BY age");
+        assert_eq!(index_name(&Plan::new(&op, &schema).unwrap()), "people_age");
+        assert_eq!(ids(&op.execute(&schema).unwrap()), ids(&all));
+    }
+
+    #[test]
+    fn test_prefers_equality_without_stats() {
+        let schema = open_fixture("analyzed.db");
+        let op = prepare("SELECT id FROM pets WHERE weight > 10 AND legs = 2");
+        let plan = Plan::new(&op, &schema).unwrap();
+        assert_eq!(index_name(&plan), "pets_legs");
+        assert_eq!(op.execute(&schema).unwrap().len(), 29);
+    }
+
+    #[test]
+    fn test_rowid_range() {
+        let schema = open_fixture("deep.db");
+        let rowid_range = |sql: &str| match Plan::new(&prepare(sql), &schema).unwrap().access {
+            Access::RowidRange { lower, upper } => (lower, upper),
+            other => panic!("Expected a rowid range, got {:?}", other),
+        };
+        assert_eq!(
+            rowid_range("SELECT id FROM deep WHERE id BETWEEN 100 AND 110"),
+            (Some(100), Some(110))
+        );
+        assert_eq!(
+            rowid_range("SELECT id FROM deep WHERE rowid > 100 AND value = 1"),
+            (Some(101), None)
+        );
+        assert_eq!(
+            rowid_range("SELECT id FROM deep WHERE 100 > _rowid_"),
+            (None, Some(99))
+        );
+        assert!(
+            !Plan::new(
+                &prepare("SELECT id FROM deep WHERE id > 5 ORDER BY id"),
+                &schema
+            )
+            .unwrap()
+            .sort
+        );
+
+        // Only the pages leading to the range are read, rather than the whole
+        // table.
+        let pager = schema.pager();
+        let pages_read = |sql: &str| {
+            let before = pager.pages_read();
+            let rows = prepare(sql).execute(&schema).unwrap();
+            (rows, pager.pages_read() - before)
+        };
+        let (rows, ranged) = pages_read("SELECT id, value FROM deep WHERE id BETWEEN 100 AND 110");
+        let ids: Vec<_> = rows
+            .iter()
+            .map(|row| row["id"].as_integer().unwrap())
+            .collect();
+        assert_eq!(ids, (100..=110).collect::<Vec<_>>());
+        let (_, full) = pages_read("SELECT id, value FROM deep WHERE value BETWEEN 100 AND 110");
+        assert!(ranged * 20 < full, "{} pages read of {}", ranged, full);
+
+        // Bounds that aren't integers are left to the WHERE clause.
+        let (rows, _) = pages_read("SELECT id FROM deep WHERE id > 19998 AND id < 20000.5");
+        assert_eq!(rows.len(), 2);
+        let (rows, _) = pages_read("SELECT id FROM deep WHERE id >= 20000 AND id < 'x'");
+        assert_eq!(rows.len(), 2);
+        let (rows, _) = pages_read("SELECT id FROM deep WHERE id NOT BETWEEN 2 AND 19999");
+        assert_eq!(rows.len(), 6);
+    }
+
+    #[test]
+    fn test_composite_index() {
+        let schema = open_fixture("composite.db");
+        let access = |op: &SelectOp| match Plan::new(op, &schema).unwrap().access {
+            Access::IndexRange {
+                index,
+                prefix,
+                lower,
+                upper,
+            } => (
+                index.name().to_owned(),
+                prefix.len(),
+                lower.is_some(),
+                upper.is_some(),
+            ),
+            other => panic!("Expected an index range, got {:?}", other),
+        };
+
+        // Both columns of the index are matched, which beats the index on day
+        // alone.
+        let op = prepare("SELECT hits FROM visits WHERE day = 7 AND site = 'c'");
+        assert_eq!(access(&op), ("visits_site_day".to_owned(), 2, false, false));
+        let rows = op.execute(&schema).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["hits"].as_integer().unwrap(), 72);
+
+        // Only a prefix of the index is matched.
+        let op = prepare("SELECT day FROM visits WHERE site = 'b' ORDER BY day");
+        assert_eq!(access(&op), ("visits_site_day".to_owned(), 1, false, false));
+        assert!(!Plan::new(&op, &schema).unwrap().sort);
+        let days: Vec<_> = op
+            .execute(&schema)
+            .unwrap()
+            .iter()
+            .map(|row| row["day"].as_integer().unwrap())
+            .collect();
+        assert_eq!(days, (1..=30).collect::<Vec<_>>());
+
+        // A prefix followed by a range on the next column.
+        let op = prepare("SELECT id FROM visits WHERE site = 'e' AND day > 25");
+        assert_eq!(access(&op), ("visits_site_day".to_owned(), 1, true, false));
+        assert_eq!(op.execute(&schema).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_multi_level_composite_index() {
+        let schema = open_fixture("sensors.db");
+        let index = schema.indices().unwrap().pop().unwrap();
+        assert_eq!(index.name(), "readings_sensor_hour");
+        assert!(index.depth().unwrap() > 1);
+        let all = schema
+            .table("readings")
+            .unwrap()
+            .select(vec!["id", "sensor", "hour"])
+            .unwrap();
+        let expected = |sensor: u64, hour: Option<u64>| -> Vec<u64> {
+            let mut ids: Vec<_> = all
+                .iter()
+                .filter(|row| {
+                    row["sensor"].as_integer().unwrap() == sensor
+                        && hour.is_none_or(|hour| row["hour"].as_integer().unwrap() == hour)
+                })
+                .map(|row| row["id"].as_integer().unwrap())
+                .collect();
+            ids.sort();
+            ids
+        };
+        let ids = |sql: &str| -> Vec<u64> {
+            let op = prepare(sql);
+            assert_eq!(index_name(&Plan::new(&op, &schema).unwrap()), index.name());
+            let mut ids: Vec<_> = op
+                .execute(&schema)
+                .unwrap()
+                .iter()
+                .map(|row| row["id"].as_integer().unwrap())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        // Between them, the prefixes cover every entry of the index.
+        for sensor in 0..10 {
+            let sql = format!("SELECT id FROM readings WHERE sensor = {}", sensor);
+            assert_eq!(ids(&sql), expected(sensor, None), "sensor {}", sensor);
+        }
+        for &(sensor, hour) in &[(0, 0), (3, 17), (9, 23)] {
+            let sql = format!(
+                "SELECT id FROM readings WHERE sensor = {} AND hour = {}",
+                sensor, hour
+            );
+            assert_eq!(ids(&sql), expected(sensor, Some(hour)));
+        }
+    }
+}
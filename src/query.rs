@@ -0,0 +1,1322 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::aggregate::Aggregate;
+use crate::errors::*;
+use crate::expr::{compare, Collation, Expr};
+#[cfg(any(test, feature = "tracing"))]
+use crate::pager::TraceEvent;
+use crate::parser::{parse_statement, parse_view};
+#[cfg(any(test, feature = "tracing"))]
+use crate::planner::Access;
+use crate::planner::Plan;
+use crate::record::{Field, LiteralValue};
+use crate::schema::Schema;
+use crate::value::{Row, TextDecoding, Value};
+
+/// A parameter in a statement, in the order its placeholder appears.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Parameter {
+    /// `?` or `?NNN`. Zero-indexed.
+    Positional(usize),
+    /// `:name`, `@name` or `$name`, including the prefix.
+    Named(String),
+}
+
+/// Values to bind to a statement's parameters.
+#[derive(Copy, Clone, Debug)]
+pub enum Params<'a> {
+    None,
+    Positional(&'a [Value]),
+    Named(&'a HashMap<String, Value>),
+}
+
+impl<'a> Params<'a> {
+    fn get(&self, param: &Parameter) -> Result<Value> {
+        let value = match (*self, param) {
+            (Params::Positional(values), Parameter::Positional(n)) => values.get(*n),
+            (Params::Named(values), Parameter::Named(name)) => values.get(name),
+            _ => None,
+        };
+        value.cloned().ok_or_else(|| {
+            let name = match *param {
+                Parameter::Positional(n) => format!("?{}", n + 1),
+                Parameter::Named(ref name) => name.clone(),
+            };
+            ErrorKind::UnboundParameter(name).into()
+        })
+    }
+}
+
+/// An item in the result columns of a SELECT.
+#[derive(Clone, Debug)]
+pub struct ProjItem {
+    pub name: String,
+    pub expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+pub struct GroupBy {
+    pub columns: Vec<String>,
+    pub having: Option<Expr>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OrderBy {
+    pub column: String,
+    pub descending: bool,
+    /// The collation given by COLLATE, overriding the column's.
+    pub collation: Option<Collation>,
+}
+
+/// A SELECT joined to the one before it with `UNION [ALL]`.
+#[derive(Clone, Debug)]
+pub struct Union {
+    pub all: bool,
+    pub select: SelectOp,
+}
+
+#[derive(Clone, Debug)]
+pub struct SelectOp {
+    pub table: String,
+    pub distinct: bool,
+    pub columns: Vec<ProjItem>,
+    pub where_clause: Option<Expr>,
+    pub group_by: Option<GroupBy>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    /// SELECTs whose results are added to this one's, before ORDER BY and
+    /// LIMIT are applied to them all.
+    pub unions: Vec<Union>,
+    /// How text that isn't valid UTF-8 is read.
+    pub text_decoding: TextDecoding,
+}
+
+impl SelectOp {
+    /// Replaces the placeholders in the operation with `values`.
+    pub fn bind(&self, values: &[Field]) -> Result<SelectOp> {
+        self.map_exprs(&|expr| expr.bind(values))
+    }
+
+    /// A copy of the operation that reads text as `decoding` says, as do
+    /// the SELECTs in its UNION and its subqueries.
+    pub fn with_text_decoding(&self, decoding: TextDecoding) -> SelectOp {
+        let mut op = self.clone();
+        op.text_decoding = decoding;
+        for union in &mut op.unions {
+            union.select = union.select.with_text_decoding(decoding);
+        }
+        op
+    }
+
+    // Runs the operation's subqueries, replacing them with their results.
+    fn resolve(&self, schema: &Schema) -> Result<SelectOp> {
+        self.map_exprs(&|expr| expr.resolve(schema, self.text_decoding))
+    }
+
+    // A copy of the operation with `f` applied to each of its expressions,
+    // including those of the SELECTs in its UNION.
+    fn map_exprs(&self, f: &dyn Fn(&Expr) -> Result<Expr>) -> Result<SelectOp> {
+        let map = |expr: &Option<Expr>| match *expr {
+            Some(ref expr) => f(expr).map(Some),
+            None => Ok(None),
+        };
+        let group_by = match self.group_by {
+            Some(ref group_by) => Some(GroupBy {
+                columns: group_by.columns.clone(),
+                having: map(&group_by.having)?,
+            }),
+            None => None,
+        };
+        let columns = self
+            .columns
+            .iter()
+            .map(|item| {
+                Ok(ProjItem {
+                    name: item.name.clone(),
+                    expr: f(&item.expr)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let unions = self
+            .unions
+            .iter()
+            .map(|union| {
+                Ok(Union {
+                    all: union.all,
+                    select: union.select.map_exprs(f)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(SelectOp {
+            table: self.table.clone(),
+            distinct: self.distinct,
+            columns,
+            where_clause: map(&self.where_clause)?,
+            group_by,
+            order_by: self.order_by.clone(),
+            limit: self.limit,
+            offset: self.offset,
+            unions,
+            text_decoding: self.text_decoding,
+        })
+    }
+
+    fn having(&self) -> Option<&Expr> {
+        self.group_by.as_ref().and_then(|g| g.having.as_ref())
+    }
+
+    // Every aggregate that needs computing for each group, without duplicates.
+    fn aggregates(&self) -> Vec<&Aggregate> {
+        let mut aggregates: Vec<&Aggregate> = Vec::new();
+        let projected = self.columns.iter().flat_map(|item| item.expr.aggregates());
+        let in_having = self.having().map(|e| e.aggregates()).unwrap_or_default();
+        for aggregate in projected.chain(in_having) {
+            if !aggregates.contains(&aggregate) {
+                aggregates.push(aggregate);
+            }
+        }
+        aggregates
+    }
+
+    pub fn is_grouped(&self) -> bool {
+        self.group_by.is_some() || !self.aggregates().is_empty()
+    }
+
+    // The columns we need to read from the table: those being returned, as
+    // well as any the WHERE clause, GROUP BY, aggregates or ORDER BY need.
+    fn fetch_columns(&self) -> Vec<String> {
+        let mut names: Vec<&str> = Vec::new();
+        for item in &self.columns {
+            names.extend(item.expr.columns());
+        }
+        names.extend(self.aggregates().iter().filter_map(|a| a.column()));
+        if let Some(ref group_by) = self.group_by {
+            names.extend(group_by.columns.iter().map(|s| s.as_str()));
+        }
+        if let Some(ref expr) = self.where_clause {
+            names.extend(expr.columns());
+        }
+        // A grouped query is ordered by the columns of its groups, which are
+        // already in the list.
+        if !self.is_grouped() {
+            names.extend(self.order_by.iter().map(|o| o.column.as_str()));
+        }
+
+        let mut fetch: Vec<String> = Vec::new();
+        for name in names {
+            if !fetch.iter().any(|c| c == name) {
+                fetch.push(name.to_owned());
+            }
+        }
+        fetch
+    }
+
+    pub fn execute(&self, schema: &Schema) -> Result<Vec<HashMap<String, Field>>> {
+        self.resolve(schema)?.run(schema)
+    }
+
+    // Runs the operation, once its subqueries have been.
+    fn run(&self, schema: &Schema) -> Result<Vec<HashMap<String, Field>>> {
+        #[cfg(any(test, feature = "tracing"))]
+        schema
+            .pager()
+            .trace(TraceEvent::Query { table: &self.table });
+        let plan = Plan::new(self, schema)?;
+        #[cfg(any(test, feature = "tracing"))]
+        schema.pager().trace(TraceEvent::Plan {
+            table: &self.table,
+            index: match plan.access {
+                Access::FullScan | Access::RowidRange { .. } => None,
+                Access::IndexRange { ref index, .. } => Some(index.name()),
+            },
+        });
+        let source;
+        let mut candidates: Box<dyn Iterator<Item = Result<HashMap<String, Field>>>> =
+            match schema.view(&self.table)? {
+                // A view's rows are the results of its SELECT, which we then
+                // treat like the rows of a table.
+                Some(view) => Box::new(
+                    parse_view(&view.sql)?
+                        .with_text_decoding(self.text_decoding)
+                        .execute(schema)?
+                        .into_iter()
+                        .map(Ok),
+                ),
+                None => {
+                    let table = schema.table(&self.table)?;
+                    source = plan.source(table, self.fetch_columns(), self.text_decoding)?;
+                    Box::new(source.rows()?.map(|row| row.map(Row::into_map)))
+                }
+            };
+
+        // Rows are read as they're needed, so if nothing needs to see every
+        // row, we can stop reading once there are enough for the LIMIT.
+        let streaming =
+            !self.is_grouped() && !self.distinct && self.unions.is_empty() && !plan.sort;
+        let wanted = match self.limit {
+            Some(limit) if streaming => limit.saturating_add(self.offset),
+            _ => usize::MAX,
+        };
+
+        let mut rows = Vec::new();
+        while rows.len() < wanted {
+            let mut row = match candidates.next() {
+                Some(row) => row?,
+                None => break,
+            };
+            if let Some(ref expr) = self.where_clause {
+                if !expr.matches(&row)? {
+                    continue;
+                }
+            }
+            if !self.is_grouped() {
+                self.project(&mut row)?;
+            }
+            rows.push(row);
+        }
+
+        if self.is_grouped() {
+            rows = self.group(rows)?;
+        }
+        if self.distinct {
+            self.dedup(&mut rows);
+        }
+        if !self.unions.is_empty() {
+            self.union(schema, &mut rows)?;
+        }
+        if plan.sort {
+            self.sort(&mut rows)?;
+        }
+
+        for row in rows.iter_mut() {
+            row.retain(|name, _| self.columns.iter().any(|item| &item.name == name));
+        }
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let rows: Vec<_> = rows.into_iter().skip(self.offset).take(limit).collect();
+        #[cfg(any(test, feature = "tracing"))]
+        schema.pager().trace(TraceEvent::Rows {
+            table: &self.table,
+            rows: rows.len(),
+        });
+        Ok(rows)
+    }
+
+    // Removes rows whose result columns are the same as an earlier row's.
+    fn dedup(&self, rows: &mut Vec<HashMap<String, Field>>) {
+        let mut seen = HashSet::new();
+        rows.retain(|row| {
+            let key: Vec<_> = self.columns.iter().map(|c| row[&c.name].key()).collect();
+            seen.insert(key)
+        });
+    }
+
+    // Adds the rows of each SELECT in the UNION to `rows`, renaming their
+    // columns to match ours. Rows are compared by their result columns only,
+    // so the others are removed first.
+    fn union(&self, schema: &Schema, rows: &mut Vec<HashMap<String, Field>>) -> Result<()> {
+        for row in rows.iter_mut() {
+            row.retain(|name, _| self.columns.iter().any(|item| &item.name == name));
+        }
+        for union in &self.unions {
+            for mut row in union.select.run(schema)? {
+                let renamed = self
+                    .columns
+                    .iter()
+                    .zip(&union.select.columns)
+                    .map(|(ours, theirs)| {
+                        Ok((
+                            ours.name.clone(),
+                            row.remove(&theirs.name).ok_or("Missing column in UNION")?,
+                        ))
+                    })
+                    .collect::<Result<_>>()?;
+                rows.push(renamed);
+            }
+            if !union.all {
+                self.dedup(rows);
+            }
+        }
+        Ok(())
+    }
+
+    // Adds the result columns to a row, so that ORDER BY and HAVING can refer
+    // to them by name.
+    fn project(&self, row: &mut HashMap<String, Field>) -> Result<()> {
+        for item in &self.columns {
+            let value = item.expr.eval(row)?;
+            row.insert(item.name.clone(), value);
+        }
+        Ok(())
+    }
+
+    fn sort(&self, rows: &mut [HashMap<String, Field>]) -> Result<()> {
+        if let Some(row) = rows.first() {
+            if let Some(order) = self.order_by.iter().find(|o| !row.contains_key(&o.column)) {
+                bail!("Unknown column in ORDER BY: {}", order.column);
+            }
+        }
+        rows.sort_by(|a, b| {
+            for order in &self.order_by {
+                let (a, b) = (&a[&order.column], &b[&order.column]);
+                let ord = match order.collation {
+                    Some(collation) => collation.compare(a, b),
+                    None => compare(a, b),
+                };
+                let ord = if order.descending { ord.reverse() } else { ord };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            Ordering::Equal
+        });
+        Ok(())
+    }
+
+    // Collapses rows into one row per group, holding the group's GROUP BY
+    // columns and aggregates, and then applies HAVING to the groups.
+    fn group(&self, rows: Vec<HashMap<String, Field>>) -> Result<Vec<HashMap<String, Field>>> {
+        let group_columns: &[String] = match self.group_by {
+            Some(ref group_by) => &group_by.columns,
+            None => &[],
+        };
+        let aggregates = self.aggregates();
+        let accumulators = || {
+            aggregates
+                .iter()
+                .map(|a| a.accumulator())
+                .collect::<Vec<_>>()
+        };
+
+        let mut groups = Vec::new();
+        let mut positions = HashMap::new();
+        for row in rows {
+            let key = group_columns
+                .iter()
+                .map(|name| row.get(name).map(Field::key))
+                .collect::<Option<Vec<_>>>()
+                .ok_or("Unknown column in GROUP BY")?;
+            let position = *positions.entry(key).or_insert_with(|| {
+                groups.push((row.clone(), accumulators()));
+                groups.len() - 1
+            });
+            for accumulator in groups[position].1.iter_mut() {
+                accumulator.update(&row)?;
+            }
+        }
+        // Without a GROUP BY, all rows are one group - even if there are none.
+        if groups.is_empty() && self.group_by.is_none() {
+            groups.push((HashMap::new(), accumulators()));
+        }
+
+        let mut results = Vec::new();
+        for (mut row, accumulators) in groups {
+            for (aggregate, accumulator) in aggregates.iter().zip(accumulators) {
+                row.insert(aggregate.name(), accumulator.finish());
+            }
+            self.project(&mut row)?;
+            if let Some(having) = self.having() {
+                if !having.matches(&row)? {
+                    continue;
+                }
+            }
+            results.push(row);
+        }
+        Ok(results)
+    }
+}
+
+/// A PRAGMA, which reads the database's metadata.
+#[derive(Debug)]
+pub enum Pragma {
+    /// `PRAGMA table_info(table)`: a row for each of the table's columns.
+    TableInfo(String),
+    /// `PRAGMA index_list(table)`: a row for each index on the table.
+    IndexList(String),
+    /// `PRAGMA index_info(index)`: a row for each of the index's columns.
+    /// SQLite doesn't record which columns the indices it creates for UNIQUE
+    /// constraints are on, so they have none.
+    IndexInfo(String),
+}
+
+impl Pragma {
+    pub fn execute(&self, schema: &Schema) -> Result<Vec<HashMap<String, Field>>> {
+        match *self {
+            Pragma::TableInfo(ref table) => {
+                let table = schema.table(table)?;
+                let rows = table.columns().iter().map(|column| {
+                    let mut row = HashMap::new();
+                    row.insert("cid".to_owned(), Field::from(column.cid as u64));
+                    row.insert("name".to_owned(), Field::from(column.name.as_str()));
+                    row.insert("type".to_owned(), Field::from(column.ty.as_str()));
+                    row.insert("notnull".to_owned(), Field::from(column.not_null as u64));
+                    let default = match column.default {
+                        Some(ref default) => Field::from(default.as_str()),
+                        None => Field::Literal(LiteralValue::Null),
+                    };
+                    row.insert("dflt_value".to_owned(), default);
+                    row.insert("pk".to_owned(), Field::from(column.pk as u64));
+                    row
+                });
+                Ok(rows.collect())
+            }
+            Pragma::IndexList(ref table) => {
+                // Check the table exists, as SQLite would.
+                let pk_autoindex = schema.table(table)?.primary_key_autoindex();
+                let indices = schema
+                    .indices()?
+                    .into_iter()
+                    .filter(|i| i.tbl_name() == table);
+                let rows = indices.enumerate().map(|(seq, index)| {
+                    let mut row = HashMap::new();
+                    row.insert("seq".to_owned(), Field::from(seq as u64));
+                    row.insert("name".to_owned(), Field::from(index.name()));
+                    row.insert("unique".to_owned(), Field::from(index.unique() as u64));
+                    // Whether the index was made by CREATE INDEX, or for a
+                    // UNIQUE or PRIMARY KEY constraint.
+                    let origin = if index.sql().is_some() {
+                        "c"
+                    } else if pk_autoindex.as_deref() == Some(index.name()) {
+                        "pk"
+                    } else {
+                        "u"
+                    };
+                    row.insert("origin".to_owned(), Field::from(origin));
+                    row.insert("partial".to_owned(), Field::from(index.partial() as u64));
+                    row
+                });
+                Ok(rows.collect())
+            }
+            Pragma::IndexInfo(ref name) => {
+                let index = schema
+                    .indices()?
+                    .into_iter()
+                    .find(|i| i.name() == name)
+                    .ok_or_else(|| format!("No such index: {}", name))?;
+                let table = schema.table(index.tbl_name())?;
+                let columns = table.columns();
+                index
+                    .columns()
+                    .iter()
+                    .enumerate()
+                    .map(|(seqno, name)| {
+                        let cid = columns
+                            .iter()
+                            .find(|c| c.name == *name)
+                            .ok_or_else(|| format!("Unknown column in index: {}", name))?
+                            .cid;
+                        let mut row = HashMap::new();
+                        row.insert("seqno".to_owned(), Field::from(seqno as u64));
+                        row.insert("cid".to_owned(), Field::from(cid as u64));
+                        row.insert("name".to_owned(), Field::from(name.as_str()));
+                        Ok(row)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// What a statement does once it's executed.
+#[derive(Debug)]
+pub enum Operation {
+    Select(Box<SelectOp>),
+    Pragma(Pragma),
+}
+
+/// A parsed statement, which may contain parameters that must be bound
+/// before it can be executed.
+#[derive(Debug)]
+pub struct Statement {
+    operation: Operation,
+    parameters: Vec<Parameter>,
+}
+
+impl Statement {
+    pub fn prepare(sql: &str) -> Result<Statement> {
+        let (operation, parameters) = parse_statement(sql)?;
+        if let Operation::Select(ref select) = operation {
+            let arms = Some(&**select)
+                .into_iter()
+                .chain(select.unions.iter().map(|u| &u.select));
+            for arm in arms {
+                if let Some(ref expr) = arm.where_clause {
+                    if !expr.aggregates().is_empty() {
+                        bail!("Misuse of aggregate in WHERE");
+                    }
+                }
+            }
+        }
+        Ok(Statement {
+            operation,
+            parameters,
+        })
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    /// The names of the columns of the rows the statement returns, in order.
+    pub fn column_names(&self) -> Vec<String> {
+        let names: &[&str] = match self.operation {
+            Operation::Select(ref select) => {
+                return select.columns.iter().map(|c| c.name.clone()).collect();
+            }
+            Operation::Pragma(Pragma::TableInfo(_)) => {
+                &["cid", "name", "type", "notnull", "dflt_value", "pk"]
+            }
+            Operation::Pragma(Pragma::IndexList(_)) => {
+                &["seq", "name", "unique", "origin", "partial"]
+            }
+            Operation::Pragma(Pragma::IndexInfo(_)) => &["seqno", "cid", "name"],
+        };
+        names.iter().map(|&name| name.to_owned()).collect()
+    }
+
+    /// Binds `params` to the statement's parameters, giving an operation
+    /// that's ready to execute.
+    pub fn bind(&self, params: Params<'_>) -> Result<SelectOp> {
+        let values = self
+            .parameters
+            .iter()
+            .map(|param| params.get(param).map(Field::from))
+            .collect::<Result<Vec<_>>>()?;
+        match self.operation {
+            Operation::Select(ref select) => select.bind(&values),
+            Operation::Pragma(_) => {
+                bail!("Only SELECTs can be bound: use execute() to run a PRAGMA")
+            }
+        }
+    }
+
+    /// Binds `params` to the statement and runs it.
+    pub fn execute(
+        &self,
+        schema: &Schema,
+        params: Params<'_>,
+    ) -> Result<Vec<HashMap<String, Field>>> {
+        match self.operation {
+            Operation::Select(_) => self.bind(params)?.execute(schema),
+            Operation::Pragma(ref pragma) => pragma.execute(schema),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{open_doctored_fixture, open_fixture, query};
+    use crate::types::Type;
+    use byteorder::{BigEndian, ByteOrder};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn row(id: u64) -> HashMap<String, Field> {
+        let mut row = HashMap::new();
+        row.insert("id".to_owned(), Field::from(id));
+        row
+    }
+
+    #[test]
+    fn test_bind_positional() {
+        let stmt = Statement::prepare("SELECT name FROM people WHERE id = ?").unwrap();
+        let op = stmt.bind(Params::Positional(&[Value::Integer(2)])).unwrap();
+        let expr = op.where_clause.unwrap();
+        assert!(expr.matches(&row(2)).unwrap());
+        assert!(!expr.matches(&row(3)).unwrap());
+    }
+
+    #[test]
+    fn test_bind_named() {
+        let stmt = Statement::prepare("SELECT name FROM people WHERE id = :id").unwrap();
+        assert_eq!(stmt.parameters(), &[Parameter::Named(":id".to_owned())]);
+
+        let mut params = HashMap::new();
+        params.insert(":id".to_owned(), Value::Integer(3));
+        let op = stmt.bind(Params::Named(&params)).unwrap();
+        let expr = op.where_clause.unwrap();
+        assert!(expr.matches(&row(3)).unwrap());
+        assert!(!expr.matches(&row(2)).unwrap());
+    }
+
+    #[test]
+    fn test_unbound_parameter() {
+        let stmt = Statement::prepare("SELECT name FROM people WHERE id = ?2").unwrap();
+        match stmt.bind(Params::Positional(&[Value::Integer(1)])) {
+            Err(Error(ErrorKind::UnboundParameter(ref name), _)) => assert_eq!(name, "?2"),
+            other => panic!("Expected UnboundParameter, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_prepare_errors() {
+        match Statement::prepare("SELECT name FROM people WHERE") {
+            Err(Error(ErrorKind::ParseError(_), _)) => {}
+            other => panic!("Expected ParseError, got {:?}", other.map(|_| ())),
+        }
+        match Statement::prepare("INSERT INTO people VALUES (1)") {
+            Err(Error(ErrorKind::ReadOnly(ref statement), _)) => assert_eq!(statement, "INSERT"),
+            other => panic!("Expected ReadOnly, got {:?}", other.map(|_| ())),
+        }
+        match Statement::prepare("drop TABLE people") {
+            Err(Error(ErrorKind::ReadOnly(ref statement), _)) => assert_eq!(statement, "DROP"),
+            other => panic!("Expected ReadOnly, got {:?}", other.map(|_| ())),
+        }
+        match Statement::prepare("INSERT people") {
+            Err(Error(ErrorKind::ReadOnly(_), _)) => {}
+            other => panic!("Expected ReadOnly, got {:?}", other.map(|_| ())),
+        }
+        match Statement::prepare("VACUUM") {
+            Err(Error(ErrorKind::UnsupportedStatement(ref statement), _)) => {
+                assert_eq!(statement, "VACUUM")
+            }
+            other => panic!("Expected UnsupportedStatement, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_having_filters_groups_by_count() {
+        let schema = open_fixture("sales.db");
+        let op =
+            Statement::prepare("SELECT region, COUNT(*) c FROM sales GROUP BY region HAVING c > 3")
+                .unwrap()
+                .bind(Params::None)
+                .unwrap();
+        let mut rows: Vec<_> = op
+            .execute(&schema)
+            .unwrap()
+            .into_iter()
+            .map(|row| {
+                (
+                    row["region"].as_text().unwrap().to_owned(),
+                    row["c"].as_integer().unwrap(),
+                )
+            })
+            .collect();
+        rows.sort();
+        assert_eq!(rows, vec![("east".to_owned(), 4), ("north".to_owned(), 5)]);
+    }
+
+    fn run(sql: &str) -> Vec<HashMap<String, Field>> {
+        query(&open_fixture("sales.db"), sql)
+    }
+
+    #[test]
+    fn test_concat_with_number() {
+        let rows = run(
+            "SELECT region || '-' || amount AS label FROM sales WHERE region || amount = 'south15'",
+        );
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["label"].as_text().unwrap(), "south-15");
+    }
+
+    #[test]
+    fn test_concat_with_null() {
+        let rows = run("SELECT region || NULL FROM sales WHERE id = 1");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["region || NULL"].ty(), Type::Null);
+        assert!(run("SELECT region FROM sales WHERE region || NULL = 'north'").is_empty());
+    }
+
+    #[test]
+    fn test_count_distinct() {
+        let rows = run(
+            "SELECT COUNT(DISTINCT region) AS regions, COUNT(*) AS n, COUNT(region) FROM sales",
+        );
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["regions"].as_integer().unwrap(), 3);
+        assert_eq!(rows[0]["n"].as_integer().unwrap(), 11);
+        assert_eq!(rows[0]["count(region)"].as_integer().unwrap(), 11);
+    }
+
+    #[test]
+    fn test_sum_and_avg_distinct() {
+        // Each value appears five times.
+        let schema = open_fixture("large.db");
+        let rows = query(
+            &schema,
+            "SELECT SUM(DISTINCT value) s, AVG(DISTINCT value) a, SUM(value) t FROM numbers",
+        );
+        assert_eq!(rows[0]["s"].as_integer().unwrap(), 499_500);
+        assert_eq!(rows[0]["a"].as_float().unwrap(), 499.5);
+        assert_eq!(rows[0]["t"].as_integer().unwrap(), 5 * 499_500);
+    }
+
+    #[test]
+    fn test_compare_columns() {
+        let schema = open_fixture("large.db");
+        let run = |sql| query(&schema, sql);
+
+        let rows = run("SELECT id FROM numbers WHERE value < id");
+        let expected = (1..=5000u64).filter(|n| n * 7 % 1000 < *n).count();
+        assert_eq!(rows.len(), expected);
+        assert!(rows.iter().all(|row| {
+            let id = row["id"].as_integer().unwrap();
+            id * 7 % 1000 < id
+        }));
+
+        // Either side can be an expression.
+        assert_eq!(
+            run("SELECT id FROM numbers WHERE label = 'row-' || id").len(),
+            5000
+        );
+        assert_eq!(
+            run("SELECT id FROM numbers WHERE id || '' = value").len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_in_subquery() {
+        let schema = open_fixture("views.db");
+        let names = |sql: &str| -> Vec<String> {
+            query(&schema, sql)
+                .iter()
+                .map(|row| row["name"].as_text().unwrap().to_owned())
+                .collect()
+        };
+        assert_eq!(
+            names("SELECT name FROM people WHERE id IN (SELECT id FROM adults WHERE age < 50) ORDER BY name"),
+            vec!["ann", "cat"]
+        );
+        assert_eq!(
+            names("SELECT name FROM people WHERE id NOT IN (SELECT person FROM audit WHERE person > 3) ORDER BY name"),
+            vec!["ann", "bob", "cat"]
+        );
+        assert_eq!(
+            names("SELECT name FROM people WHERE id IN (5, 1 + 0) ORDER BY name"),
+            vec!["ann", "eve"]
+        );
+        assert!(Statement::prepare(
+            "SELECT name FROM people WHERE id IN (SELECT id, name FROM people)"
+        )
+        .is_err());
+
+        let eval = |sql: &str| {
+            let sql = format!("SELECT {} AS x FROM people WHERE id = 1", sql);
+            query(&schema, &sql).remove(0).remove("x").unwrap()
+        };
+        assert_eq!(eval("NULL IN (1, 2)").ty(), Type::Null);
+        assert_eq!(eval("1 IN (2, NULL)").ty(), Type::Null);
+        assert_eq!(eval("1 IN (2, NULL, 1)").as_integer().unwrap(), 1);
+        assert_eq!(eval("NULL NOT IN ()").as_integer().unwrap(), 1);
+        assert_eq!(
+            eval("1 IN (SELECT person FROM audit)")
+                .as_integer()
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_select_from_view() {
+        let schema = open_fixture("views.db");
+        let rows = query(
+            &schema,
+            "SELECT name FROM adults WHERE age < 50 ORDER BY name",
+        );
+        let names: Vec<_> = rows
+            .iter()
+            .map(|row| row["name"].as_text().unwrap())
+            .collect();
+        assert_eq!(names, vec!["ann", "cat"]);
+
+        let rows = query(&schema, "SELECT COUNT(*) AS n FROM adults");
+        assert_eq!(rows[0]["n"].as_integer().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_mixed_projection() {
+        let rows = run("SELECT id, region, amount * 2 AS double, -amount, id + amount % 7 FROM sales WHERE id = 3");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["id"].as_integer().unwrap(), 3);
+        assert_eq!(rows[0]["region"].as_text().unwrap(), "north");
+        assert_eq!(rows[0]["double"].as_integer().unwrap(), 60);
+        assert_eq!(rows[0]["-amount"].as_integer().unwrap() as i64, -30);
+        assert_eq!(rows[0]["id + amount % 7"].as_integer().unwrap(), 5);
+
+        // Expressions can use aggregates as well as grouped columns.
+        let rows = run(
+            "SELECT region, COUNT(*), SUM(amount) * 2 AS double, SUM(amount) / 2.0 AS half \
+             FROM sales GROUP BY region ORDER BY region",
+        );
+        assert_eq!(rows[0]["region"].as_text().unwrap(), "east");
+        assert_eq!(rows[0]["count(*)"].as_integer().unwrap(), 4);
+        assert_eq!(rows[0]["double"].as_integer().unwrap(), 20);
+        assert_eq!(rows[0]["half"].as_float().unwrap(), 5.0);
+
+        // Aggregates can't be used row by row, or within each other.
+        assert!(Statement::prepare("SELECT id FROM sales WHERE SUM(amount) > 1").is_err());
+        assert!(Statement::prepare("SELECT SUM(COUNT(amount)) FROM sales").is_err());
+        assert!(Statement::prepare("SELECT SUM(amount * 2) FROM sales").is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let eval = |sql: &str| {
+            let sql = format!("SELECT {} AS x FROM sales WHERE id = 1", sql);
+            run(&sql).remove(0).remove("x").unwrap()
+        };
+        assert_eq!(eval("7 / 2").as_integer().unwrap(), 3);
+        assert_eq!(eval("7 / 2.0").as_float().unwrap(), 3.5);
+        assert_eq!(eval("7.5 % 2").as_integer().unwrap(), 1);
+        assert_eq!(eval("'3' + 4").as_integer().unwrap(), 7);
+        assert_eq!(eval("1 + 2 * 3 - 4").as_integer().unwrap(), 3);
+        assert_eq!(eval("2 * 3 || 4").as_integer().unwrap(), 68);
+        assert_eq!(eval("1 / 0").ty(), Type::Null);
+        assert_eq!(eval("NULL + 1").ty(), Type::Null);
+        assert_eq!(
+            eval("9223372036854775807 + 1").as_float().unwrap(),
+            9223372036854775808.0
+        );
+    }
+
+    #[test]
+    fn test_cast() {
+        let eval = |sql: &str| {
+            let sql = format!("SELECT CAST({}) AS x FROM sales WHERE id = 1", sql);
+            run(&sql).remove(0).remove("x").unwrap()
+        };
+        // Text becomes a number using its longest numeric prefix.
+        assert_eq!(eval("'12abc' AS INTEGER").as_integer().unwrap(), 12);
+        assert_eq!(eval("'  -7.5' AS int").as_signed_integer().unwrap(), -7);
+        assert_eq!(eval("'abc' AS INTEGER").as_integer().unwrap(), 0);
+        assert_eq!(eval("'2.5e1x' AS REAL").as_float().unwrap(), 25.0);
+        // Numbers become text as they're shown.
+        assert_eq!(eval("amount AS TEXT").as_text().unwrap(), "10");
+        assert_eq!(eval("amount AS VARCHAR(10)").as_text().unwrap(), "10");
+        assert_eq!(eval("amount AS REAL").as_float().unwrap(), 10.0);
+        assert_eq!(eval("'ab' AS BLOB").as_blob().unwrap(), b"ab");
+        // Reals are truncated towards zero.
+        assert_eq!(eval("2.9 AS INTEGER").as_integer().unwrap(), 2);
+        assert_eq!(eval("-2.9 AS INTEGER").as_signed_integer().unwrap(), -2);
+        assert_eq!(eval("NULL AS INTEGER").ty(), Type::Null);
+
+        let rows = run("SELECT id FROM sales WHERE CAST(amount AS TEXT) = '40'");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["id"].as_integer().unwrap(), 4);
+        assert!(Statement::prepare("SELECT CAST(amount AS NUMERIC) FROM sales").is_err());
+    }
+
+    #[test]
+    fn test_typeof() {
+        let rows = run(
+            "SELECT typeof(id) AS i, typeof(region) AS t, typeof(amount / 2.0) AS r, \
+             typeof(x'01') AS b, typeof(NULL) AS n FROM sales WHERE id = 1",
+        );
+        for &(column, ty) in &[
+            ("i", "integer"),
+            ("t", "text"),
+            ("r", "real"),
+            ("b", "blob"),
+            ("n", "null"),
+        ] {
+            assert_eq!(rows[0][column].as_text().unwrap(), ty);
+        }
+
+        // Columns of each row of a table, including NULLs.
+        let schema = open_fixture("reals.db");
+        let types: Vec<_> = query(&schema, "SELECT TYPEOF(value) AS ty FROM readings")
+            .iter()
+            .map(|row| row["ty"].as_text().unwrap().to_owned())
+            .collect();
+        assert_eq!(types, vec!["real", "real", "real", "null"]);
+
+        assert!(Statement::prepare("SELECT typeof(id, region) FROM sales").is_err());
+    }
+
+    #[test]
+    fn test_length_lower_upper() {
+        let eval = |sql: &str| {
+            let sql = format!("SELECT {} AS x FROM sales WHERE id = 1", sql);
+            run(&sql).remove(0).remove("x").unwrap()
+        };
+        // Text is measured in characters, and blobs in bytes.
+        assert_eq!(eval("length('naïve ☃')").as_integer().unwrap(), 7);
+        assert_eq!(eval("length(x'00ff10')").as_integer().unwrap(), 3);
+        assert_eq!(eval("length(region)").as_integer().unwrap(), 5);
+        assert_eq!(eval("length(-12.5)").as_integer().unwrap(), 5);
+        assert_eq!(eval("upper(region || 'ïx')").as_text().unwrap(), "NORTHïX");
+        assert_eq!(eval("LOWER('MiXeD Ä')").as_text().unwrap(), "mixed Ä");
+        for sql in &["length(NULL)", "lower(NULL)", "upper(NULL)"] {
+            assert_eq!(eval(sql).ty(), Type::Null);
+        }
+
+        let rows = run("SELECT id FROM sales WHERE upper(region) = 'SOUTH'");
+        assert_eq!(rows.len(), 2);
+    }
+
+    #[test]
+    fn test_coalesce_and_ifnull() {
+        let eval = |sql: &str| {
+            let sql = format!("SELECT {} AS x FROM sales WHERE id = 1", sql);
+            run(&sql).remove(0).remove("x").unwrap()
+        };
+        assert_eq!(eval("coalesce(NULL, region)").as_text().unwrap(), "north");
+        assert_eq!(eval("ifnull(NULL, 2)").as_integer().unwrap(), 2);
+        assert_eq!(eval("ifnull(amount, 2)").as_integer().unwrap(), 10);
+        assert_eq!(eval("coalesce(NULL, NULL, 3, 4)").as_integer().unwrap(), 3);
+        assert_eq!(eval("coalesce(NULL, NULL)").ty(), Type::Null);
+
+        assert!(Statement::prepare("SELECT coalesce(id) FROM sales").is_err());
+        assert!(Statement::prepare("SELECT ifnull(id, 1, 2) FROM sales").is_err());
+    }
+
+    #[test]
+    fn test_like_escape() {
+        let eval = |sql: &str| {
+            let sql = format!("SELECT {} AS x FROM sales WHERE id = 1", sql);
+            run(&sql).remove(0).remove("x").unwrap()
+        };
+        assert_eq!(
+            eval(r"'100%' LIKE '100\%' ESCAPE '\'")
+                .as_integer()
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            eval(r"'1000' LIKE '100\%' ESCAPE '\'")
+                .as_integer()
+                .unwrap(),
+            0
+        );
+        assert_eq!(eval(r"'1000' LIKE '100%'").as_integer().unwrap(), 1);
+        assert_eq!(
+            eval("'a_c' LIKE 'a!_c' ESCAPE '!'").as_integer().unwrap(),
+            1
+        );
+        assert_eq!(
+            eval("'abc' LIKE 'a!_c' ESCAPE '!'").as_integer().unwrap(),
+            0
+        );
+        assert_eq!(eval("'abc' NOT LIKE 'a_c'").as_integer().unwrap(), 0);
+        assert_eq!(
+            eval("'a!c' LIKE 'a!!c' ESCAPE '!'").as_integer().unwrap(),
+            1
+        );
+        assert_eq!(eval("'a' LIKE 'a' ESCAPE NULL").ty(), Type::Null);
+
+        let rows = run("SELECT region FROM sales WHERE region LIKE 'NO%'");
+        assert!(!rows.is_empty());
+        assert!(rows
+            .iter()
+            .all(|row| row["region"].as_text().unwrap() == "north"));
+        assert!(
+            Statement::prepare("SELECT region FROM sales WHERE region LIKE 'a' ESCAPE 'ab'")
+                .unwrap()
+                .bind(Params::None)
+                .unwrap()
+                .execute(&open_fixture("sales.db"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_lossy_text_decoding() {
+        let pager = open_doctored_fixture("sales.db", |bytes| {
+            // The first row's record header is [0, 23, 1], so its body starts
+            // with the text "north". Make the "n" invalid UTF-8.
+            let page = &mut bytes[4096..8192];
+            let cell = BigEndian::read_u16(&page[8..]) as usize;
+            assert_eq!(&page[cell + 6..cell + 11], b"north");
+            page[cell + 6] = 0xff;
+        });
+        let schema = Schema::new(pager).unwrap();
+        let select = Statement::prepare("SELECT id, region FROM sales ORDER BY id")
+            .unwrap()
+            .bind(Params::None)
+            .unwrap();
+        assert!(select.execute(&schema).is_err());
+
+        let rows = select
+            .with_text_decoding(TextDecoding::Lossy)
+            .execute(&schema)
+            .unwrap();
+        let regions: Vec<_> = rows
+            .iter()
+            .map(|row| row["region"].as_text().unwrap())
+            .collect();
+        assert_eq!(rows.len(), 11);
+        assert_eq!(regions[0], "\u{fffd}orth");
+        assert_eq!(regions[1], "north");
+    }
+
+    #[test]
+    fn test_order_by_collate_nocase() {
+        let op = Statement::prepare("SELECT name FROM t ORDER BY name COLLATE nocase DESC")
+            .unwrap()
+            .bind(Params::None)
+            .unwrap();
+        assert_eq!(op.order_by[0].collation, Some(Collation::NoCase));
+        let mut rows: Vec<_> = ["b", "A", "c", "a", "B", "C"]
+            .iter()
+            .map(|&name| {
+                let mut row = HashMap::new();
+                row.insert("name".to_owned(), Field::from(name));
+                row
+            })
+            .collect();
+        op.sort(&mut rows).unwrap();
+        let names: Vec<_> = rows
+            .iter()
+            .map(|row| row["name"].as_text().unwrap())
+            .collect();
+        assert_eq!(names, vec!["c", "C", "b", "B", "A", "a"]);
+
+        assert!(Statement::prepare("SELECT name FROM t ORDER BY name COLLATE klingon").is_err());
+    }
+
+    #[test]
+    fn test_negative_integers() {
+        let schema = open_fixture("signed.db");
+        let values = |sql| -> Vec<i64> {
+            query(&schema, sql)
+                .iter()
+                .map(|row| row["v"].as_signed_integer().unwrap())
+                .collect()
+        };
+        assert_eq!(values("SELECT v FROM nums ORDER BY v"), vec![-5, -1, 3, 10]);
+        assert_eq!(values("SELECT v FROM nums WHERE v < 0"), vec![-5, -1]);
+        assert_eq!(
+            values("SELECT v FROM nums WHERE v >= -1 ORDER BY v DESC"),
+            vec![10, 3, -1]
+        );
+
+        // Against reals, as well as integers.
+        assert_eq!(values("SELECT v FROM nums WHERE v < 2.5"), vec![-5, -1]);
+        assert_eq!(
+            values("SELECT v FROM nums WHERE v > -1.5 ORDER BY v"),
+            vec![-1, 3, 10]
+        );
+        assert_eq!(values("SELECT v FROM nums WHERE -4.5 > v"), vec![-5]);
+    }
+
+    #[test]
+    fn test_min_max_negative_integers() {
+        let schema = open_fixture("signed.db");
+        let rows = query(&schema, "SELECT MIN(v) lo, MAX(v) hi FROM nums WHERE v < 5");
+        assert_eq!(rows[0]["lo"].as_signed_integer().unwrap(), -5);
+        assert_eq!(rows[0]["hi"].as_signed_integer().unwrap(), 3);
+        let rows = query(&schema, "SELECT MAX(v) hi FROM nums");
+        assert_eq!(rows[0]["hi"].as_signed_integer().unwrap(), 10);
+    }
+
+    #[test]
+    fn test_union() {
+        let schema = open_fixture("views.db");
+        let run = |sql| query(&schema, sql);
+
+        // Each person added a row to audit.
+        let rows = run("SELECT id, name FROM people UNION ALL SELECT person, note FROM audit");
+        assert_eq!(rows.len(), 10);
+        assert!(rows
+            .iter()
+            .all(|row| row.len() == 2 && row.contains_key("name")));
+        let rows = run("SELECT note FROM audit UNION SELECT 'added' FROM people");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["note"].as_text().unwrap(), "added");
+
+        // ORDER BY and LIMIT apply to the whole compound.
+        let rows = run(
+            "SELECT name FROM people WHERE age < 18 UNION SELECT name FROM people WHERE age > 60 \
+             UNION SELECT name FROM people WHERE age < 13 ORDER BY name DESC LIMIT 2",
+        );
+        let names: Vec<_> = rows
+            .iter()
+            .map(|row| row["name"].as_text().unwrap())
+            .collect();
+        assert_eq!(names, vec!["eve", "dan"]);
+
+        assert!(
+            Statement::prepare("SELECT id FROM people UNION SELECT person, note FROM audit")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_limit_stops_reading() {
+        let schema = open_fixture("large.db");
+        let depth = schema.table("numbers").unwrap().depth().unwrap();
+        let pages = Rc::new(RefCell::new(Vec::new()));
+        let captured = pages.clone();
+        schema.pager().set_tracer(move |event| {
+            // Page 1 holds the schema, which every query reads.
+            if let TraceEvent::PageRead { number, page_type } = *event {
+                if number != 1 {
+                    captured.borrow_mut().push(page_type);
+                }
+            }
+        });
+        let read = |sql: &str| {
+            pages.borrow_mut().clear();
+            let rows = query(&schema, sql);
+            (rows, pages.borrow().clone())
+        };
+
+        // Only the way down to the first row is read, and a single leaf.
+        let (rows, read_pages) = read("SELECT id, value, label FROM numbers LIMIT 1");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["id"].as_integer().unwrap(), 1);
+        assert_eq!(read_pages.len(), depth);
+        assert_eq!(read_pages.iter().filter(|&&ty| ty == 0x0d).count(), 1);
+
+        let (rows, read_pages) = read("SELECT id FROM numbers WHERE id > 2500 LIMIT 1");
+        assert_eq!(rows[0]["id"].as_integer().unwrap(), 2501);
+        assert_eq!(read_pages.len(), depth);
+        let (rows, read_pages) = read("SELECT id FROM numbers WHERE value = 14 LIMIT 1");
+        assert_eq!(rows[0]["id"].as_integer().unwrap(), 2);
+        assert_eq!(read_pages.len(), depth);
+
+        // OFFSET rows are read, but nothing after them.
+        let (rows, _) = read("SELECT id FROM numbers LIMIT 2 OFFSET 3");
+        let ids: Vec<_> = rows
+            .iter()
+            .map(|row| row["id"].as_integer().unwrap())
+            .collect();
+        assert_eq!(ids, vec![4, 5]);
+        let (rows, read_pages) = read("SELECT id FROM numbers LIMIT 0");
+        assert!(rows.is_empty());
+        assert!(!read_pages.contains(&0x0d));
+
+        // Sorting needs every row.
+        let (rows, read_pages) = read("SELECT id FROM numbers ORDER BY value DESC LIMIT 1");
+        assert_eq!(rows.len(), 1);
+        assert!(read_pages.len() > depth);
+    }
+
+    #[test]
+    fn test_trace() {
+        let schema = open_fixture("indexed.db");
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let captured = events.clone();
+        schema.pager().set_tracer(move |event| {
+            let event = match *event {
+                TraceEvent::PageRead { .. } => "page".to_owned(),
+                ref event => format!("{:?}", event),
+            };
+            captured.borrow_mut().push(event);
+        });
+
+        query(&schema, "SELECT name FROM items WHERE price = 37");
+        let events = events.borrow();
+        assert_eq!(events[0], r#"Query { table: "items" }"#);
+        assert!(
+            events.contains(&r#"Plan { table: "items", index: Some("items_price") }"#.to_owned())
+        );
+        assert!(events.contains(&"page".to_owned()));
+        assert_eq!(
+            events.last().unwrap(),
+            r#"Rows { table: "items", rows: 1 }"#
+        );
+    }
+
+    #[test]
+    fn test_pragma_table_info() {
+        let schema = open_fixture("views.db");
+        let mut rows = query(&schema, "PRAGMA table_info(people)");
+        assert_eq!(rows.len(), 3);
+        rows.sort_by_key(|row| row["cid"].as_integer().unwrap());
+
+        let id = &rows[0];
+        assert_eq!(id["name"].as_text().unwrap(), "id");
+        assert_eq!(id["type"].as_text().unwrap(), "integer");
+        assert_eq!(id["notnull"].as_integer().unwrap(), 0);
+        assert_eq!(id["dflt_value"].ty(), Type::Null);
+        assert_eq!(id["pk"].as_integer().unwrap(), 1);
+        let name = &rows[1];
+        assert_eq!(name["cid"].as_integer().unwrap(), 1);
+        assert_eq!(name["name"].as_text().unwrap(), "name");
+        assert_eq!(name["type"].as_text().unwrap(), "text");
+        assert_eq!(name["pk"].as_integer().unwrap(), 0);
+        let mut columns: Vec<_> = id.keys().cloned().collect();
+        columns.sort();
+        let mut names = Statement::prepare("PRAGMA table_info(people)")
+            .unwrap()
+            .column_names();
+        names.sort();
+        assert_eq!(columns, names);
+
+        assert!(Statement::prepare("PRAGMA main.table_info('people')").is_ok());
+        assert!(Statement::prepare("PRAGMA journal_mode").is_err());
+        let stmt = Statement::prepare("PRAGMA table_info(missing)").unwrap();
+        assert!(stmt.execute(&schema, Params::None).is_err());
+
+        // Generated columns are left out, without leaving gaps in the cids.
+        let mut rows = query(&open_fixture("generated.db"), "PRAGMA table_info(shapes)");
+        rows.sort_by_key(|row| row["cid"].as_integer().unwrap());
+        let columns: Vec<_> = rows
+            .iter()
+            .map(|row| {
+                (
+                    row["cid"].as_integer().unwrap(),
+                    row["name"].as_text().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(columns, vec![(0, "side"), (1, "name")]);
+    }
+
+    #[test]
+    fn test_pragma_index_list_and_info() {
+        let schema = open_fixture("composite.db");
+        let run = |sql| query(&schema, sql);
+
+        let mut indices = run("PRAGMA index_list(visits)");
+        indices.sort_by_key(|row| row["seq"].as_integer().unwrap());
+        let names: Vec<_> = indices
+            .iter()
+            .map(|row| row["name"].as_text().unwrap())
+            .collect();
+        assert_eq!(names, vec!["visits_site_day", "visits_day"]);
+        assert!(indices
+            .iter()
+            .all(|row| row["unique"].as_integer().unwrap() == 0
+                && row["partial"].as_integer().unwrap() == 0));
+
+        let mut columns = run("PRAGMA index_info(visits_site_day)");
+        columns.sort_by_key(|row| row["seqno"].as_integer().unwrap());
+        let columns: Vec<_> = columns
+            .iter()
+            .map(|row| {
+                (
+                    row["cid"].as_integer().unwrap(),
+                    row["name"].as_text().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(columns, vec![(1, "site"), (2, "day")]);
+
+        let stmt = Statement::prepare("PRAGMA index_info(missing)").unwrap();
+        assert!(stmt.execute(&schema, Params::None).is_err());
+        let stmt = Statement::prepare("PRAGMA index_list(missing)").unwrap();
+        assert!(stmt.execute(&schema, Params::None).is_err());
+
+        // Indices made for constraints say which kind.
+        let mut indices = query(&open_fixture("constraints.db"), "PRAGMA index_list(codes)");
+        indices.sort_by_key(|row| row["name"].as_text().unwrap().to_owned());
+        let origins: Vec<_> = indices
+            .iter()
+            .map(|row| {
+                (
+                    row["name"].as_text().unwrap(),
+                    row["origin"].as_text().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            origins,
+            vec![
+                ("codes_n", "c"),
+                ("sqlite_autoindex_codes_1", "u"),
+                ("sqlite_autoindex_codes_2", "pk"),
+                ("sqlite_autoindex_codes_3", "u"),
+            ]
+        );
+        assert!(indices
+            .iter()
+            .all(|row| row["origin"].as_text().unwrap() == "c"
+                || row["unique"].as_integer().unwrap() == 1));
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::{Ordering, PartialOrd};
 use std::fmt;
 use std::io::Cursor;
@@ -11,7 +12,7 @@ use bytes::Bytes;
 
 use crate::errors::*;
 use crate::types::Type;
-use crate::util::read_varint;
+use crate::util::{read_varint, write_varint};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum FieldType {
@@ -30,6 +31,81 @@ enum FieldType {
 }
 
 impl FieldType {
+    fn from_serial(serial: u64) -> Result<FieldType> {
+        Ok(match serial {
+            0 => FieldType::Null,
+            1 => FieldType::U8,
+            2 => FieldType::U16,
+            3 => FieldType::U24,
+            4 => FieldType::U32,
+            5 => FieldType::U48,
+            6 => FieldType::U64,
+            7 => FieldType::F64,
+            8 => FieldType::Zero,
+            9 => FieldType::One,
+            s @ 10 | s @ 11 => bail!(ErrorKind::Unsupported(format!(
+                "reserved serial type {} in record",
+                s
+            ))),
+            n if n >= 12 && (n % 2 == 0) => FieldType::Blob(((n as usize) - 12) / 2),
+            n if n >= 13 && (n % 2 == 1) => FieldType::Str(((n as usize) - 13) / 2),
+            // The compiler can't tell the above is an exhaustive match:
+            _ => unreachable!(),
+        })
+    }
+
+    // The serial type SQLite would store `field` with: the narrowest that
+    // can hold it, with 0 and 1 taking up no space at all.
+    fn of(field: &Field) -> FieldType {
+        match *field {
+            Field::Literal(LiteralValue::Null) => FieldType::Null,
+            // XXX Field doesn't know integers are signed yet.
+            Field::Literal(LiteralValue::Integer(i)) => match i as i64 {
+                0 => FieldType::Zero,
+                1 => FieldType::One,
+                i if (-(1 << 7)..1 << 7).contains(&i) => FieldType::U8,
+                i if (-(1 << 15)..1 << 15).contains(&i) => FieldType::U16,
+                i if (-(1 << 23)..1 << 23).contains(&i) => FieldType::U24,
+                i if (-(1 << 31)..1 << 31).contains(&i) => FieldType::U32,
+                i if (-(1 << 47)..1 << 47).contains(&i) => FieldType::U48,
+                _ => FieldType::U64,
+            },
+            Field::Literal(LiteralValue::Float(_)) => FieldType::F64,
+            Field::Literal(LiteralValue::Blob(ref vec)) => FieldType::Blob(vec.len()),
+            Field::Literal(LiteralValue::Str(ref string)) => FieldType::Str(string.len()),
+            Field::Lazy(LazyValue::Blob(ref bytes)) => FieldType::Blob(bytes.len()),
+            Field::Lazy(LazyValue::Str(ref bytes)) => FieldType::Str(bytes.len()),
+        }
+    }
+
+    fn serial(&self) -> u64 {
+        match *self {
+            FieldType::Null => 0,
+            FieldType::U8 => 1,
+            FieldType::U16 => 2,
+            FieldType::U24 => 3,
+            FieldType::U32 => 4,
+            FieldType::U48 => 5,
+            FieldType::U64 => 6,
+            FieldType::F64 => 7,
+            FieldType::Zero => 8,
+            FieldType::One => 9,
+            FieldType::Blob(len) => 12 + 2 * len as u64,
+            FieldType::Str(len) => 13 + 2 * len as u64,
+        }
+    }
+
+    fn ty(&self) -> Type {
+        use self::FieldType::*;
+        match *self {
+            Null => Type::Null,
+            U8 | U16 | U24 | U32 | U48 | U64 | Zero | One => Type::Integer,
+            F64 => Type::Float,
+            Blob(_) => Type::Blob,
+            Str(_) => Type::Text,
+        }
+    }
+
     fn size_of(&self) -> usize {
         use self::FieldType::*;
         match *self {
@@ -70,32 +146,28 @@ pub enum Field {
     Literal(LiteralValue),
 }
 
+/// The type of the value a record header's serial type describes, and how
+/// many bytes of the record's body it takes up.
+pub fn serial_type(serial: u64) -> Result<(Type, usize)> {
+    let ty = FieldType::from_serial(serial)?;
+    Ok((ty.ty(), ty.size_of()))
+}
+
 impl Field {
     fn from_bytes(ty: FieldType, bytes: Bytes) -> Field {
         match ty {
             FieldType::Null => Field::Literal(LiteralValue::Null),
-            FieldType::U8 => Field::Literal(LiteralValue::Integer(bytes[0] as u64)),
-            FieldType::U16 => {
-                Field::Literal(LiteralValue::Integer(BigEndian::read_u16(&bytes) as u64))
-            }
-            FieldType::U24 => Field::Literal(LiteralValue::Integer(
-                (((bytes[0] as u64) << 16) | ((bytes[1] as u64) << 8) | (bytes[2] as u64)) as u64,
-            )),
-            FieldType::U32 => {
-                Field::Literal(LiteralValue::Integer(BigEndian::read_u32(&bytes) as u64))
+            // "Value is a big-endian N-bit twos-complement integer", so the
+            // narrower widths must be sign-extended.
+            // XXX Field doesn't know integers are signed yet.
+            FieldType::U8 | FieldType::U16 | FieldType::U24 | FieldType::U32 | FieldType::U48 => {
+                let n = ty.size_of();
+                Field::Literal(LiteralValue::Integer(BigEndian::read_int(&bytes, n) as u64))
             }
-            FieldType::U48 => Field::Literal(LiteralValue::Integer(
-                (((bytes[0] as u64) << 40)
-                    | ((bytes[1] as u64) << 32)
-                    | ((bytes[2] as u64) << 24)
-                    | ((bytes[3] as u64) << 16)
-                    | ((bytes[4] as u64) << 8)
-                    | (bytes[5] as u64)) as u64,
-            )),
             FieldType::U64 => Field::Literal(LiteralValue::Integer(BigEndian::read_u64(&bytes))),
-            FieldType::F64 => Field::Literal(LiteralValue::Float(BigEndian::read_f64(&bytes))),
-            FieldType::Zero => Field::Literal(LiteralValue::Integer(0 as u64)),
-            FieldType::One => Field::Literal(LiteralValue::Integer(1 as u64)),
+            FieldType::F64 => Field::from(BigEndian::read_f64(&bytes)),
+            FieldType::Zero => Field::Literal(LiteralValue::Integer(0)),
+            FieldType::One => Field::Literal(LiteralValue::Integer(1)),
             FieldType::Blob(_) => Field::Lazy(LazyValue::Blob(bytes)),
             FieldType::Str(_) => Field::Lazy(LazyValue::Str(bytes)),
         }
@@ -131,6 +203,13 @@ impl Field {
         }
     }
 
+    /// The integer as the signed value SQLite stores. Integers are kept as
+    /// the `u64` with the same bits, so this is what `as_integer()` gives
+    /// for a negative value.
+    pub fn as_signed_integer(&self) -> Result<i64> {
+        Ok(self.as_integer()? as i64)
+    }
+
     pub fn as_float(&self) -> Result<f64> {
         match *self {
             Field::Literal(LiteralValue::Float(f)) => Ok(f),
@@ -138,6 +217,20 @@ impl Field {
         }
     }
 
+    /// The bytes of a text or blob field as a `Bytes`, which can outlive the
+    /// field. Fields read from a record share the page's buffer, so this
+    /// doesn't copy them (although `Bytes` copies very short ones anyway).
+    pub fn to_bytes(&self) -> Result<Bytes> {
+        match *self {
+            Field::Lazy(LazyValue::Blob(ref bytes)) | Field::Lazy(LazyValue::Str(ref bytes)) => {
+                Ok(bytes.clone())
+            }
+            Field::Literal(LiteralValue::Blob(ref vec)) => Ok(Bytes::from(&vec[..])),
+            Field::Literal(LiteralValue::Str(ref string)) => Ok(Bytes::from(string.as_bytes())),
+            _ => Err(ErrorKind::UnexpectedType(Type::Blob, self.ty()).into()),
+        }
+    }
+
     pub fn as_blob(&self) -> Result<&[u8]> {
         match *self {
             Field::Literal(LiteralValue::Blob(ref vec)) => Ok(vec),
@@ -148,12 +241,241 @@ impl Field {
 
     pub fn as_text(&self) -> Result<&str> {
         match *self {
-            Field::Literal(LiteralValue::Str(ref string)) => Ok(&string),
+            Field::Literal(LiteralValue::Str(ref string)) => Ok(string),
             // XXX Not necessarily UTF-8.
             Field::Lazy(LazyValue::Str(ref bytes)) => Ok(str::from_utf8(bytes)?),
             _ => Err(ErrorKind::UnexpectedType(Type::Text, self.ty()).into()),
         }
     }
+
+    /// Like `as_text()`, but replaces any invalid UTF-8 rather than failing,
+    /// so that text with the wrong encoding can still be read.
+    pub fn as_text_lossy(&self) -> Result<Cow<'_, str>> {
+        Ok(String::from_utf8_lossy(self.as_text_bytes()?))
+    }
+
+    /// The bytes of a text field, without checking they're UTF-8.
+    pub fn as_text_bytes(&self) -> Result<&[u8]> {
+        match *self {
+            Field::Literal(LiteralValue::Str(ref string)) => Ok(string.as_bytes()),
+            Field::Lazy(LazyValue::Str(ref bytes)) => Ok(bytes),
+            _ => Err(ErrorKind::UnexpectedType(Type::Text, self.ty()).into()),
+        }
+    }
+
+    /// The field as a JSON value. JSON has no blobs, so they're written as a
+    /// string of hex digits, and non-finite reals become `null`.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(match self.ty() {
+            Type::Null => "null".to_owned(),
+            Type::Integer => self.to_text()?,
+            Type::Float if self.as_float()?.is_finite() => self.to_text()?,
+            Type::Float => "null".to_owned(),
+            Type::Text => json_string(&self.as_text_lossy()?),
+            Type::Blob => {
+                let hex: String = self
+                    .as_blob()?
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                json_string(&hex)
+            }
+        })
+    }
+
+    /// Interprets the field as a condition, as SQLite does for `WHERE x`:
+    /// zero is false and any other number is true. Text and blobs are first
+    /// converted to a number using their longest numeric prefix, so `'1abc'`
+    /// is true and `'abc'` is false. NULL is unknown, which never passes a
+    /// filter, so is false.
+    pub fn as_bool(&self) -> Result<bool> {
+        Ok(match self.ty() {
+            Type::Null => false,
+            Type::Integer => self.as_integer()? != 0,
+            Type::Float => self.as_float()? != 0.0,
+            Type::Text => numeric_prefix(self.as_text_bytes()?) != 0.0,
+            Type::Blob => numeric_prefix(self.as_blob()?) != 0.0,
+        })
+    }
+
+    /// The field converted to text, as for `||`: numbers are formatted and
+    /// a blob's bytes are read as UTF-8. NULL has no text.
+    pub fn to_text(&self) -> Result<String> {
+        Ok(match self.ty() {
+            Type::Null => bail!(ErrorKind::UnexpectedType(Type::Text, Type::Null)),
+            // XXX Field doesn't know integers are signed yet.
+            Type::Integer => (self.as_integer()? as i64).to_string(),
+            // SQLite always shows a real's decimal point, so 2.0 isn't "2".
+            Type::Float => format!("{:?}", self.as_float()?),
+            Type::Text => self.as_text_lossy()?.into_owned(),
+            Type::Blob => String::from_utf8_lossy(self.as_blob()?).into_owned(),
+        })
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// The value of the longest prefix of `bytes` that looks like a number
+// (ignoring leading whitespace), or 0.0 if there isn't one.
+pub fn numeric_prefix(bytes: &[u8]) -> f64 {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_start();
+    let mut end = 0;
+    let mut seen_digit = false;
+    let mut seen_dot = false;
+    let mut seen_exp = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '+' | '-' if i == 0 => {}
+            '0'..='9' => {
+                seen_digit = true;
+                end = i + 1;
+                continue;
+            }
+            '.' if !seen_dot && !seen_exp => seen_dot = true,
+            'e' | 'E' if seen_digit && !seen_exp => seen_exp = true,
+            '+' | '-' if text[..i].ends_with(['e', 'E']) => {}
+            _ => break,
+        }
+    }
+    text[..end].parse().unwrap_or(0.0)
+}
+
+// The value of the longest prefix of `bytes` that's an integer (ignoring
+// leading whitespace), or 0 if there isn't one. Integers too big for 64 bits
+// are clamped to the largest or smallest there is, as SQLite does.
+pub fn integer_prefix(bytes: &[u8]) -> i64 {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_start();
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    digits
+        .bytes()
+        .take_while(u8::is_ascii_digit)
+        .fold(0i64, |value, c| {
+            let digit = i64::from(c - b'0');
+            if negative {
+                value.saturating_mul(10).saturating_sub(digit)
+            } else {
+                value.saturating_mul(10).saturating_add(digit)
+            }
+        })
+}
+
+/// A hashable stand-in for a `Field`, used to group and de-duplicate rows.
+/// Fields which compare equal have equal keys.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum FieldKey {
+    Null,
+    Integer(u64),
+    // The bits of a float with a fractional part. Whole floats are keyed as
+    // integers so that they group with the equivalent integer.
+    Float(u64),
+    Blob(Vec<u8>),
+    Text(Vec<u8>),
+}
+
+impl Field {
+    pub fn key(&self) -> FieldKey {
+        match *self {
+            Field::Literal(ref lit) => match *lit {
+                LiteralValue::Null => FieldKey::Null,
+                LiteralValue::Integer(i) => FieldKey::Integer(i),
+                LiteralValue::Float(f) => match canonical_float(f) {
+                    None => FieldKey::Null,
+                    Some(f) if f.fract() == 0.0 && f.abs() < 9.2e18 => {
+                        FieldKey::Integer(f as i64 as u64)
+                    }
+                    Some(f) => FieldKey::Float(f.to_bits()),
+                },
+                LiteralValue::Blob(ref vec) => FieldKey::Blob(vec.clone()),
+                LiteralValue::Str(ref string) => FieldKey::Text(string.as_bytes().to_vec()),
+            },
+            Field::Lazy(ref lazy) => match *lazy {
+                LazyValue::Blob(ref bytes) => FieldKey::Blob(bytes.to_vec()),
+                LazyValue::Str(ref bytes) => FieldKey::Text(bytes.to_vec()),
+            },
+        }
+    }
+
+    /// The field as a column with `affinity` would store it. INTEGER and
+    /// REAL columns store text that looks like a number as that number (a
+    /// whole number as an integer, in an INTEGER column), and REAL columns
+    /// store integers as reals. Any other field, or affinity, is unchanged.
+    pub fn with_affinity(self, affinity: Type) -> Field {
+        let number = match (affinity, self.ty()) {
+            (Type::Integer, Type::Text) | (Type::Float, Type::Text) => match self.as_text_lossy() {
+                Ok(ref text) if looks_numeric(text) => text.trim().parse::<f64>().ok(),
+                _ => None,
+            },
+            // XXX Field doesn't know integers are signed yet.
+            (Type::Float, Type::Integer) => self.as_signed_integer().ok().map(|i| i as f64),
+            _ => None,
+        };
+        match number {
+            Some(f) if affinity == Type::Integer && f.fract() == 0.0 && f.abs() < 9.2e18 => {
+                Field::from(f as i64 as u64)
+            }
+            Some(f) => Field::from(f),
+            None => self,
+        }
+    }
+}
+
+/// Whether `text` is a number, as written in SQL, with nothing else but
+/// surrounding whitespace.
+pub fn looks_numeric(text: &str) -> bool {
+    let text = text.trim();
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+        && text.parse::<f64>().is_ok()
+}
+
+// SQLite never stores a NaN: it stores NULL in its place, and reads a NaN
+// in a record as NULL too. So a NaN is either a corrupt record or the result
+// of arithmetic like `inf - inf`, which SQLite gives NULL for, and either way
+// it's NULL to us. -0.0 equals 0.0, and is made 0.0 so that the two group,
+// hash and print the same.
+fn canonical_float(f: f64) -> Option<f64> {
+    if f.is_nan() {
+        None
+    } else if f == 0.0 {
+        Some(0.0)
+    } else {
+        Some(f)
+    }
+}
+
+/// A float field, after normalising it as described by `canonical_float()`:
+/// NaN becomes NULL, and -0.0 becomes 0.0.
+impl From<f64> for Field {
+    fn from(value: f64) -> Field {
+        Field::Literal(match canonical_float(value) {
+            Some(f) => LiteralValue::Float(f),
+            None => LiteralValue::Null,
+        })
+    }
 }
 
 impl From<u64> for Field {
@@ -162,7 +484,13 @@ impl From<u64> for Field {
     }
 }
 
-impl<'a> From<&'a str> for Field {
+impl From<String> for Field {
+    fn from(value: String) -> Field {
+        Field::Literal(LiteralValue::Str(value))
+    }
+}
+
+impl From<&str> for Field {
     fn from(value: &str) -> Field {
         Field::Literal(LiteralValue::Str(value.to_owned()))
     }
@@ -173,10 +501,14 @@ impl PartialEq for Field {
     fn eq(&self, other: &Field) -> bool {
         let result = match self.ty() {
             Type::Null => other.as_null().map(|_| true),
-            Type::Integer => other.as_integer().map(|o| self.as_integer().unwrap() == o),
+            Type::Integer => other
+                .as_signed_integer()
+                .map(|o| self.as_signed_integer().unwrap() == o),
             Type::Float => other.as_float().map(|o| self.as_float().unwrap() == o),
             Type::Blob => other.as_blob().map(|o| self.as_blob().unwrap() == o),
-            Type::Text => other.as_text().map(|o| self.as_text().unwrap() == o),
+            Type::Text => other
+                .as_text_bytes()
+                .map(|o| self.as_text_bytes().unwrap() == o),
         };
         result.expect("Unimplemented: proper affinity types in Field comparisons")
     }
@@ -187,8 +519,8 @@ impl PartialOrd for Field {
         let result = match self.ty() {
             Type::Null => other.as_null().map(|_| Some(Ordering::Equal)),
             Type::Integer => other
-                .as_integer()
-                .map(|o| self.as_integer().unwrap().partial_cmp(&o)),
+                .as_signed_integer()
+                .map(|o| self.as_signed_integer().unwrap().partial_cmp(&o)),
             Type::Float => other
                 .as_float()
                 .map(|o| self.as_float().unwrap().partial_cmp(&o)),
@@ -196,8 +528,8 @@ impl PartialOrd for Field {
                 .as_blob()
                 .map(|o| self.as_blob().unwrap().partial_cmp(o)),
             Type::Text => other
-                .as_text()
-                .map(|o| self.as_text().unwrap().partial_cmp(o)),
+                .as_text_bytes()
+                .map(|o| self.as_text_bytes().unwrap().partial_cmp(o)),
         };
         result.expect("Unimplemented: proper affinity types in Field comparisons")
     }
@@ -226,7 +558,7 @@ impl fmt::Debug for Field {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Record {
     fields: Vec<Field>,
 }
@@ -239,27 +571,25 @@ impl Record {
     pub fn from_bytes(bytes: Bytes) -> Result<Record> {
         let mut cursor = Cursor::new(bytes);
         let header_size = read_varint(&mut cursor)?;
+        if header_size > cursor.get_ref().len() as u64 {
+            bail!(
+                "Record header of {} bytes extends past end of payload",
+                header_size
+            );
+        }
 
         let mut field_types = Vec::new();
         while cursor.position() < header_size {
-            let ty = match read_varint(&mut cursor)? {
-                0 => FieldType::Null,
-                1 => FieldType::U8,
-                2 => FieldType::U16,
-                3 => FieldType::U24,
-                4 => FieldType::U32,
-                5 => FieldType::U48,
-                6 => FieldType::U64,
-                7 => FieldType::F64,
-                8 => FieldType::Zero,
-                9 => FieldType::One,
-                s @ 10 | s @ 11 => Err(format!("Use of reserved serial type {} in record", s))?,
-                n if n > 12 && (n % 2 == 0) => FieldType::Blob(((n as usize) - 12) / 2),
-                n if n > 13 && (n % 2 == 1) => FieldType::Str(((n as usize) - 13) / 2),
-                // The compiler can't tell the above is an exhaustive match:
-                _ => unreachable!(),
-            };
-            field_types.push(ty);
+            field_types.push(FieldType::from_serial(read_varint(&mut cursor)?)?);
+        }
+        // The last serial type must end exactly where the header does, or
+        // we've read part of the body as if it were the header.
+        if cursor.position() != header_size {
+            bail!(
+                "Record header should be {} bytes, but its serial types end at {}",
+                header_size,
+                cursor.position()
+            );
         }
 
         let mut offset = cursor.position() as usize;
@@ -268,22 +598,115 @@ impl Record {
             .into_iter()
             .map(|ty| {
                 let size_of = ty.size_of();
-                let field = Field::from_bytes(ty, bytes.slice(offset, offset + size_of));
-                offset += size_of;
-                field
+                let end = match offset.checked_add(size_of) {
+                    Some(end) if end <= bytes.len() => end,
+                    _ => bail!(
+                        "Record field of {} bytes extends past end of payload",
+                        size_of
+                    ),
+                };
+                let field = Field::from_bytes(ty, bytes.slice(offset, end));
+                offset = end;
+                Ok(field)
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
         Ok(Record { fields })
     }
 
+    /// Encodes the record as SQLite would: a header of the fields' serial
+    /// types, each the narrowest that holds its value, followed by a body
+    /// of their values. `from_bytes()` reads it back.
+    pub fn to_bytes(&self) -> Bytes {
+        let types: Vec<FieldType> = self.fields.iter().map(FieldType::of).collect();
+        let serials: Vec<u8> = types
+            .iter()
+            .flat_map(|ty| write_varint(ty.serial()))
+            .collect();
+        // The header's size includes the varint giving it, which may need
+        // more than a byte itself.
+        let mut header_size = serials.len() + 1;
+        while write_varint(header_size as u64).len() + serials.len() != header_size {
+            header_size = write_varint(header_size as u64).len() + serials.len();
+        }
+
+        let mut bytes = write_varint(header_size as u64);
+        bytes.extend(serials);
+        for (ty, field) in types.iter().zip(&self.fields) {
+            // The type was chosen from the field, so the field always has
+            // the value the type says.
+            match *ty {
+                FieldType::Null | FieldType::Zero | FieldType::One => {}
+                FieldType::F64 => {
+                    let mut buf = [0; 8];
+                    BigEndian::write_f64(&mut buf, field.as_float().unwrap());
+                    bytes.extend(&buf);
+                }
+                FieldType::Blob(_) => bytes.extend(field.as_blob().unwrap()),
+                FieldType::Str(_) => bytes.extend(field.as_text_bytes().unwrap()),
+                ty => {
+                    let n = ty.size_of();
+                    let mut buf = [0; 8];
+                    BigEndian::write_int(&mut buf, field.as_signed_integer().unwrap(), n);
+                    bytes.extend(&buf[..n]);
+                }
+            }
+        }
+        Bytes::from(bytes)
+    }
+
     pub fn len(&self) -> usize {
         self.fields.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
     pub fn iter(&self) -> slice::Iter<'_, Field> {
         self.fields.iter()
     }
+
+    pub fn into_fields(self) -> Vec<Field> {
+        self.fields
+    }
+}
+
+/// Shows each field with its storage class, such as `(3:int, "x":text, null)`,
+/// as a mismatch in types is often why two records don't compare as expected.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+        write!(f, "(")?;
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match field.ty() {
+                Type::Null => write!(f, "null")?,
+                Type::Integer => write!(f, "{}:int", field.to_text().map_err(|_| fmt::Error)?)?,
+                Type::Float => write!(f, "{}:real", field.to_text().map_err(|_| fmt::Error)?)?,
+                Type::Text => write!(
+                    f,
+                    "{:?}:text",
+                    field.as_text_lossy().map_err(|_| fmt::Error)?
+                )?,
+                Type::Blob => {
+                    write!(f, "x'")?;
+                    for byte in field.as_blob().map_err(|_| fmt::Error)? {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                    write!(f, "':blob")?;
+                }
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Debug for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+        write!(f, "Record{}", self)
+    }
 }
 
 impl Index<usize> for Record {
@@ -293,3 +716,232 @@ impl Index<usize> for Record {
         &self.fields[index]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_as_bool() {
+        assert!(!Field::from(0).as_bool().unwrap());
+        assert!(Field::from(1).as_bool().unwrap());
+        assert!(!Field::Literal(LiteralValue::Float(0.0)).as_bool().unwrap());
+        assert!(Field::Literal(LiteralValue::Float(0.5)).as_bool().unwrap());
+        assert!(!Field::Literal(LiteralValue::Null).as_bool().unwrap());
+    }
+
+    #[test]
+    fn test_as_bool_coerces_text() {
+        assert!(Field::from("1").as_bool().unwrap());
+        assert!(Field::from(" 2abc").as_bool().unwrap());
+        assert!(!Field::from("0.0").as_bool().unwrap());
+        assert!(Field::from("1e3").as_bool().unwrap());
+        assert!(!Field::from("abc").as_bool().unwrap());
+        assert!(!Field::from("").as_bool().unwrap());
+        let lazy = Field::Lazy(LazyValue::Str(Bytes::from(&b"-7"[..])));
+        assert!(lazy.as_bool().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_utf8_text() {
+        let field = Field::Lazy(LazyValue::Str(Bytes::from(&b"caf\xe9"[..])));
+        assert!(field.as_text().is_err());
+        assert_eq!(field.as_text_lossy().unwrap(), "caf\u{fffd}");
+        assert_eq!(field.as_text_bytes().unwrap(), b"caf\xe9");
+        assert!(Field::from(1).as_text_lossy().is_err());
+
+        // It can still be compared, byte by byte.
+        assert!(field == Field::Lazy(LazyValue::Str(Bytes::from(&b"caf\xe9"[..]))));
+        assert!(field > Field::from("caf"));
+        assert!(field < Field::from("caf\u{fffd}"));
+    }
+
+    #[test]
+    fn test_malformed_header_size() {
+        // A header of 3 bytes: its size, then serial types for a text of one
+        // byte (15) and an integer of one byte (1), followed by the body.
+        let record = Record::from_bytes(Bytes::from(&[3, 15, 1, b'a', 7][..])).unwrap();
+        assert_eq!(record[0].as_text().unwrap(), "a");
+        assert_eq!(record[1].as_integer().unwrap(), 7);
+
+        // The header size says 2 bytes, but the second serial type is a
+        // two-byte varint (0x81 0x00) which runs past it.
+        assert!(Record::from_bytes(Bytes::from(&[2, 0x81, 0x00, 0, 0][..])).is_err());
+        // A header size bigger than the whole payload.
+        assert!(Record::from_bytes(Bytes::from(&[9, 1, 1][..])).is_err());
+    }
+
+    #[test]
+    fn test_sign_extension() {
+        let decode = |serial_type: u8, body: &[u8]| {
+            let mut payload = vec![2, serial_type];
+            payload.extend_from_slice(body);
+            let record = Record::from_bytes(Bytes::from(payload)).unwrap();
+            record[0].as_signed_integer().unwrap()
+        };
+        // 24-bit integers (serial type 3).
+        assert_eq!(decode(3, &[0x7f, 0xff, 0xff]), 8_388_607);
+        assert_eq!(decode(3, &[0x80, 0x00, 0x00]), -8_388_608);
+        assert_eq!(decode(3, &[0xff, 0xff, 0xfe]), -2);
+        // 48-bit integers (serial type 5).
+        assert_eq!(
+            decode(5, &[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            (1 << 47) - 1
+        );
+        assert_eq!(decode(5, &[0x80, 0, 0, 0, 0, 0]), -(1 << 47));
+        assert_eq!(decode(5, &[0xff; 6]), -1);
+        // And the other widths.
+        assert_eq!(decode(1, &[0xff]), -1);
+        assert_eq!(decode(2, &[0x80, 0x00]), -32768);
+        assert_eq!(decode(4, &[0xff, 0xff, 0xff, 0x9c]), -100);
+        assert_eq!(decode(6, &[0xff; 8]), -1);
+    }
+
+    #[test]
+    fn test_compare_negative_integers() {
+        let minus_one = Field::from(-1i64 as u64);
+        assert!(minus_one < Field::from(0));
+        assert!(Field::from(-5i64 as u64) < minus_one);
+        assert_eq!(minus_one, Field::from(u64::MAX));
+    }
+
+    #[test]
+    fn test_field_past_end_of_payload() {
+        // A blob of 10 bytes (serial type 32), with only 3 bytes of body.
+        let err = Record::from_bytes(Bytes::from(&[2, 32, 1, 2, 3][..])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Record field of 10 bytes extends past end of payload"
+        );
+        // A text field claiming to be about 2^34 bytes long.
+        let payload = &[6, 0x8f, 0xff, 0xff, 0xff, 0x7f, b'a'][..];
+        assert!(Record::from_bytes(Bytes::from(payload)).is_err());
+        // A one-byte blob (14) after a one-byte integer, which only fits if
+        // the payload has the blob's byte too.
+        assert!(Record::from_bytes(Bytes::from(&[3, 1, 14, 7][..])).is_err());
+        assert!(Record::from_bytes(Bytes::from(&[3, 1, 14, 7, 0][..])).is_ok());
+    }
+
+    #[test]
+    fn test_float_normalization() {
+        // A header of 3 bytes, then two 8-byte floats (serial type 7): -0.0,
+        // and a NaN, which SQLite would never have written.
+        let mut payload = vec![3, 7, 7];
+        payload.extend_from_slice(&(-0.0f64).to_bits().to_be_bytes());
+        payload.extend_from_slice(&0x7ff8_0000_dead_beefu64.to_be_bytes());
+        let record = Record::from_bytes(Bytes::from(payload)).unwrap();
+
+        match record[0] {
+            Field::Literal(LiteralValue::Float(f)) => assert!(f == 0.0 && f.is_sign_positive()),
+            ref field => panic!("Expected a float, got {:?}", field),
+        }
+        assert_eq!(record[0].key(), Field::from(0.0).key());
+        assert_eq!(record[1], Field::Literal(LiteralValue::Null));
+        assert_eq!(record[1].key(), FieldKey::Null);
+
+        assert_eq!(Field::from(-0.0).key(), Field::from(0).key());
+        assert_eq!(Field::from(f64::NAN), Field::Literal(LiteralValue::Null));
+        // A NaN that was built directly, rather than normalized, keys as NULL.
+        assert_eq!(
+            Field::Literal(LiteralValue::Float(f64::NAN)).key(),
+            FieldKey::Null
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(Field::from(42).to_json().unwrap(), "42");
+        assert_eq!(
+            Field::Literal(LiteralValue::Float(2.0)).to_json().unwrap(),
+            "2.0"
+        );
+        assert_eq!(
+            Field::Literal(LiteralValue::Null).to_json().unwrap(),
+            "null"
+        );
+        assert_eq!(
+            Field::from("say \"hi\"\n\u{1}").to_json().unwrap(),
+            r#""say \"hi\"\n\u0001""#
+        );
+        assert_eq!(
+            Field::Literal(LiteralValue::Blob(vec![0x0a, 0xff]))
+                .to_json()
+                .unwrap(),
+            r#""0aff""#
+        );
+    }
+
+    #[test]
+    fn test_display_record() {
+        let record = Record::new(vec![
+            Field::from(3),
+            Field::from("x"),
+            Field::Literal(LiteralValue::Null),
+            Field::Literal(LiteralValue::Float(1.5)),
+            Field::Literal(LiteralValue::Blob(vec![0x0a, 0xff])),
+            Field::Lazy(LazyValue::Str(Bytes::from(&b"say \"hi\""[..]))),
+        ]);
+        assert_eq!(
+            record.to_string(),
+            r#"(3:int, "x":text, null, 1.5:real, x'0aff':blob, "say \"hi\"":text)"#
+        );
+        assert_eq!(format!("{:?}", Record::new(vec![])), "Record()");
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let integers = [
+            0i64,
+            1,
+            2,
+            -1,
+            127,
+            128,
+            -129,
+            32767,
+            32768,
+            -8_388_609,
+            1 << 31,
+            -(1 << 31),
+            (1 << 47) - 1,
+            1 << 47,
+            i64::MIN,
+            i64::MAX,
+        ];
+        let mut fields: Vec<Field> = integers.iter().map(|&i| Field::from(i as u64)).collect();
+        fields.push(Field::Literal(LiteralValue::Null));
+        fields.push(Field::from(1.5));
+        fields.push(Field::from(""));
+        fields.push(Field::from("x".repeat(100)));
+        fields.push(Field::Literal(LiteralValue::Blob(vec![0, 0xff])));
+        let record = Record::new(fields);
+
+        let bytes = record.to_bytes();
+        let header: Vec<u64> = {
+            let mut cursor = Cursor::new(bytes.clone());
+            let header_size = read_varint(&mut cursor).unwrap();
+            let mut serials = vec![];
+            while cursor.position() < header_size {
+                serials.push(read_varint(&mut cursor).unwrap());
+            }
+            serials
+        };
+        assert_eq!(
+            header,
+            vec![8, 9, 1, 1, 1, 2, 2, 2, 3, 4, 5, 4, 5, 6, 6, 6, 0, 7, 13, 213, 16]
+        );
+
+        let decoded = Record::from_bytes(bytes).unwrap();
+        assert_eq!(decoded.to_string(), record.to_string());
+        assert_eq!(decoded.to_bytes(), record.to_bytes());
+        assert_eq!(&Record::new(vec![]).to_bytes()[..], &[1]);
+
+        // SQLite chooses the same serial types, so its records come back
+        // byte for byte.
+        let schema = crate::testing::open_fixture("large.db");
+        for cell in schema.table("numbers").unwrap().raw_cells().unwrap() {
+            let record = Record::from_bytes(cell.payload.clone()).unwrap();
+            assert_eq!(record.to_bytes(), cell.payload);
+        }
+    }
+}
@@ -1,11 +1,14 @@
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::errors::*;
-use crate::index::Index;
+use crate::index::{index_columns, Index, IndexKind, IndexStats};
 use crate::pager::Pager;
+use crate::record::Field;
 use crate::table::Table;
+use crate::types::Type;
 
-const SQLITE_MASTER_SCHEMA: &'static str = "
+const SQLITE_MASTER_SCHEMA: &str = "
     CREATE TABLE sqlite_master(
         type text,
         name text,
@@ -15,6 +18,47 @@ const SQLITE_MASTER_SCHEMA: &'static str = "
     );
 "; // XXX should be "rootpage integer" but there's a bug in nom-sql
 
+// ANALYZE creates sqlite_stat1 with untyped columns, which nom-sql can't
+// parse, so we use this in its place.
+const SQLITE_STAT1_SCHEMA: &str = "
+    CREATE TABLE sqlite_stat1(
+        tbl text,
+        idx text,
+        stat text
+    );
+";
+
+// Likewise for sqlite_sequence, which SQLite creates for AUTOINCREMENT.
+const SQLITE_SEQUENCE_SCHEMA: &str = "
+    CREATE TABLE sqlite_sequence(
+        name text,
+        seq int
+    );
+";
+
+/// A view, which has no b-tree of its own: it's a stored SELECT.
+#[derive(Debug)]
+pub struct View {
+    pub name: String,
+    pub sql: String,
+}
+
+#[derive(Debug)]
+pub struct Trigger {
+    pub name: String,
+    pub tbl_name: String,
+    pub sql: String,
+}
+
+/// Anything in the schema that can be named.
+#[derive(Debug)]
+pub enum SchemaObject {
+    Table(Table),
+    Index(Index),
+    View(View),
+    Trigger(Trigger),
+}
+
 pub struct Schema {
     pager: Rc<Pager>,
     schema_table: Table,
@@ -30,33 +74,155 @@ impl Schema {
         })
     }
 
-    pub fn indices(&self) -> Result<Vec<Index>> {
+    pub fn pager(&self) -> &Rc<Pager> {
+        &self.pager
+    }
+
+    // The rows of sqlite_master, which describe everything in the schema.
+    fn catalog(&self) -> Result<Vec<HashMap<String, Field>>> {
         self.schema_table
-            .select(vec!["type", "name", "tbl_name", "rootpage", "sql"])?
+            .select(vec!["type", "name", "tbl_name", "rootpage", "sql"])
+    }
+
+    pub fn indices(&self) -> Result<Vec<Index>> {
+        let tables = self.tables()?;
+        self.catalog()?
             .iter()
-            .filter(|row| row["type"].as_text().unwrap_or("") == "index")
+            .filter(|row| is_type(row, "index") && has_btree(row))
             .map(|row| {
-                Index::new(
-                    self.pager.clone(),
-                    row["rootpage"].as_integer()? as usize,
-                    row["tbl_name"].as_text()?,
-                    row["name"].as_text()?,
-                )
+                let tbl_name = row["tbl_name"].as_text()?;
+                let table = tables.iter().find(|t| t.name() == tbl_name);
+                self.index_from_row(row, table)
             })
             .collect()
     }
 
     pub fn tables(&self) -> Result<Vec<Table>> {
-        self.schema_table
-            .select(vec!["type", "tbl_name", "rootpage", "sql"])?
+        self.catalog()?
             .iter()
-            .filter(|table| table["type"].as_text().unwrap_or("") == "table")
-            .map(|table| {
-                let page_num = table["rootpage"].as_integer()? as usize;
-                let name = table["tbl_name"].as_text()?;
-                let sql = table["sql"].as_text()?;
-                Table::new(self.pager.clone(), page_num, name, &sql)
-            })
+            .filter(|row| is_type(row, "table") && has_btree(row))
+            .map(|row| self.table_from_row(row))
+            .collect()
+    }
+
+    /// Whatever in the schema is called `name`, read from sqlite_master in a
+    /// single pass.
+    pub fn object<S: AsRef<str>>(&self, name: S) -> Result<SchemaObject> {
+        let name = name.as_ref();
+        let catalog = self.catalog()?;
+        let row = catalog
+            .iter()
+            .find(|row| row["name"].as_text().ok() == Some(name))
+            .ok_or_else(|| ErrorKind::ObjectDoesNotExist(name.to_owned()))?;
+        Ok(match row["type"].as_text()? {
+            "table" => SchemaObject::Table(self.table_from_row(row)?),
+            "index" => {
+                let tbl_name = row["tbl_name"].as_text()?;
+                let table = catalog
+                    .iter()
+                    .find(|row| {
+                        is_type(row, "table") && row["name"].as_text().ok() == Some(tbl_name)
+                    })
+                    .ok_or_else(|| ErrorKind::TableDoesNotExist(tbl_name.to_owned()))?;
+                let table = self.table_from_row(table)?;
+                SchemaObject::Index(self.index_from_row(row, Some(&table))?)
+            }
+            "view" => SchemaObject::View(view_from_row(row)?),
+            "trigger" => SchemaObject::Trigger(trigger_from_row(row)?),
+            other => bail!("Unknown type of schema object {}: {}", name, other),
+        })
+    }
+
+    fn table_from_row(&self, row: &HashMap<String, Field>) -> Result<Table> {
+        let name = row["tbl_name"].as_text()?;
+        let page_num = root_page(row)?.ok_or_else(|| format!("Table {} has no b-tree", name))?;
+        let sql = match name {
+            "sqlite_stat1" => SQLITE_STAT1_SCHEMA,
+            "sqlite_sequence" => SQLITE_SEQUENCE_SCHEMA,
+            _ => row["sql"].as_text()?,
+        };
+        Table::new(self.pager.clone(), page_num, name, sql)
+    }
+
+    // The index described by `row`, which is on `table`.
+    fn index_from_row(&self, row: &HashMap<String, Field>, table: Option<&Table>) -> Result<Index> {
+        let kind = if table.is_some_and(|t| t.without_rowid()) {
+            IndexKind::WithoutRowid
+        } else {
+            IndexKind::Rowid
+        };
+        // Indices created for UNIQUE constraints have no SQL.
+        let sql = row["sql"].as_text().ok();
+        let columns = sql.map(index_columns).unwrap_or_default();
+        let affinities = table.map(|t| t.affinities(&columns)).unwrap_or_default();
+        let name = row["name"].as_text()?;
+        let page_num = root_page(row)?.ok_or_else(|| format!("Index {} has no b-tree", name))?;
+        let index = Index::new(
+            self.pager.clone(),
+            page_num,
+            row["tbl_name"].as_text()?,
+            name,
+            kind,
+            columns,
+            sql.map(|sql| sql.to_owned()),
+        )?;
+        Ok(index.with_affinities(affinities))
+    }
+
+    /// The statistics ANALYZE has gathered for each index, by index name.
+    /// This is empty if ANALYZE has never been run.
+    pub fn index_stats(&self) -> Result<HashMap<String, IndexStats>> {
+        let table = match self.table("sqlite_stat1") {
+            Ok(table) => table,
+            Err(Error(ErrorKind::TableDoesNotExist(_), _)) => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let mut stats = HashMap::new();
+        for row in table.select(vec!["idx", "stat"])? {
+            // Rows for tables without indices have a NULL idx.
+            if let (Ok(idx), Ok(stat)) = (row["idx"].as_text(), row["stat"].as_text()) {
+                if let Some(index_stats) = IndexStats::parse(stat) {
+                    stats.insert(idx.to_owned(), index_stats);
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// The largest rowid each AUTOINCREMENT table has ever used, by table
+    /// name. This is empty if no table uses AUTOINCREMENT.
+    pub fn sequences(&self) -> Result<HashMap<String, i64>> {
+        let table = match self.table("sqlite_sequence") {
+            Ok(table) => table,
+            Err(Error(ErrorKind::TableDoesNotExist(_), _)) => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let mut sequences = HashMap::new();
+        for row in table.select(vec!["name", "seq"])? {
+            // XXX Field doesn't know integers are signed yet.
+            let seq = row["seq"].as_integer()? as i64;
+            sequences.insert(row["name"].as_text()?.to_owned(), seq);
+        }
+        Ok(sequences)
+    }
+
+    pub fn views(&self) -> Result<Vec<View>> {
+        self.catalog()?
+            .iter()
+            .filter(|row| is_type(row, "view"))
+            .map(view_from_row)
+            .collect()
+    }
+
+    pub fn view<S: AsRef<str>>(&self, name: S) -> Result<Option<View>> {
+        Ok(self.views()?.into_iter().find(|v| v.name == name.as_ref()))
+    }
+
+    pub fn triggers(&self) -> Result<Vec<Trigger>> {
+        self.catalog()?
+            .iter()
+            .filter(|row| is_type(row, "trigger"))
+            .map(trigger_from_row)
             .collect()
     }
 
@@ -68,3 +234,152 @@ impl Schema {
             .ok_or(ErrorKind::TableDoesNotExist(name.as_ref().to_owned()).into())
     }
 }
+
+fn is_type(row: &HashMap<String, Field>, ty: &str) -> bool {
+    row["type"].as_text().unwrap_or("") == ty
+}
+
+// The page a row's b-tree starts on, or None if it has no b-tree. Views and
+// triggers have a rootpage of 0, as do virtual tables, whose rows are kept
+// elsewhere. A NULL is taken to mean the same.
+fn root_page(row: &HashMap<String, Field>) -> Result<Option<usize>> {
+    let rootpage = &row["rootpage"];
+    Ok(match rootpage.ty() {
+        Type::Null => None,
+        Type::Integer => match rootpage.as_integer()? {
+            0 => None,
+            n => Some(n as usize),
+        },
+        ty => bail!(
+            "{} has a rootpage of type {:?}",
+            row["name"].as_text_lossy()?,
+            ty
+        ),
+    })
+}
+
+// Whether a row has a b-tree that can be read. Rows with a rootpage that
+// can't be read at all are kept, so that reading them gives the error.
+fn has_btree(row: &HashMap<String, Field>) -> bool {
+    match root_page(row) {
+        Ok(page_num) => page_num.is_some(),
+        Err(_) => true,
+    }
+}
+
+fn view_from_row(row: &HashMap<String, Field>) -> Result<View> {
+    Ok(View {
+        name: row["name"].as_text()?.to_owned(),
+        sql: row["sql"].as_text()?.to_owned(),
+    })
+}
+
+fn trigger_from_row(row: &HashMap<String, Field>) -> Result<Trigger> {
+    Ok(Trigger {
+        name: row["name"].as_text()?.to_owned(),
+        tbl_name: row["tbl_name"].as_text()?.to_owned(),
+        sql: row["sql"].as_text()?.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::open_fixture;
+
+    #[test]
+    fn test_views_and_triggers() {
+        let schema = open_fixture("views.db");
+        let views = schema.views().unwrap();
+        assert_eq!(views.len(), 1);
+        assert_eq!(views[0].name, "adults");
+        assert!(views[0].sql.starts_with("CREATE VIEW adults AS SELECT"));
+
+        let triggers = schema.triggers().unwrap();
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].name, "people_insert");
+        assert_eq!(triggers[0].tbl_name, "people");
+
+        let tables: Vec<_> = schema
+            .tables()
+            .unwrap()
+            .iter()
+            .map(|t| t.name().to_owned())
+            .collect();
+        assert_eq!(tables, vec!["people", "audit"]);
+    }
+
+    #[test]
+    fn test_rows_without_btrees() {
+        let schema = open_fixture("rootless.db");
+        let tables: Vec<_> = schema
+            .tables()
+            .unwrap()
+            .iter()
+            .map(|t| t.name().to_owned())
+            .collect();
+        assert_eq!(tables, vec!["kept"]);
+        let indices: Vec<_> = schema
+            .indices()
+            .unwrap()
+            .iter()
+            .map(|i| i.name().to_owned())
+            .collect();
+        assert_eq!(indices, vec!["kept_name"]);
+        assert_eq!(schema.views().unwrap()[0].name, "named");
+        assert_eq!(schema.triggers().unwrap()[0].name, "kept_delete");
+
+        assert_eq!(schema.table("kept").unwrap().len().unwrap(), 3);
+        match schema.table("ghost") {
+            Err(Error(ErrorKind::TableDoesNotExist(_), _)) => {}
+            other => panic!("Expected TableDoesNotExist, got {:?}", other),
+        }
+        let error = schema.object("phantom").unwrap_err().to_string();
+        assert!(error.contains("phantom has no b-tree"), "{}", error);
+        assert!(schema.object("ghost_name").is_err());
+        match schema.object("named").unwrap() {
+            SchemaObject::View(view) => assert!(view.sql.starts_with("CREATE VIEW named")),
+            other => panic!("Expected a view, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sequences() {
+        let sequences = open_fixture("autoincrement.db").sequences().unwrap();
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences["jobs"], 5);
+        assert_eq!(sequences["runs"], 100);
+
+        assert!(open_fixture("sales.db").sequences().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_object() {
+        let schema = open_fixture("indexed.db");
+        match schema.object("items").unwrap() {
+            SchemaObject::Table(table) => assert_eq!(table.name(), "items"),
+            other => panic!("Expected a table, got {:?}", other),
+        }
+        match schema.object("items_price").unwrap() {
+            SchemaObject::Index(index) => {
+                assert_eq!(index.tbl_name(), "items");
+                assert_eq!(index.columns(), ["price"]);
+            }
+            other => panic!("Expected an index, got {:?}", other),
+        }
+
+        let schema = open_fixture("views.db");
+        match schema.object("adults").unwrap() {
+            SchemaObject::View(view) => assert!(view.sql.starts_with("CREATE VIEW adults")),
+            other => panic!("Expected a view, got {:?}", other),
+        }
+        match schema.object("people_insert").unwrap() {
+            SchemaObject::Trigger(trigger) => assert_eq!(trigger.tbl_name, "people"),
+            other => panic!("Expected a trigger, got {:?}", other),
+        }
+        match schema.object("missing") {
+            Err(Error(ErrorKind::ObjectDoesNotExist(name), _)) => assert_eq!(name, "missing"),
+            other => panic!("Expected ObjectDoesNotExist, got {:?}", other),
+        }
+    }
+}
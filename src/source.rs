@@ -0,0 +1,304 @@
+use crate::errors::*;
+use crate::index::{Bound, Index};
+use crate::record::{Field, Record};
+use crate::table::Table;
+use crate::value::{Row, TextDecoding, Value};
+
+/// Where a query reads the rows of its table from. However a source finds
+/// the rows, each has the source's columns in order, so that the query can
+/// filter, project and limit them the same way.
+pub trait RowSource {
+    /// The columns of each row.
+    fn columns(&self) -> &[String];
+
+    /// The rows, read as the iterator is advanced.
+    fn rows<'a>(&'a self) -> Result<Box<dyn Iterator<Item = Result<Row>> + 'a>>;
+}
+
+/// Every row of a table, in rowid order.
+pub struct TableScan {
+    table: Table,
+    columns: Vec<String>,
+    decoding: TextDecoding,
+}
+
+impl TableScan {
+    pub fn new(table: Table, columns: Vec<String>, decoding: TextDecoding) -> TableScan {
+        TableScan {
+            table,
+            columns,
+            decoding,
+        }
+    }
+}
+
+impl RowSource for TableScan {
+    fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn rows<'a>(&'a self) -> Result<Box<dyn Iterator<Item = Result<Row>> + 'a>> {
+        let rows = self.table.select_iter(self.columns.clone())?;
+        Ok(Box::new(rows.map(move |row| {
+            Row::from_fields_with(&self.columns, &row?, self.decoding)
+        })))
+    }
+}
+
+/// The rows of a table whose rowids lie between `lower` and `upper`,
+/// including both, in rowid order.
+pub struct TableRange {
+    table: Table,
+    lower: Option<i64>,
+    upper: Option<i64>,
+    columns: Vec<String>,
+    decoding: TextDecoding,
+}
+
+impl TableRange {
+    pub fn new(
+        table: Table,
+        lower: Option<i64>,
+        upper: Option<i64>,
+        columns: Vec<String>,
+        decoding: TextDecoding,
+    ) -> TableRange {
+        TableRange {
+            table,
+            lower,
+            upper,
+            columns,
+            decoding,
+        }
+    }
+}
+
+impl RowSource for TableRange {
+    fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn rows<'a>(&'a self) -> Result<Box<dyn Iterator<Item = Result<Row>> + 'a>> {
+        let rows = self
+            .table
+            .select_range(self.lower, self.upper, self.columns.clone())?;
+        Ok(Box::new(rows.map(move |row| {
+            Row::from_fields_with(&self.columns, &row?, self.decoding)
+        })))
+    }
+}
+
+/// The entries of an index whose leading columns equal `prefix`, and whose
+/// next column lies between `lower` and `upper`, in index order.
+#[derive(Debug)]
+pub struct IndexEntries {
+    pub index: Index,
+    pub prefix: Vec<Field>,
+    pub lower: Option<Bound>,
+    pub upper: Option<Bound>,
+}
+
+impl IndexEntries {
+    fn read(&self) -> Result<Vec<Record>> {
+        self.index
+            .scan_bounds(self.prefix.clone(), self.lower.clone(), self.upper.clone())
+    }
+}
+
+/// The rows of a range of an index, read from the index alone. This only
+/// works if the index has every column that's wanted.
+pub struct IndexScan {
+    entries: IndexEntries,
+    columns: Vec<String>,
+    // Where each of `columns` is in the index's entries.
+    positions: Vec<usize>,
+    decoding: TextDecoding,
+}
+
+impl IndexScan {
+    pub fn new(
+        entries: IndexEntries,
+        columns: Vec<String>,
+        decoding: TextDecoding,
+    ) -> Result<IndexScan> {
+        let positions = columns
+            .iter()
+            .map(|column| {
+                entries
+                    .index
+                    .columns()
+                    .iter()
+                    .position(|c| c == column)
+                    .ok_or_else(|| {
+                        format!("Index {} has no column {}", entries.index.name(), column).into()
+                    })
+            })
+            .collect::<Result<_>>()?;
+        Ok(IndexScan {
+            entries,
+            columns,
+            positions,
+            decoding,
+        })
+    }
+
+    /// Whether an index has all of `columns`, so that they can be read from
+    /// it without looking up each row in the table.
+    pub fn covers<S: AsRef<str>>(index: &Index, columns: &[S]) -> bool {
+        columns
+            .iter()
+            .all(|column| index.columns().iter().any(|c| c == column.as_ref()))
+    }
+}
+
+impl RowSource for IndexScan {
+    fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn rows<'a>(&'a self) -> Result<Box<dyn Iterator<Item = Result<Row>> + 'a>> {
+        let rows = self.entries.read()?.into_iter().map(move |entry| {
+            let values = self
+                .positions
+                .iter()
+                .map(|&i| Value::from_field_with(&entry[i], self.decoding))
+                .collect::<Result<_>>()?;
+            Ok(Row::new(self.columns.clone(), values))
+        });
+        Ok(Box::new(rows))
+    }
+}
+
+/// The rows of a range of an index, looked up in the table by the rowid of
+/// each of the index's entries.
+pub struct IndexLookup {
+    entries: IndexEntries,
+    table: Table,
+    columns: Vec<String>,
+    decoding: TextDecoding,
+}
+
+impl IndexLookup {
+    pub fn new(
+        entries: IndexEntries,
+        table: Table,
+        columns: Vec<String>,
+        decoding: TextDecoding,
+    ) -> IndexLookup {
+        IndexLookup {
+            entries,
+            table,
+            columns,
+            decoding,
+        }
+    }
+}
+
+impl RowSource for IndexLookup {
+    fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn rows<'a>(&'a self) -> Result<Box<dyn Iterator<Item = Result<Row>> + 'a>> {
+        let index = &self.entries.index;
+        let rows = self.entries.read()?.into_iter().map(move |entry| {
+            let row_id = index
+                .split_rowid(entry)?
+                .1
+                .ok_or_else(|| format!("Index {} has no rowids", index.name()))?;
+            let row = self.table.get(row_id, &self.columns)?.ok_or_else(|| {
+                format!("Index {} refers to missing row {}", index.name(), row_id)
+            })?;
+            Row::from_fields_with(&self.columns, &row, self.decoding)
+        });
+        Ok(Box::new(rows))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::open_fixture;
+
+    fn sorted_rows(source: &dyn RowSource) -> Vec<Vec<Value>> {
+        let mut rows: Vec<_> = source
+            .rows()
+            .unwrap()
+            .map(|row| row.unwrap().values().to_vec())
+            .collect();
+        rows.sort_by(|a, b| format!("{:?}", a).cmp(&format!("{:?}", b)));
+        rows
+    }
+
+    #[test]
+    fn test_sources_agree() {
+        let schema = open_fixture("indexed.db");
+        let index = || {
+            schema
+                .indices()
+                .unwrap()
+                .into_iter()
+                .find(|i| i.name() == "items_price")
+                .unwrap()
+        };
+        let entries = || IndexEntries {
+            index: index(),
+            prefix: vec![],
+            lower: None,
+            upper: None,
+        };
+        let columns = vec!["price".to_owned()];
+        let table = || schema.table("items").unwrap();
+
+        let scan = TableScan::new(table(), columns.clone(), TextDecoding::Strict);
+        let covering = IndexScan::new(entries(), columns.clone(), TextDecoding::Strict).unwrap();
+        let lookup = IndexLookup::new(entries(), table(), columns.clone(), TextDecoding::Strict);
+        let expected = sorted_rows(&scan);
+        assert_eq!(expected.len(), table().len().unwrap());
+        assert_eq!(sorted_rows(&covering), expected);
+        assert_eq!(sorted_rows(&lookup), expected);
+        for source in &[&scan as &dyn RowSource, &covering, &lookup] {
+            assert_eq!(source.columns(), columns.as_slice());
+        }
+
+        // Only the lookup can read columns the index doesn't have.
+        let columns = vec!["name".to_owned(), "price".to_owned()];
+        assert!(!IndexScan::covers(&index(), &columns));
+        assert!(IndexScan::new(entries(), columns.clone(), TextDecoding::Strict).is_err());
+        let scan = TableScan::new(table(), columns.clone(), TextDecoding::Strict);
+        let lookup = IndexLookup::new(entries(), table(), columns, TextDecoding::Strict);
+        assert_eq!(sorted_rows(&lookup), sorted_rows(&scan));
+    }
+
+    #[test]
+    fn test_sources_agree_on_multi_level_index() {
+        // Some of people_age's entries are in its interior page.
+        let schema = open_fixture("analyzed.db");
+        let index = schema
+            .indices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name() == "people_age")
+            .unwrap();
+        assert!(index.depth().unwrap() > 1);
+        let entries = || IndexEntries {
+            index: index.clone(),
+            prefix: vec![],
+            lower: None,
+            upper: None,
+        };
+        let table = || schema.table("people").unwrap();
+
+        let columns = vec!["age".to_owned()];
+        let scan = TableScan::new(table(), columns.clone(), TextDecoding::Strict);
+        let covering = IndexScan::new(entries(), columns, TextDecoding::Strict).unwrap();
+        let expected = sorted_rows(&scan);
+        assert_eq!(expected.len(), table().len().unwrap());
+        assert_eq!(sorted_rows(&covering), expected);
+
+        let columns = vec!["id".to_owned(), "age".to_owned()];
+        let scan = TableScan::new(table(), columns.clone(), TextDecoding::Strict);
+        let lookup = IndexLookup::new(entries(), table(), columns, TextDecoding::Strict);
+        assert_eq!(sorted_rows(&lookup), sorted_rows(&scan));
+    }
+}
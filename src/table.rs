@@ -1,81 +1,532 @@
 use std::collections::HashMap;
 use std::fmt;
-use std::io::Cursor;
+use std::io::{self, Cursor, Read};
 use std::rc::Rc;
 use std::result;
 
 use byteorder::{BigEndian, ByteOrder};
 use bytes::Bytes;
-use nom_sql::{self, ColumnConstraint, CreateTableStatement, SqlQuery, SqlType};
+use nom_sql::{self, ColumnConstraint, CreateTableStatement, Literal, SqlQuery, TableKey};
 
-use crate::btree::{BTree, Cell, InteriorCell};
+use crate::btree::{
+    local_payload_len, overflow_page, read_page, table_max_local, BTree, BTreeKind, Cell,
+    InteriorCell, PayloadReader, RangeBetween, RangeGt,
+};
 use crate::errors::*;
+use crate::index::{Index, IndexKind};
 use crate::pager::Pager;
-use crate::record::{Field, Record};
+use crate::parser;
+use crate::record::{looks_numeric, serial_type, Field, Record};
 use crate::types::Type;
-use crate::util::read_varint;
+use crate::util::{read_varint, read_varint_from};
+use crate::value::{FromRow, Row, Value};
 
 #[derive(Debug)]
 enum ColumnReference {
     RowId,
     Index(usize),
+    /// The field of a REAL column. SQLite stores whole numbers in these as
+    /// integers, to save space, so integers are turned back into reals when
+    /// they're read.
+    Real(usize),
 }
 
 #[derive(Debug)]
 struct Column {
     name: String,
     ty: Type,
+    // The type the column was declared with, as the schema spells it, or ""
+    // if it wasn't declared with one.
+    declared: String,
+    // Whether the column was declared with a type that SQLite gives NUMERIC
+    // affinity, such as BOOLEAN or DATETIME. These are read as INTEGER
+    // columns are, which only differ in how CAST treats them.
+    numeric: bool,
     primary_key: bool,
+    not_null: bool,
+    // The SQL of the column's DEFAULT, if it has one.
+    default: Option<String>,
+    // What rows written before the column was added hold for it: its
+    // DEFAULT, or NULL.
+    missing: Field,
+    generated: Option<Generated>,
+}
+
+/// A column as `PRAGMA table_info` describes it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnInfo {
+    pub cid: usize,
+    pub name: String,
+    /// The type the column was declared with, as the schema spells it.
+    pub ty: String,
+    /// The affinity the type gives the column, which is the type we read it
+    /// as. NUMERIC columns are read as `Type::Integer`.
+    pub affinity: Type,
+    pub not_null: bool,
+    pub default: Option<String>,
+    /// The column's position in the primary key, counting from 1, or 0 if
+    /// it isn't part of it.
+    pub pk: usize,
 }
 
 #[derive(Debug)]
 struct TableSchema {
     columns: Vec<Column>,
+    // The primary key's columns, in the order the key lists them.
+    primary_key: Vec<String>,
+    without_rowid: bool,
+    // The number SQLite gave the index it made for the primary key, as in
+    // `sqlite_autoindex_<table>_<n>`, if it made one.
+    pk_autoindex: Option<usize>,
+}
+
+// nom_sql doesn't understand table options such as `WITHOUT ROWID`, so we
+// split them off the end of the statement before parsing.
+fn split_table_options(sql: &str) -> (&str, Vec<String>) {
+    let end = match sql.rfind(')') {
+        Some(end) => end + 1,
+        None => return (sql, vec![]),
+    };
+    let options = sql[end..]
+        .trim()
+        .trim_end_matches(';')
+        .split(',')
+        .map(|option| {
+            option
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_uppercase()
+        })
+        .filter(|option| !option.is_empty())
+        .collect();
+    (&sql[..end], options)
+}
+
+// Finds the `)` matching the `(` at `open`, skipping over quoted strings.
+fn matching_paren(sql: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in sql[open..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Splits the body of a CREATE TABLE into its column definitions and table
+// constraints, at the commas that aren't nested in brackets or quotes.
+fn split_definitions(body: &str) -> Vec<&str> {
+    let mut definitions = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                definitions.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    definitions.push(&body[start..]);
+    definitions
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Generated {
+    /// Computed when read, and not stored in the record.
+    Virtual,
+    /// Computed when written, and stored in the record like any other column.
+    Stored,
+}
+
+// Cuts the `[GENERATED ALWAYS] AS (...) [VIRTUAL|STORED]` clause out of a
+// column definition, if it has one.
+fn strip_generated(definition: &str) -> Result<Option<(String, Generated)>> {
+    // Keywords are ASCII, so the upper-cased copy has the same byte offsets.
+    let upper = definition.to_ascii_uppercase();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut found = None;
+    for (i, c) in upper.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, 'A')
+                if depth == 0
+                    && upper[i..].starts_with("AS")
+                    && upper[..i].ends_with(char::is_whitespace)
+                    && upper[i + 2..].trim_start().starts_with('(') =>
+            {
+                found = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let as_start = match found {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+
+    let before = upper[..as_start].trim_end();
+    let start = if before.ends_with("GENERATED ALWAYS") {
+        before.len() - "GENERATED ALWAYS".len()
+    } else {
+        as_start
+    };
+    let open = as_start + upper[as_start..].find('(').unwrap();
+    let close = matching_paren(&upper, open)
+        .ok_or_else(|| format!("Unbalanced brackets in column: {}", definition))?;
+    let rest = upper[close + 1..].trim_start();
+    let (generated, end) = if rest.starts_with("STORED") {
+        (Generated::Stored, upper.len() - rest.len() + "STORED".len())
+    } else if rest.starts_with("VIRTUAL") {
+        (
+            Generated::Virtual,
+            upper.len() - rest.len() + "VIRTUAL".len(),
+        )
+    } else {
+        (Generated::Virtual, close + 1)
+    };
+
+    let stripped = format!("{}{}", &definition[..start], &definition[end..]);
+    Ok(Some((stripped, generated)))
+}
+
+// nom_sql only knows MySQL's AUTO_INCREMENT, not SQLite's AUTOINCREMENT.
+// That only affects how rowids are chosen when inserting, so we drop it.
+fn strip_autoincrement(definition: &str) -> String {
+    const KEYWORD: &str = "AUTOINCREMENT";
+    let upper = definition.to_ascii_uppercase();
+    let mut quote = None;
+    for (i, c) in upper.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, 'A')
+                if upper[i..].starts_with(KEYWORD)
+                    && upper[..i].ends_with(char::is_whitespace)
+                    && !upper[i + KEYWORD.len()..]
+                        .starts_with(|c: char| c.is_alphanumeric() || c == '_') =>
+            {
+                return format!("{}{}", &definition[..i], &definition[i + KEYWORD.len()..]);
+            }
+            _ => {}
+        }
+    }
+    definition.to_owned()
+}
+
+// nom_sql takes the BOOL off the front of BOOLEAN and then chokes on the EAN
+// that's left, so we spell the type the way it expects.
+fn shorten_boolean(definition: &str) -> String {
+    let mut words = definition.trim_start().splitn(2, char::is_whitespace);
+    let (name, rest) = match (words.next(), words.next()) {
+        (Some(name), Some(rest)) => (name, rest.trim_start()),
+        _ => return definition.to_owned(),
+    };
+    let is_boolean = rest
+        .get(..7)
+        .is_some_and(|ty| ty.eq_ignore_ascii_case("BOOLEAN"))
+        && !rest[7..].starts_with(|c: char| c.is_alphanumeric() || c == '_');
+    if is_boolean {
+        format!("{} BOOL{}", name, &rest[7..])
+    } else {
+        definition.to_owned()
+    }
+}
+
+// Words that start a column constraint, and so end the column's type.
+const CONSTRAINT_KEYWORDS: [&str; 11] = [
+    "CONSTRAINT",
+    "PRIMARY",
+    "NOT",
+    "NULL",
+    "UNIQUE",
+    "CHECK",
+    "DEFAULT",
+    "COLLATE",
+    "REFERENCES",
+    "GENERATED",
+    "AS",
+];
+
+// The type each column was declared with, as it's written in the schema.
+// nom_sql only tells us which of its own types it parsed the type as, which
+// loses how it was spelt (and BOOLEAN, which we respell for it).
+fn declared_types(sql: &str) -> HashMap<String, String> {
+    let body = match sql.find('(') {
+        Some(open) => match matching_paren(sql, open) {
+            Some(close) => &sql[open + 1..close],
+            None => return HashMap::new(),
+        },
+        None => return HashMap::new(),
+    };
+    split_definitions(body)
+        .into_iter()
+        .filter_map(|definition| {
+            let mut words = definition.split_whitespace();
+            let name = words
+                .next()?
+                .trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']');
+            let ty: Vec<_> = words
+                .take_while(|word| {
+                    !CONSTRAINT_KEYWORDS
+                        .iter()
+                        .any(|keyword| word.eq_ignore_ascii_case(keyword))
+                })
+                .collect();
+            Some((name.to_owned(), ty.join(" ")))
+        })
+        .collect()
+}
+
+// nom_sql can't parse generated columns either, so we strip them out of the
+// statement, remembering which columns were generated and how. AUTOINCREMENT
+// and BOOLEAN are dealt with at the same time.
+fn strip_generated_columns(sql: &str) -> Result<(String, HashMap<String, Generated>)> {
+    let mut generated = HashMap::new();
+    let (open, close) = match sql.find('(') {
+        Some(open) => (open, matching_paren(sql, open)),
+        None => return Ok((sql.to_owned(), generated)),
+    };
+    let close = close.ok_or_else(|| format!("Unbalanced brackets in schema: {}", sql))?;
+
+    let mut definitions = Vec::new();
+    for definition in split_definitions(&sql[open + 1..close]) {
+        let definition = shorten_boolean(&strip_autoincrement(definition));
+        match strip_generated(&definition)? {
+            Some((stripped, how)) => {
+                let name = stripped
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or("")
+                    .trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']');
+                generated.insert(name.to_owned(), how);
+                definitions.push(stripped);
+            }
+            None => definitions.push(definition),
+        }
+    }
+
+    let sql = format!(
+        "{}{}{}",
+        &sql[..open + 1],
+        definitions.join(","),
+        &sql[close..]
+    );
+    Ok((sql, generated))
 }
 
 impl TableSchema {
     fn from_sql(sql: &str) -> Result<TableSchema> {
+        let (sql, options) = split_table_options(sql);
+        let mut without_rowid = false;
+        for option in options {
+            match option.as_str() {
+                "WITHOUT ROWID" => without_rowid = true,
+                "STRICT" => bail!(ErrorKind::Unsupported("STRICT tables".to_owned())),
+                _ => bail!(ErrorKind::Unsupported(format!("table option {}", option))),
+            }
+        }
+        let mut declared = declared_types(sql);
+        let (sql, mut generated) = strip_generated_columns(sql)?;
+        let sql = sql.as_str();
+
         let parsed = nom_sql::parser::parse_query(sql)
             .map_err(|_| format!("Failed to parse schema: {}", sql))?;
-        let column_defs = match parsed {
-            SqlQuery::CreateTable(CreateTableStatement { fields, .. }) => fields,
+        let (column_defs, keys) = match parsed {
+            SqlQuery::CreateTable(CreateTableStatement { fields, keys, .. }) => {
+                (fields, keys.unwrap_or_default())
+            }
             _ => bail!("Expected CREATE TABLE: {}", sql),
         };
+        // The UNIQUE and PRIMARY KEY constraints in the order they're
+        // declared, as whether each is the primary key.
+        let constraints: Vec<bool> = column_defs
+            .iter()
+            .flat_map(|col| &col.constraints)
+            .filter_map(|constraint| match *constraint {
+                ColumnConstraint::Unique => Some(false),
+                ColumnConstraint::PrimaryKey => Some(true),
+                _ => None,
+            })
+            .chain(keys.iter().filter_map(|key| match *key {
+                TableKey::UniqueKey(..) => Some(false),
+                TableKey::PrimaryKey(_) => Some(true),
+                _ => None,
+            }))
+            .collect();
 
-        let columns: Result<Vec<_>> = column_defs
+        let mut columns = column_defs
             .into_iter()
             .map(|col| {
-                let ty = match col.sql_type {
-                    SqlType::Int(_) => Type::Integer,
-                    SqlType::Text => Type::Text,
-                    other => bail!("Unexpected column type: {:?}", other),
+                let declared = declared.remove(&col.column.name).unwrap_or_default();
+                // SQLite has no booleans or dates, for example: "if none of
+                // the previous rules apply, the affinity is NUMERIC".
+                let (ty, numeric) = match parser::affinity(&declared) {
+                    _ if declared.is_empty() => (Type::Blob, false),
+                    Some(ty) => (ty, false),
+                    None => (Type::Integer, true),
                 };
                 let primary_key = col.constraints.contains(&ColumnConstraint::PrimaryKey);
+                let not_null = col.constraints.contains(&ColumnConstraint::NotNull);
+                let default = col.constraints.iter().find_map(|c| match *c {
+                    ColumnConstraint::DefaultValue(ref value) => Some(value),
+                    _ => None,
+                });
+                let missing = Field::from(default.map_or(Value::Null, literal_value));
                 Ok(Column {
+                    declared,
+                    generated: generated.remove(&col.column.name),
                     name: col.column.name,
                     ty,
+                    numeric,
                     primary_key,
+                    not_null,
+                    default: default.map(Literal::to_string),
+                    missing: missing.with_affinity(ty),
                 })
             })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut primary_key: Vec<String> = columns
+            .iter()
+            .filter(|c| c.primary_key)
+            .map(|c| c.name.clone())
             .collect();
+        // A table-level `PRIMARY KEY (a, b)` names its columns separately.
+        for key in keys {
+            if let TableKey::PrimaryKey(key_columns) = key {
+                if !primary_key.is_empty() {
+                    bail!("Table has more than one primary key: {}", sql);
+                }
+                for key_column in key_columns {
+                    let column = columns
+                        .iter_mut()
+                        .find(|c| c.name == key_column.name)
+                        .ok_or_else(|| {
+                            format!("Unknown primary key column: {}", key_column.name)
+                        })?;
+                    column.primary_key = true;
+                    primary_key.push(key_column.name);
+                }
+            }
+        }
 
-        Ok(TableSchema { columns: columns? })
+        // SQLite makes an index for each constraint, numbering them in the
+        // order they're declared, except for a primary key that aliases the
+        // rowid.
+        let aliases_rowid = !without_rowid
+            && primary_key.len() == 1
+            && columns
+                .iter()
+                .any(|c| c.name == primary_key[0] && c.ty == Type::Integer && !c.numeric);
+        let pk_autoindex = if aliases_rowid {
+            None
+        } else {
+            constraints.iter().position(|&pk| pk).map(|i| i + 1)
+        };
+
+        Ok(TableSchema {
+            columns,
+            primary_key,
+            without_rowid,
+            pk_autoindex,
+        })
     }
 
-    fn column_indices<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<ColumnReference>> {
+    // See `Table::columns()`.
+    fn column_info(&self) -> Vec<ColumnInfo> {
+        let primary_key = &self.primary_key;
+        self.columns
+            .iter()
+            .filter(|col| col.generated.is_none())
+            .enumerate()
+            .map(|(cid, col)| ColumnInfo {
+                cid,
+                name: col.name.clone(),
+                ty: col.declared.clone(),
+                affinity: col.ty,
+                not_null: col.not_null,
+                default: col.default.clone(),
+                pk: primary_key
+                    .iter()
+                    .position(|name| *name == col.name)
+                    .map_or(0, |position| position + 1),
+            })
+            .collect()
+    }
+
+    fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|col| col.name == name)
+    }
+
+    // The value of each of `names` in rows written before it was added by
+    // ALTER TABLE, which are NULL for the rowid and unknown columns.
+    fn missing_fields<S: AsRef<str>>(&self, names: &[S]) -> Vec<Field> {
+        names
+            .iter()
+            .map(|name| match self.column(name.as_ref()) {
+                Some(col) => col.missing.clone(),
+                None => Field::from(Value::Null),
+            })
+            .collect()
+    }
+
+    fn primary_key(&self) -> Vec<String> {
+        self.primary_key.clone()
+    }
+
+    // Whether `name` refers to the rowid: either the column aliasing it, or
+    // one of the rowid's own names if no column has taken that name.
+    fn is_rowid(&self, name: &str) -> bool {
+        if self.without_rowid {
+            return false;
+        }
         // If the primary key is a single integer column, then it is
         // actually stored as the RowId and a null is stored in its place
-        // in the fields.
+        // in the fields. WITHOUT ROWID tables have no RowId to alias. A
+        // NUMERIC column, such as a DATE, is read as an integer but doesn't
+        // alias the rowid.
         let pks = self
             .columns
             .iter()
             .filter(|c| c.primary_key)
             .collect::<Vec<_>>();
-        let pk_is_rowid = pks.len() == 1 && pks[0].ty == Type::Integer;
+        if pks.len() == 1 && pks[0].ty == Type::Integer && !pks[0].numeric && pks[0].name == name {
+            return true;
+        }
+        ROWID_NAMES.iter().any(|n| n.eq_ignore_ascii_case(name))
+            && !self.columns.iter().any(|c| c.name == name)
+    }
+
+    fn column_indices<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<ColumnReference>> {
         names
             .iter()
             .map(|name| {
-                if pk_is_rowid && pks[0].name == name.as_ref() {
+                if self.is_rowid(name.as_ref()) {
                     Ok(ColumnReference::RowId)
                 } else {
                     let idx = self
@@ -83,28 +534,82 @@ impl TableSchema {
                         .iter()
                         .position(|col| col.name == name.as_ref())
                         .ok_or(format!("Unknown column: {}", name.as_ref()))?;
-                    Ok(ColumnReference::Index(idx))
+                    if self.columns[idx].generated == Some(Generated::Virtual) {
+                        bail!(ErrorKind::Unsupported(format!(
+                            "reading VIRTUAL generated column {}",
+                            name.as_ref()
+                        )));
+                    }
+                    // VIRTUAL columns aren't stored, so don't have a field in
+                    // the record.
+                    let stored = self.columns[..idx]
+                        .iter()
+                        .filter(|col| col.generated != Some(Generated::Virtual))
+                        .count();
+                    if self.columns[idx].ty == Type::Float {
+                        Ok(ColumnReference::Real(stored))
+                    } else {
+                        Ok(ColumnReference::Index(stored))
+                    }
                 }
             })
             .collect()
     }
 }
 
-type CellKey = u64;
+// Rowids are signed 64-bit integers, stored as varints.
+type CellKey = i64;
+
+// The names the rowid can be read by, unless a column is called them.
+const ROWID_NAMES: &[&str] = &["rowid", "oid", "_rowid_"];
+
+fn table_leaf_overflow_page(bytes: &Bytes, usable_size: usize) -> Result<Option<usize>> {
+    let mut cursor = Cursor::new(bytes);
+    let len = read_varint(&mut cursor)? as usize;
+    let _row_id = read_varint(&mut cursor)?;
+    let position = cursor.position() as usize;
+    overflow_page(
+        bytes,
+        position,
+        len,
+        table_max_local(usable_size),
+        usable_size,
+    )
+}
+
+// A reader of the payload of the table leaf `cell` of the row with `row_id`,
+// following its overflow pages.
+fn cell_payload_reader(pager: &Rc<Pager>, cell: &Bytes, row_id: i64) -> Result<PayloadReader> {
+    let (len, len_size) = read_varint_from(cell)?;
+    let (_, row_id_size) = read_varint_from(&cell[len_size..])?;
+    let position = len_size + row_id_size;
+
+    let header = &pager.header;
+    let usable_size = header.page_size - header.reserved_byes_per_page;
+    let len = len as usize;
+    let local = local_payload_len(len, table_max_local(usable_size), usable_size);
+    if position + local > cell.len() {
+        bail!("Payload of row {} extends past the end of its page", row_id);
+    }
+    let overflow = table_leaf_overflow_page(cell, usable_size)?;
+    let local = cell.slice(position, position + local);
+    Ok(PayloadReader::new(pager.clone(), local, overflow, len))
+}
 
 #[derive(Debug)]
 pub struct TableLeafCell {
-    pub row_id: u64,
+    pub row_id: i64,
     pub record: Record,
 }
 
 impl Cell for TableLeafCell {
     type Key = CellKey;
+    const KIND: BTreeKind = BTreeKind::Table;
 
     fn from_bytes(bytes: Bytes) -> Result<Self> {
         let mut cursor = Cursor::new(bytes);
         let _payload_length = read_varint(&mut cursor)?;
-        let row_id = read_varint(&mut cursor)?;
+        let row_id = read_varint(&mut cursor)? as i64;
         let position = cursor.position() as usize;
         let bytes = cursor.into_inner().slice_from(position);
         let record = Record::from_bytes(bytes)?;
@@ -115,22 +620,80 @@ impl Cell for TableLeafCell {
     fn key(&self) -> &Self::Key {
         &self.row_id
     }
+
+    fn overflow_page(bytes: &Bytes, usable_size: usize) -> Result<Option<usize>> {
+        table_leaf_overflow_page(bytes, usable_size)
+    }
+}
+
+/// A table leaf cell whose payload hasn't been decoded into a `Record`, for
+/// when the raw bytes are wanted (or won't decode).
+#[derive(Debug)]
+pub struct RawTableLeafCell {
+    pub row_id: i64,
+    // XXX This is cut short at the end of the page if the payload overflows.
+    pub payload: Bytes,
+    // The length of the whole payload, including any overflow.
+    len: usize,
+    // The cell, up to the end of its page, for finding its overflow pages.
+    cell: Bytes,
+}
+
+impl Cell for RawTableLeafCell {
+    type Key = CellKey;
+    const KIND: BTreeKind = BTreeKind::Table;
+
+    fn from_bytes(bytes: Bytes) -> Result<Self> {
+        let mut cursor = Cursor::new(bytes);
+        let payload_length = read_varint(&mut cursor)? as usize;
+        let row_id = read_varint(&mut cursor)? as i64;
+        let position = cursor.position() as usize;
+        let cell = cursor.into_inner();
+        let end = position.saturating_add(payload_length).min(cell.len());
+        let payload = cell.slice(position.min(end), end);
+
+        Ok(RawTableLeafCell {
+            row_id,
+            payload,
+            len: payload_length,
+            cell,
+        })
+    }
+
+    fn key(&self) -> &Self::Key {
+        &self.row_id
+    }
+
+    fn overflow_page(bytes: &Bytes, usable_size: usize) -> Result<Option<usize>> {
+        table_leaf_overflow_page(bytes, usable_size)
+    }
 }
 
 #[derive(Debug)]
 pub struct TableInteriorCell {
-    row_id: u64,
+    row_id: i64,
     left: usize,
 }
 
 impl Cell for TableInteriorCell {
     type Key = CellKey;
+    const KIND: BTreeKind = BTreeKind::Table;
 
     fn from_bytes(bytes: Bytes) -> Result<Self> {
-        // XXX Are we reading the same bytes as left and row_id?
+        if bytes.len() < 4 {
+            bail!("Table interior cell too short: {} bytes", bytes.len());
+        }
+        // "A 4-byte big-endian page number which is the left child pointer"
+        // followed by "A varint which is the integer key". There's nothing
+        // else: the cell is 4 bytes plus the varint's length, and `bytes`
+        // runs on past it to the end of the page.
         let left = BigEndian::read_u32(&bytes) as usize;
-        let row_id = read_varint(&mut Cursor::new(bytes))?;
-        Ok(TableInteriorCell { row_id, left })
+        let (row_id, _) = read_varint_from(&bytes[4..])
+            .chain_err(|| "Table interior cell's rowid runs past the end of the page")?;
+        Ok(TableInteriorCell {
+            row_id: row_id as i64,
+            left,
+        })
     }
 
     fn key(&self) -> &Self::Key {
@@ -145,12 +708,17 @@ impl InteriorCell for TableInteriorCell {
 }
 
 type TableBTree = BTree<CellKey, TableInteriorCell, TableLeafCell>;
+type RawTableBTree = BTree<CellKey, TableInteriorCell, RawTableLeafCell>;
+
+// How many leaf pages `estimated_row_count()` reads.
+const ROW_COUNT_SAMPLES: usize = 8;
 
 pub struct Table {
     pager: Rc<Pager>,
     page_num: usize,
     name: String,
     schema: TableSchema,
+    columns: Vec<ColumnInfo>,
 }
 
 impl Table {
@@ -160,46 +728,527 @@ impl Table {
         name: S,
         sql: &str,
     ) -> Result<Table> {
+        let schema = TableSchema::from_sql(sql)?;
         Ok(Table {
             pager,
             page_num,
             name: name.into(),
-            schema: TableSchema::from_sql(sql)?,
+            columns: schema.column_info(),
+            schema,
         })
     }
 
+    /// Whether this is a WITHOUT ROWID table, in which case its b-tree is
+    /// really an index keyed by the primary key. See `primary_key_index()`.
+    pub fn without_rowid(&self) -> bool {
+        self.schema.without_rowid
+    }
+
+    /// The name of the index SQLite made for the table's primary key, if it
+    /// made one. A primary key that aliases the rowid doesn't need one.
+    pub fn primary_key_autoindex(&self) -> Option<String> {
+        self.schema
+            .pk_autoindex
+            .map(|n| format!("sqlite_autoindex_{}_{}", self.name, n))
+    }
+
+    /// The b-tree of a WITHOUT ROWID table, which is an index whose entries
+    /// are whole rows keyed by primary key.
+    pub fn primary_key_index(&self) -> Result<Option<Index>> {
+        if !self.without_rowid() {
+            return Ok(None);
+        }
+        // This is the name SQLite gives the index internally, although it
+        // doesn't appear in sqlite_master.
+        let name = format!("sqlite_autoindex_{}_1", self.name);
+        Index::new(
+            self.pager.clone(),
+            self.page_num,
+            self.name.clone(),
+            name,
+            IndexKind::PrimaryKey,
+            self.schema.primary_key(),
+            None,
+        )
+        .map(|index| Some(index.with_affinities(self.affinities(&self.schema.primary_key()))))
+    }
+
+    /// The type each of `columns` was declared with, which decides how
+    /// values are converted before they're stored in it. Columns the table
+    /// doesn't have are given `Type::Blob`, which converts nothing, as
+    /// SQLite's BLOB affinity doesn't.
+    pub fn affinities<S: AsRef<str>>(&self, columns: &[S]) -> Vec<Type> {
+        columns
+            .iter()
+            .map(|name| {
+                self.schema
+                    .column(name.as_ref())
+                    .map_or(Type::Blob, |col| col.ty)
+            })
+            .collect()
+    }
+
+    // The b-tree of a WITHOUT ROWID table is an index b-tree, which we can't
+    // read as a table.
+    fn btree(&self) -> Result<TableBTree> {
+        if self.without_rowid() {
+            bail!(ErrorKind::Unsupported(format!(
+                "reading WITHOUT ROWID table {} (use its primary key index)",
+                self.name
+            )));
+        }
+        TableBTree::new(self.pager.clone(), self.page_num)
+    }
+
+    /// Every leaf cell of the table with its payload left undecoded.
+    pub fn raw_cells(&self) -> Result<Vec<RawTableLeafCell>> {
+        if self.without_rowid() {
+            bail!(ErrorKind::Unsupported(format!(
+                "raw cells of WITHOUT ROWID table {}",
+                self.name
+            )));
+        }
+        let btree = RawTableBTree::new(self.pager.clone(), self.page_num)?;
+        btree.iter().collect()
+    }
+
     pub fn len(&self) -> Result<usize> {
-        let btree = TableBTree::new(self.pager.clone(), self.page_num)?;
-        Ok(btree.iter().collect::<Vec<_>>().len())
+        let btree = self.btree()?;
+        let mut len = 0;
+        for row in btree.iter() {
+            row?;
+            len += 1;
+        }
+        Ok(len)
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        let btree = self.btree()?;
+        match btree.iter().next() {
+            Some(row) => row.map(|_| false),
+            None => Ok(true),
+        }
+    }
+
+    /// The number of rows, as `len()`, but counted from the leaf pages'
+    /// headers rather than by reading each row.
+    pub fn count(&self) -> Result<usize> {
+        self.btree()?.count()
+    }
+
+    /// An estimate of the number of rows, from a sample of the table's leaf
+    /// pages. This is much cheaper than `len()` for large tables, as it only
+    /// reads a few pages besides the interior ones, but it's only accurate if
+    /// the rows are much the same size.
+    pub fn estimated_row_count(&self) -> Result<usize> {
+        self.btree()?.estimate_len(ROW_COUNT_SAMPLES)
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// The names of the columns that can be read, in the order they were
+    /// declared. VIRTUAL generated columns aren't stored, so are left out.
+    pub fn column_names(&self) -> Vec<String> {
+        self.schema
+            .columns
+            .iter()
+            .filter(|col| col.generated != Some(Generated::Virtual))
+            .map(|col| col.name.clone())
+            .collect()
+    }
+
+    /// A description of each of the table's columns, like `PRAGMA
+    /// table_info` gives. Generated columns are hidden from it, as they are
+    /// from SQLite's.
+    pub fn columns(&self) -> &[ColumnInfo] {
+        &self.columns
+    }
+
+    /// Checks each row against the constraints the schema declares, which
+    /// the structure of the b-tree says nothing about: that NOT NULL columns
+    /// aren't NULL, and that values are stored as their column's affinity
+    /// would have them. Returns the `(rowid, column, problem)` of each
+    /// violation.
+    pub fn check_constraints(&self) -> Result<Vec<(i64, String, String)>> {
+        let names: Vec<&str> = self
+            .schema
+            .columns
+            .iter()
+            .filter(|col| col.generated != Some(Generated::Virtual))
+            .map(|col| col.name.as_str())
+            .collect();
+        let colrefs = self.schema.column_indices(&names)?;
+
+        let mut violations = vec![];
+        for row in self.btree()?.iter() {
+            let row = row?;
+            for (name, colref) in names.iter().zip(colrefs.iter()) {
+                let idx = match *colref {
+                    // The rowid can't be NULL, and is always an integer.
+                    ColumnReference::RowId => continue,
+                    ColumnReference::Index(idx) | ColumnReference::Real(idx) => idx,
+                };
+                // Rows written before a column was added don't have it.
+                if idx >= row.record.len() {
+                    continue;
+                }
+                let column = self.schema.column(name).unwrap();
+                if let Some(problem) = check_value(column, &row.record[idx])? {
+                    violations.push((row.row_id, name.to_string(), problem));
+                }
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Where the row with `row_id` is stored, as the number of its leaf page
+    /// and the index of its cell on that page.
+    pub fn locate(&self, row_id: i64) -> Result<Option<(usize, usize)>> {
+        // Only the rowids are needed, so there's no need to decode records,
+        // which can't be decoded yet if they overflow. `btree()` is only
+        // for its check that the table is keyed by rowid.
+        self.btree()?;
+        RawTableBTree::new(self.pager.clone(), self.page_num)?.locate(row_id)
+    }
+
+    /// Whether `column` is the rowid, or the INTEGER PRIMARY KEY column that
+    /// aliases it.
+    pub fn is_rowid(&self, column: &str) -> bool {
+        self.schema.is_rowid(column)
+    }
+
+    /// Every page the table uses. See `BTree::pages()`.
+    pub fn pages(&self) -> Result<Vec<usize>> {
+        match self.primary_key_index()? {
+            Some(index) => index.pages(),
+            None => self.btree()?.pages(),
+        }
+    }
+
+    /// The number of levels in the table's b-tree.
+    pub fn depth(&self) -> Result<usize> {
+        self.btree()?.depth()
+    }
+
     pub fn select<S: Into<String>>(&self, columns: Vec<S>) -> Result<Vec<HashMap<String, Field>>> {
+        self.select_iter(columns)?.collect()
+    }
+
+    /// Like `select()`, but reads rows as the iterator is advanced rather
+    /// than all at once, so that any size of table can be read in bounded
+    /// memory.
+    pub fn select_iter<S: Into<String>>(
+        &self,
+        columns: Vec<S>,
+    ) -> Result<impl Iterator<Item = Result<HashMap<String, Field>>>> {
         let columns: Vec<String> = columns.into_iter().map(|s| s.into()).collect();
         let colrefs = self.schema.column_indices(&columns)?;
+        let missing = self.schema.missing_fields(&columns);
 
-        let btree = TableBTree::new(self.pager.clone(), self.page_num)?;
-        let results = btree
+        let btree = self.btree()?;
+        Ok(btree
             .iter()
-            .map(|row| {
-                columns
-                    .iter()
-                    .zip(colrefs.iter())
-                    .map(|(name, colref)| {
-                        let value = match *colref {
-                            ColumnReference::RowId => Field::from(*row.key()),
-                            ColumnReference::Index(idx) => row.record[idx].clone(), // XXX rethink
-                        };
-                        (name.clone(), value)
-                    })
-                    .collect()
+            .map(move |row| row.map(|row| project(&columns, &colrefs, &missing, &row))))
+    }
+
+    /// The last `n` rows by rowid, the most recent first. Only the pages
+    /// holding those rows are read, however big the table is.
+    pub fn last_n<S: Into<String>>(
+        &self,
+        n: usize,
+        columns: Vec<S>,
+    ) -> Result<impl Iterator<Item = Result<HashMap<String, Field>>>> {
+        let columns: Vec<String> = columns.into_iter().map(|s| s.into()).collect();
+        let colrefs = self.schema.column_indices(&columns)?;
+        let missing = self.schema.missing_fields(&columns);
+
+        let btree = self.btree()?;
+        Ok(btree
+            .iter_rev()
+            .take(n)
+            .map(move |row| row.map(|row| project(&columns, &colrefs, &missing, &row))))
+    }
+
+    /// Like `select_iter()`, but gives each row's values in the order of
+    /// `columns`.
+    pub fn select_rows<S: Into<String>>(
+        &self,
+        columns: Vec<S>,
+    ) -> Result<impl Iterator<Item = Result<Row>>> {
+        let columns: Vec<String> = columns.into_iter().map(|s| s.into()).collect();
+        let rows = self.select_iter(columns.clone())?;
+        Ok(rows.map(move |row| Row::from_fields(&columns, &row?)))
+    }
+
+    /// Like `select_rows()`, but converts each row to `T`, such as a tuple
+    /// of the values of `columns`.
+    pub fn query_as<T: FromRow, S: Into<String>>(&self, columns: Vec<S>) -> Result<Vec<T>> {
+        self.select_rows(columns)?
+            .map(|row| T::from_row(&row?))
+            .collect()
+    }
+
+    /// Like `select_rows()`, but only gives the rows for which `predicate`
+    /// returns true. Rows are read and tested as the iterator is advanced,
+    /// and errors reading them are passed on rather than filtered out.
+    pub fn scan_filter<S, F>(
+        &self,
+        columns: Vec<S>,
+        mut predicate: F,
+    ) -> Result<impl Iterator<Item = Result<Row>>>
+    where
+        S: Into<String>,
+        F: FnMut(&Row) -> bool,
+    {
+        Ok(self.select_rows(columns)?.filter(move |row| match *row {
+            Ok(ref row) => predicate(row),
+            Err(_) => true,
+        }))
+    }
+
+    /// Like `select_iter()`, but reads the table's leaf pages in the order
+    /// they appear in the file, which avoids seeking back and forth when
+    /// exporting a whole table. The order of the rows is unspecified.
+    pub fn scan_physical<S: Into<String>>(
+        &self,
+        columns: Vec<S>,
+    ) -> Result<impl Iterator<Item = Result<HashMap<String, Field>>>> {
+        let columns: Vec<String> = columns.into_iter().map(|s| s.into()).collect();
+        let colrefs = self.schema.column_indices(&columns)?;
+        let missing = self.schema.missing_fields(&columns);
+
+        let btree = self.btree()?;
+        Ok(btree
+            .iter_physical()?
+            .map(move |row| row.map(|row| project(&columns, &colrefs, &missing, &row))))
+    }
+
+    /// The values of one column, in rowid order. This saves building a map
+    /// for each row when only one column is wanted.
+    pub fn column_values(&self, column: &str) -> Result<Vec<Value>> {
+        self.column_fields(column)?
+            .map(|field| Value::from_field(&field?))
+            .collect()
+    }
+
+    /// Like `column_values()`, but reads rows as the iterator is advanced,
+    /// and gives fields as they're read. Text and blobs that fit on their
+    /// page still refer to it rather than being copied out of it. Values
+    /// that spill onto overflow pages are copied together once, as they're
+    /// read.
+    pub fn column_fields(&self, column: &str) -> Result<impl Iterator<Item = Result<Field>>> {
+        let colref = self.schema.column_indices(&[column])?.remove(0);
+        let missing = self.schema.missing_fields(&[column]).remove(0);
+        // `btree()` is only for its check that the table is keyed by rowid.
+        self.btree()?;
+        let btree = RawTableBTree::new(self.pager.clone(), self.page_num)?;
+        let pager = self.pager.clone();
+        let header = &self.pager.header;
+        let usable_size = header.page_size - header.reserved_byes_per_page;
+        Ok(btree.iter().map(move |cell| {
+            let cell = cell?;
+            let local = local_payload_len(cell.len, table_max_local(usable_size), usable_size);
+            let record = if local < cell.len {
+                let mut payload = Vec::with_capacity(cell.len);
+                cell_payload_reader(&pager, &cell.cell, cell.row_id)?.read_to_end(&mut payload)?;
+                Record::from_bytes(Bytes::from(payload))?
+            } else {
+                Record::from_bytes(cell.payload)?
+            };
+            Ok(match colref {
+                // XXX Field doesn't know integers are signed yet.
+                ColumnReference::RowId => Field::from(cell.row_id as u64),
+                // Rows written before the column was added don't have it.
+                ColumnReference::Index(idx) | ColumnReference::Real(idx) if idx >= record.len() => {
+                    missing.clone()
+                }
+                ColumnReference::Index(idx) => record.into_fields().swap_remove(idx),
+                ColumnReference::Real(idx) => real(record.into_fields().swap_remove(idx)),
             })
-            .collect();
+        }))
+    }
+
+    /// The rows with a rowid greater than `row_id`, in rowid order. Rows are
+    /// read as the iterator is advanced, so this can be used to page through
+    /// a table without skipping over the rows before each page.
+    pub fn select_from<S: Into<String>>(
+        &self,
+        row_id: i64,
+        columns: Vec<S>,
+    ) -> Result<impl Iterator<Item = Result<HashMap<String, Field>>>> {
+        let columns: Vec<String> = columns.into_iter().map(|s| s.into()).collect();
+        let colrefs = self.schema.column_indices(&columns)?;
+        let missing = self.schema.missing_fields(&columns);
+
+        let btree = self.btree()?;
+        Ok(btree
+            .iter_range(RangeGt::new(row_id))
+            .map(move |row| row.map(|row| project(&columns, &colrefs, &missing, &row))))
+    }
+
+    /// The rows with rowids from `lower` to `upper`, including both, in
+    /// rowid order. A missing bound leaves that end of the range open. Only
+    /// the pages holding the rows, and the interior pages leading to them,
+    /// are read.
+    pub fn select_range<S: Into<String>>(
+        &self,
+        lower: Option<i64>,
+        upper: Option<i64>,
+        columns: Vec<S>,
+    ) -> Result<impl Iterator<Item = Result<HashMap<String, Field>>>> {
+        let columns: Vec<String> = columns.into_iter().map(|s| s.into()).collect();
+        let colrefs = self.schema.column_indices(&columns)?;
+        let missing = self.schema.missing_fields(&columns);
 
-        Ok(results)
+        let range = RangeBetween::new(lower.unwrap_or(i64::MIN), upper.unwrap_or(i64::MAX));
+        let btree = self.btree()?;
+        Ok(btree
+            .iter_range(range)
+            .map(move |row| row.map(|row| project(&columns, &colrefs, &missing, &row))))
+    }
+
+    /// A reader of the record of the row with `row_id`, which reads the
+    /// record's overflow pages only as it gets to them.
+    pub fn payload_reader(&self, row_id: i64) -> Result<Option<PayloadReader>> {
+        let (page_num, index) = match self.locate(row_id)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let cell = read_page::<TableLeafCell>(&self.pager, page_num)?.cell(index)?;
+        cell_payload_reader(&self.pager, &cell, row_id).map(Some)
+    }
+
+    /// A reader of the value of a text or blob `column` of the row with
+    /// `row_id`. Unlike reading the row, this doesn't need the whole value
+    /// in memory at once: its overflow pages are read as the value is.
+    pub fn blob_reader(
+        &self,
+        row_id: i64,
+        column: &str,
+    ) -> Result<Option<io::Take<PayloadReader>>> {
+        let idx = match self.schema.column_indices(&[column])?.remove(0) {
+            ColumnReference::Index(idx) | ColumnReference::Real(idx) => idx,
+            ColumnReference::RowId => bail!(ErrorKind::UnexpectedType(Type::Blob, Type::Integer)),
+        };
+        let mut payload = match self.payload_reader(row_id)? {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+
+        // The record header starts with a varint giving its size in bytes,
+        // including the varint itself.
+        let mut header = Vec::new();
+        loop {
+            let mut byte = [0];
+            payload.read_exact(&mut byte)?;
+            header.push(byte[0]);
+            if byte[0] < 0x80 || header.len() == 9 {
+                break;
+            }
+        }
+        let (header_size, mut offset) = read_varint_from(&header)?;
+        let rest = (header_size as usize).saturating_sub(offset) as u64;
+        (&mut payload).take(rest).read_to_end(&mut header)?;
+
+        let mut types = Vec::new();
+        while offset < header.len() {
+            let (serial, len) = read_varint_from(&header[offset..])?;
+            types.push(serial_type(serial)?);
+            offset += len;
+        }
+        let (ty, size) = *types
+            .get(idx)
+            .ok_or_else(|| format!("Row {} has no value stored for column {}", row_id, column))?;
+        if ty != Type::Blob && ty != Type::Text {
+            bail!(ErrorKind::UnexpectedType(Type::Blob, ty));
+        }
+
+        // Skip over the values of the fields before the one we want.
+        let skip: u64 = types[..idx].iter().map(|&(_, size)| size as u64).sum();
+        if io::copy(&mut (&mut payload).take(skip), &mut io::sink())? != skip {
+            bail!("Record of row {} is shorter than its header says", row_id);
+        }
+        Ok(Some(payload.take(size as u64)))
+    }
+
+    /// Looks up a single row by its rowid.
+    pub fn get<S: AsRef<str>>(
+        &self,
+        row_id: i64,
+        columns: &[S],
+    ) -> Result<Option<HashMap<String, Field>>> {
+        let colrefs = self.schema.column_indices(columns)?;
+        let missing = self.schema.missing_fields(columns);
+        let btree = self.btree()?;
+        Ok(btree
+            .get(row_id)?
+            .map(|row| project(columns, &colrefs, &missing, &row)))
+    }
+}
+
+// What's wrong with storing `value` in `column`, if anything.
+fn check_value(column: &Column, value: &Field) -> Result<Option<String>> {
+    Ok(match (column.ty, value.ty()) {
+        (_, Type::Null) if column.not_null => Some("NULL in NOT NULL column".to_owned()),
+        // TEXT affinity turns numbers into text before they're stored.
+        (Type::Text, ty @ Type::Integer) | (Type::Text, ty @ Type::Float) => {
+            Some(format!("{:?} stored in TEXT column", ty))
+        }
+        // INTEGER affinity turns text that looks like a number into one.
+        (Type::Integer, Type::Text) if looks_numeric(&value.as_text_lossy()?) => {
+            Some("numeric text stored in INTEGER column".to_owned())
+        }
+        _ => None,
+    })
+}
+
+fn project<S: AsRef<str>>(
+    columns: &[S],
+    colrefs: &[ColumnReference],
+    missing: &[Field],
+    row: &TableLeafCell,
+) -> HashMap<String, Field> {
+    columns
+        .iter()
+        .zip(colrefs.iter().zip(missing))
+        .map(|(name, (colref, missing))| {
+            let value = match *colref {
+                // XXX Field doesn't know integers are signed yet.
+                ColumnReference::RowId => Field::from(*row.key() as u64),
+                // Rows written before the column was added don't have it.
+                ColumnReference::Index(idx) | ColumnReference::Real(idx)
+                    if idx >= row.record.len() =>
+                {
+                    missing.clone()
+                }
+                ColumnReference::Index(idx) => row.record[idx].clone(), // XXX rethink
+                ColumnReference::Real(idx) => real(row.record[idx].clone()),
+            };
+            (name.as_ref().to_owned(), value)
+        })
+        .collect()
+}
+
+// The value of a DEFAULT. CURRENT_TIME and the like can't be the default of
+// a column added to a table that has rows, so they never need reading.
+fn literal_value(literal: &Literal) -> Value {
+    match *literal {
+        Literal::Integer(i) => Value::Integer(i),
+        Literal::FixedPoint(_) => Value::Float(literal.to_string().parse().unwrap_or(0.0)),
+        Literal::String(ref s) => Value::Text(s.clone()),
+        Literal::Blob(ref b) => Value::Blob(b.clone()),
+        _ => Value::Null,
+    }
+}
+
+// A field of a REAL column, with any integer turned into the real it was
+// stored for.
+fn real(field: Field) -> Field {
+    match field.as_signed_integer() {
+        Ok(i) => Field::from(i as f64),
+        Err(_) => field,
     }
 }
 
@@ -212,3 +1261,757 @@ impl fmt::Debug for Table {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::{Params, Statement};
+    use crate::testing::{open_doctored_fixture, open_fixture};
+
+    fn unsupported(sql: &str) -> String {
+        match TableSchema::from_sql(sql) {
+            Err(Error(ErrorKind::Unsupported(feature), _)) => feature,
+            other => panic!("Expected Unsupported, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_table_options() {
+        let schema =
+            TableSchema::from_sql("CREATE TABLE kv(k text primary key, v int) WITHOUT ROWID")
+                .unwrap();
+        assert!(schema.without_rowid);
+        assert_eq!(unsupported("CREATE TABLE t(a int) STRICT"), "STRICT tables");
+        assert_eq!(
+            unsupported("CREATE TABLE t(a int) WITHOUT ROWID, STRICT;"),
+            "STRICT tables"
+        );
+    }
+
+    #[test]
+    fn test_autoincrement() {
+        let schema = TableSchema::from_sql(
+            "CREATE TABLE jobs(id integer primary key AutoIncrement, autoincrements int)",
+        )
+        .unwrap();
+        assert_eq!(schema.primary_key, vec!["id"]);
+        assert_eq!(schema.columns[1].name, "autoincrements");
+    }
+
+    #[test]
+    fn test_autoincrement_rowid_alias() {
+        // The id column isn't stored in the record, so reading it must give
+        // the rowid rather than the NULL stored in its place.
+        let schema = open_fixture("autoincrement.db");
+        let ids = |name| -> Vec<u64> {
+            let table = schema.table(name).unwrap();
+            table
+                .select(vec!["id"])
+                .unwrap()
+                .iter()
+                .map(|row| row["id"].as_integer().unwrap())
+                .collect()
+        };
+        assert_eq!(ids("jobs"), vec![1, 2, 3]);
+        assert_eq!(ids("runs"), vec![100]);
+        let jobs = schema.table("jobs").unwrap();
+        let row = jobs.get(2, &["id", "name"]).unwrap().unwrap();
+        assert_eq!(row["id"].as_integer().unwrap(), 2);
+        assert_eq!(row["name"].as_text().unwrap(), "job 1");
+    }
+
+    #[test]
+    fn test_boolean_and_date_columns() {
+        let schema = open_fixture("dates.db");
+        let table = schema.table("tasks").unwrap();
+        let types: Vec<_> = table.columns().iter().map(|c| c.ty.as_str()).collect();
+        assert_eq!(
+            types,
+            ["integer", "BOOLEAN", "DATE", "DATETIME", "TIMESTAMP"]
+        );
+
+        let values = |column| table.column_values(column).unwrap();
+        assert_eq!(values("done"), [Value::Integer(1), Value::Integer(0)]);
+        assert_eq!(
+            values("due"),
+            [Value::Text("2024-01-31".to_owned()), Value::Null]
+        );
+        // Text that looks like a number was stored as one.
+        assert_eq!(
+            values("updated"),
+            [
+                Value::Text("2024-01-02 03:04:05".to_owned()),
+                Value::Integer(1_704_164_645)
+            ]
+        );
+        assert_eq!(
+            values("created"),
+            [
+                Value::Integer(1_704_164_645),
+                Value::Text("2024-01-02T03:04:05Z".to_owned())
+            ]
+        );
+        assert!(table.check_constraints().unwrap().is_empty());
+
+        let rows = Statement::prepare("SELECT id FROM tasks WHERE done = 1")
+            .unwrap()
+            .execute(&schema, Params::None)
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["id"].as_integer().unwrap(), 1);
+
+        // A DATE primary key doesn't alias the rowid, so it's read from the
+        // row rather than standing in for the rowid.
+        let holidays = schema.table("holidays").unwrap();
+        assert!(!holidays.is_rowid("day"));
+        let rows = Statement::prepare("SELECT day, name FROM holidays")
+            .unwrap()
+            .execute(&schema, Params::None)
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["day"].as_integer().unwrap(), 20_240_101);
+        assert_eq!(rows[0]["name"].as_text().unwrap(), "new year");
+    }
+
+    #[test]
+    fn test_real_stored_as_integer() {
+        // 2.0 and -3.0 are stored as the integers 2 and -3.
+        let schema = open_fixture("reals.db");
+        let table = schema.table("readings").unwrap();
+        let raw = table.raw_cells().unwrap();
+        let record = Record::from_bytes(raw[0].payload.clone()).unwrap();
+        assert_eq!(record[1].ty(), Type::Integer);
+
+        let values = table.column_values("value").unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Value::Float(2.0),
+                Value::Float(2.5),
+                Value::Float(-3.0),
+                Value::Null
+            ]
+        );
+        let row = table.get(1, &["value"]).unwrap().unwrap();
+        assert_eq!(row["value"].as_float().unwrap(), 2.0);
+
+        let rows = Statement::prepare("SELECT id FROM readings WHERE value < 2.1")
+            .unwrap()
+            .execute(&schema, Params::None)
+            .unwrap();
+        let mut ids: Vec<_> = rows
+            .iter()
+            .map(|row| row["id"].as_integer().unwrap())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_column_affinity() {
+        let schema = TableSchema::from_sql(
+            "CREATE TABLE t(a bigint, b varchar(10), c longtext, d blob, e double, \
+             f numeric, g decimal(5,2), h date, i Boolean)",
+        )
+        .unwrap();
+        let affinities: Vec<_> = schema
+            .columns
+            .iter()
+            .map(|col| (col.ty, col.numeric))
+            .collect();
+        assert_eq!(
+            affinities,
+            vec![
+                (Type::Integer, false),
+                (Type::Text, false),
+                (Type::Text, false),
+                (Type::Blob, false),
+                (Type::Float, false),
+                (Type::Integer, true),
+                (Type::Integer, true),
+                (Type::Integer, true),
+                (Type::Integer, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_ddl() {
+        assert_eq!(
+            unsupported("CREATE TABLE t(a int) WITHOUT ROWID, FOO"),
+            "table option FOO"
+        );
+    }
+
+    #[test]
+    fn test_generated_columns() {
+        let schema = open_fixture("generated.db");
+        let table = schema.table("shapes").unwrap();
+        let rows = table.select(vec!["side", "area", "name"]).unwrap();
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|row| {
+                (
+                    row["side"].as_integer().unwrap(),
+                    row["area"].as_integer().unwrap(),
+                    row["name"].as_text().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(rows, vec![(2, 4, "small"), (5, 25, "large")]);
+
+        match table.select(vec!["perimeter"]) {
+            Err(Error(ErrorKind::Unsupported(_), _)) => {}
+            other => panic!("Expected Unsupported, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_raw_cells() {
+        let schema = open_fixture("large.db");
+        let table = schema.table("numbers").unwrap();
+        let cells = table.raw_cells().unwrap();
+        assert_eq!(cells.len(), table.len().unwrap());
+
+        let cell = &cells[41];
+        assert_eq!(cell.row_id, 42);
+        let record = Record::from_bytes(cell.payload.clone()).unwrap();
+        assert_eq!(record[1].as_integer().unwrap(), 294);
+        assert_eq!(record[2].as_text().unwrap(), "row-42");
+    }
+
+    #[test]
+    fn test_signed_rowids() {
+        let schema = open_fixture("deep.db");
+        let table = schema.table("deep").unwrap();
+        for &row_id in &[-(1i64 << 62), -1000, -1, 1, 511, 12345, 20000, 1 << 62] {
+            let row = table.get(row_id, &["value"]).unwrap().unwrap();
+            assert_eq!(
+                row["value"].as_integer().unwrap() as i64,
+                row_id.rem_euclid(1000)
+            );
+        }
+        assert!(table.get(0, &["value"]).unwrap().is_none());
+        assert!(table.get(20001, &["value"]).unwrap().is_none());
+
+        let row_ids: Vec<i64> = table
+            .raw_cells()
+            .unwrap()
+            .iter()
+            .map(|cell| cell.row_id)
+            .collect();
+        let mut expected = vec![-(1i64 << 62), -1000, -1];
+        expected.extend(1..=20000);
+        expected.push(1 << 62);
+        assert_eq!(row_ids, expected);
+    }
+
+    #[test]
+    fn test_select_from() {
+        let schema = open_fixture("deep.db");
+        let table = schema.table("deep").unwrap();
+
+        let mut row_ids = vec![];
+        let mut last = i64::MIN;
+        loop {
+            let page = table
+                .select_from(last, vec!["id"])
+                .unwrap()
+                .take(1000)
+                .map(|row| row.unwrap()["id"].as_integer().unwrap() as i64)
+                .collect::<Vec<_>>();
+            match page.last() {
+                Some(&row_id) => last = row_id,
+                None => break,
+            }
+            row_ids.extend(page);
+        }
+
+        let all: Vec<i64> = table
+            .raw_cells()
+            .unwrap()
+            .iter()
+            .map(|cell| cell.row_id)
+            .collect();
+        assert_eq!(all.len(), 20004);
+        assert_eq!(row_ids, all);
+    }
+
+    #[test]
+    fn test_depth() {
+        let depth = |fixture, table| open_fixture(fixture).table(table).unwrap().depth().unwrap();
+        assert_eq!(depth("sales.db", "sales"), 1);
+        assert_eq!(depth("large.db", "numbers"), 2);
+        assert_eq!(depth("deep.db", "deep"), 3);
+    }
+
+    #[test]
+    fn test_table_level_primary_key() {
+        let schema =
+            TableSchema::from_sql("CREATE TABLE t(id integer, name text, PRIMARY KEY (id))")
+                .unwrap();
+        assert_eq!(schema.primary_key(), vec!["id"]);
+        match schema.column_indices(&["id", "name"]).unwrap().as_slice() {
+            [ColumnReference::RowId, ColumnReference::Index(1)] => {}
+            other => panic!("Expected id to alias the rowid, got {:?}", other),
+        }
+
+        let schema = TableSchema::from_sql(
+            "CREATE TABLE t(a int, b text, c int, PRIMARY KEY (c, a)) WITHOUT ROWID",
+        )
+        .unwrap();
+        assert_eq!(schema.primary_key(), vec!["c", "a"]);
+        match schema.column_indices(&["a", "c"]).unwrap().as_slice() {
+            [ColumnReference::Index(0), ColumnReference::Index(2)] => {}
+            other => panic!("Expected stored columns, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pages() {
+        let schema = open_fixture("overflow.db");
+        let docs = schema.table("docs").unwrap().pages().unwrap();
+        let tags = schema.table("tags").unwrap().pages().unwrap();
+        let index = schema.indices().unwrap()[0].pages().unwrap();
+        assert!(docs.len() > 10 && tags.len() > 1 && index.len() > 10);
+
+        // Along with the schema on page 1, they account for every page in the
+        // database, each exactly once.
+        let mut all = vec![1];
+        all.extend(docs);
+        all.extend(tags);
+        all.extend(index);
+        all.sort();
+        let num_pages = schema.table("docs").unwrap().pager.header.num_pages;
+        assert_eq!(all, (1..=num_pages).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_estimated_row_count() {
+        for &(fixture, name) in &[
+            ("deep.db", "deep"),
+            ("large.db", "numbers"),
+            ("sales.db", "sales"),
+        ] {
+            let schema = open_fixture(fixture);
+            let table = schema.table(name).unwrap();
+            let estimate = table.estimated_row_count().unwrap() as f64;
+            let len = table.len().unwrap() as f64;
+            assert!(
+                estimate > len * 0.8 && estimate < len * 1.25,
+                "{}: estimated {} rows, but there are {}",
+                name,
+                estimate,
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_blob_reader() {
+        let schema = open_fixture("overflow.db");
+        let docs = schema.table("docs").unwrap();
+        // Row 10's body is 21,000 bytes, spread over 1024-byte pages.
+        let expected = "doc 10 ".repeat(3000);
+        let mut reader = docs.blob_reader(10, "body").unwrap().unwrap();
+        let mut body = Vec::new();
+        let mut chunk = [0; 1000];
+        loop {
+            let len = reader.read(&mut chunk).unwrap();
+            if len == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..len]);
+        }
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+
+        let mut tag = String::new();
+        let tags = schema.table("tags").unwrap();
+        let mut reader = tags.blob_reader(1, "tag").unwrap().unwrap();
+        reader.read_to_string(&mut tag).unwrap();
+        assert_eq!(tag, "tag-0");
+
+        assert!(docs.blob_reader(11, "body").unwrap().is_none());
+        assert!(docs.blob_reader(1, "id").is_err());
+        assert!(tags.blob_reader(1, "doc").is_err());
+    }
+
+    #[test]
+    fn test_check_constraints() {
+        const SQL: &str =
+            "CREATE TABLE sales(id integer primary key, region text not null, amount int not null)";
+        let pager = open_doctored_fixture("sales.db", |bytes| {
+            // The first row's record header is [0, 23, 1], for a NULL (the
+            // rowid alias), the text "north" and a one-byte integer. Make the
+            // integer NULL.
+            let page = &mut bytes[4096..8192];
+            let cell = BigEndian::read_u16(&page[8..]) as usize;
+            assert_eq!(&page[cell + 2..cell + 6], &[4, 0, 23, 1]);
+            page[cell + 5] = 0;
+        });
+        let table = Table::new(pager.clone(), 2, "sales", SQL).unwrap();
+        assert_eq!(
+            table.check_constraints().unwrap(),
+            vec![(1, "amount".to_owned(), "NULL in NOT NULL column".to_owned())]
+        );
+
+        // Numbers can't end up in a TEXT column.
+        let sql = "CREATE TABLE sales(id integer primary key, region text, amount text)";
+        let table = Table::new(pager, 2, "sales", sql).unwrap();
+        let violations = table.check_constraints().unwrap();
+        assert_eq!(violations.len(), 10);
+        assert_eq!(violations[0].2, "Integer stored in TEXT column");
+    }
+
+    #[test]
+    fn test_columns() {
+        let pager = Rc::new(Pager::open("fixtures/sales.db").unwrap());
+        let sql = "CREATE TABLE t(a text not null default 'x', b int, c real, \
+                   PRIMARY KEY (b, a))";
+        let table = Table::new(pager, 2, "t", sql).unwrap();
+        let columns = table.columns();
+        let names: Vec<_> = columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        let affinities: Vec<_> = columns.iter().map(|c| c.affinity).collect();
+        assert_eq!(affinities, [Type::Text, Type::Integer, Type::Float]);
+        let pks: Vec<_> = columns.iter().map(|c| c.pk).collect();
+        assert_eq!(pks, [2, 1, 0]);
+        assert!(columns[0].not_null);
+        assert!(!columns[1].not_null);
+        assert_eq!(columns[0].default, Some("'x'".to_owned()));
+        assert_eq!(columns[1].default, None);
+
+        // Generated columns aren't listed, and the cids of the others run on
+        // without gaps.
+        let schema = open_fixture("generated.db");
+        let table = schema.table("shapes").unwrap();
+        let columns: Vec<_> = table
+            .columns()
+            .iter()
+            .map(|c| (c.cid, c.name.as_str()))
+            .collect();
+        assert_eq!(columns, [(0, "side"), (1, "name")]);
+    }
+
+    #[test]
+    fn test_locate() {
+        let schema = open_fixture("deep.db");
+        let table = schema.table("deep").unwrap();
+        for &row_id in &[-(1i64 << 62), 1, 12345, 20000, 1 << 62] {
+            let (page_num, index) = table.locate(row_id).unwrap().unwrap();
+            let page = table.pager.get_page(page_num).unwrap();
+            assert_eq!(page[0], 0x0d, "page {} isn't a table leaf", page_num);
+            let pointer = BigEndian::read_u16(&page[8 + index * 2..]) as usize;
+            let cell = TableLeafCell::from_bytes(page.slice_from(pointer)).unwrap();
+            assert_eq!(cell.row_id, row_id);
+        }
+        assert_eq!(table.locate(0).unwrap(), None);
+        assert_eq!(table.locate(20001).unwrap(), None);
+    }
+
+    #[test]
+    fn test_select_rows() {
+        let schema = open_fixture("sales.db");
+        let table = schema.table("sales").unwrap();
+        let row = table
+            .select_rows(vec!["amount", "region"])
+            .unwrap()
+            .nth(2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.columns(), &["amount", "region"]);
+        assert_eq!(row.value_at(0).unwrap(), &Value::Integer(30));
+        assert_eq!(row.value("region").unwrap(), &Value::from("north"));
+    }
+
+    #[test]
+    fn test_scan_filter() {
+        let schema = open_fixture("sales.db");
+        let table = schema.table("sales").unwrap();
+        let mut tested = 0;
+        let rows: Vec<_> = table
+            .scan_filter(vec!["id", "amount"], |row| {
+                tested += 1;
+                match *row.value("id").unwrap() {
+                    Value::Integer(id) => id % 2 == 0,
+                    _ => false,
+                }
+            })
+            .unwrap()
+            .take(2)
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(rows[0].values(), &[Value::Integer(2), Value::Integer(20)]);
+        assert_eq!(rows[1].values(), &[Value::Integer(4), Value::Integer(40)]);
+        // Taking two rows only read as far as the second even id.
+        assert_eq!(tested, 4);
+    }
+
+    #[test]
+    fn test_query_as() {
+        let schema = open_fixture("sales.db");
+        let table = schema.table("sales").unwrap();
+        let rows: Vec<(i64, String)> = table.query_as(vec!["id", "region"]).unwrap();
+        assert_eq!(rows.len(), table.len().unwrap());
+        assert_eq!(rows[0], (1, "north".to_owned()));
+
+        let rows: Result<Vec<(String, String)>> = table.query_as(vec!["id", "region"]);
+        assert!(rows.is_err());
+    }
+
+    #[test]
+    fn test_scan_physical() {
+        let schema = open_fixture("deep.db");
+        let table = schema.table("deep").unwrap();
+        let rows = |iter: Box<dyn Iterator<Item = Result<HashMap<String, Field>>>>| {
+            let mut rows: Vec<_> = iter
+                .map(|row| {
+                    let row = row.unwrap();
+                    (
+                        row["id"].as_integer().unwrap(),
+                        row["value"].as_integer().unwrap(),
+                    )
+                })
+                .collect();
+            rows.sort();
+            rows
+        };
+        let ordered = rows(Box::new(table.select_iter(vec!["id", "value"]).unwrap()));
+        let physical = rows(Box::new(table.scan_physical(vec!["id", "value"]).unwrap()));
+        assert_eq!(ordered.len(), 20004);
+        assert_eq!(physical, ordered);
+
+        let leaves = table.btree().unwrap().leaves().unwrap();
+        assert!(leaves.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_last_n() {
+        let schema = open_fixture("large.db");
+        let table = schema.table("numbers").unwrap();
+        let ids = |n| -> Vec<u64> {
+            table
+                .last_n(n, vec!["id", "label"])
+                .unwrap()
+                .map(|row| row.unwrap()["id"].as_integer().unwrap())
+                .collect()
+        };
+        assert_eq!(ids(5), vec![5000, 4999, 4998, 4997, 4996]);
+        assert_eq!(ids(0), Vec::<u64>::new());
+
+        // Reading every row backwards gives them all, in reverse.
+        let all: Vec<_> = table.btree().unwrap().iter().map(|c| c.unwrap()).collect();
+        let rev: Vec<_> = table
+            .btree()
+            .unwrap()
+            .iter_rev()
+            .map(|c| c.unwrap())
+            .collect();
+        assert_eq!(all.len(), 5000);
+        assert!(all
+            .iter()
+            .rev()
+            .map(|c| c.row_id)
+            .eq(rev.iter().map(|c| c.row_id)));
+    }
+
+    #[test]
+    fn test_index_root_page() {
+        // Page 3 is the root of the items_price index, not a table.
+        let pager = Rc::new(Pager::open("fixtures/indexed.db").unwrap());
+        let sql = "CREATE TABLE items(id integer primary key, price int, name text)";
+        let table = Table::new(pager, 3, "items", sql).unwrap();
+        match table.select(vec!["price"]) {
+            Err(Error(ErrorKind::UnexpectedPageType(3, 0x0a, BTreeKind::Table), _)) => {}
+            other => panic!("Expected UnexpectedPageType, got {:?}", other),
+        }
+        assert!(table.get(1, &["price".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn test_column_values() {
+        let schema = open_fixture("large.db");
+        let table = schema.table("numbers").unwrap();
+        let labels = table.column_values("label").unwrap();
+        let selected: Vec<_> = table
+            .select(vec!["label"])
+            .unwrap()
+            .iter()
+            .map(|row| Value::from_field(&row["label"]).unwrap())
+            .collect();
+        assert_eq!(labels.len(), 5000);
+        assert_eq!(labels, selected);
+        assert_eq!(labels[0], Value::from("row-1"));
+
+        let ids = table.column_values("id").unwrap();
+        assert_eq!(ids, (1..=5000).map(Value::Integer).collect::<Vec<_>>());
+        assert!(table.column_values("missing").is_err());
+    }
+
+    #[test]
+    fn test_column_values_after_add_column() {
+        // The first two rows were written before qty, price and note were
+        // added, so they read as the columns' defaults.
+        let schema = open_fixture("altered.db");
+        let table = schema.table("items").unwrap();
+        assert_eq!(
+            table.column_values("qty").unwrap(),
+            vec![Value::Integer(5), Value::Integer(5), Value::Integer(7)]
+        );
+        assert_eq!(
+            table.column_values("price").unwrap(),
+            vec![Value::Float(2.0), Value::Float(2.0), Value::Float(0.5)]
+        );
+        assert_eq!(
+            table.column_values("note").unwrap(),
+            vec![Value::Null, Value::Null, Value::from("loose")]
+        );
+    }
+
+    #[test]
+    fn test_table_interior_cell() {
+        let cell = |bytes: &[u8]| TableInteriorCell::from_bytes(Bytes::from(bytes));
+
+        // Whatever follows the cell on the page is ignored.
+        let decoded = cell(&[0, 0, 1, 2, 0x82, 0x2c, 0xff, 0xff]).unwrap();
+        assert_eq!(decoded.left(), 258);
+        assert_eq!(*decoded.key(), 300);
+        let decoded = cell(&[
+            0, 0, 0, 7, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+        ])
+        .unwrap();
+        assert_eq!(decoded.left(), 7);
+        assert_eq!(*decoded.key(), -2);
+
+        assert!(cell(&[0, 0, 0, 7]).is_err());
+        assert!(cell(&[0, 0, 0, 7, 0x81]).is_err());
+        assert!(cell(&[0, 0, 7]).is_err());
+    }
+
+    #[test]
+    fn test_table_info() {
+        let pager = Rc::new(Pager::open("fixtures/sales.db").unwrap());
+        let sql =
+            "CREATE TABLE t(a int NOT NULL DEFAULT 5, b text DEFAULT 'x', PRIMARY KEY (b, a))";
+        let table = Table::new(pager, 2, "t", sql).unwrap();
+        assert_eq!(
+            table.columns(),
+            [
+                ColumnInfo {
+                    cid: 0,
+                    name: "a".to_owned(),
+                    ty: "int".to_owned(),
+                    affinity: Type::Integer,
+                    not_null: true,
+                    default: Some("5".to_owned()),
+                    pk: 2,
+                },
+                ColumnInfo {
+                    cid: 1,
+                    name: "b".to_owned(),
+                    ty: "text".to_owned(),
+                    affinity: Type::Text,
+                    not_null: false,
+                    default: Some("'x'".to_owned()),
+                    pk: 1,
+                },
+            ]
+        );
+
+        // Types are given as they're written, even where nom_sql knows them
+        // by another name.
+        let sql = "CREATE TABLE u(a int(11) NOT NULL, b double DEFAULT 1, c Boolean)";
+        let table = Table::new(table.pager.clone(), 2, "u", sql).unwrap();
+        let types: Vec<_> = table.columns().iter().map(|c| c.ty.as_str()).collect();
+        assert_eq!(types, ["int(11)", "double", "Boolean"]);
+    }
+
+    #[test]
+    fn test_column_fields_share_page() {
+        let schema = open_fixture("blobs.db");
+        let table = schema.table("files").unwrap();
+        let data: Vec<_> = table
+            .column_fields("data")
+            .unwrap()
+            .map(|field| field.unwrap().to_bytes().unwrap())
+            .collect();
+        assert_eq!(data.len(), 10);
+        assert!(data.iter().all(|blob| blob.len() == 2000));
+        assert_eq!(data[2][0], 3);
+
+        // A row's fields are slices of its page, so each name is followed
+        // directly by its data in memory.
+        for row in table.select(vec!["name", "data"]).unwrap() {
+            let name = row["name"].to_bytes().unwrap();
+            let data = row["data"].to_bytes().unwrap();
+            assert_eq!(name.as_ptr() as usize + name.len(), data.as_ptr() as usize);
+        }
+    }
+
+    #[test]
+    fn test_column_fields_overflow() {
+        // Each body is bigger than a page, so is put back together from its
+        // overflow pages.
+        let schema = open_fixture("overflow.db");
+        let table = schema.table("docs").unwrap();
+        let bodies: Vec<_> = table
+            .column_fields("body")
+            .unwrap()
+            .map(|field| field.unwrap().to_bytes().unwrap())
+            .collect();
+        assert_eq!(bodies.len(), 10);
+        for (n, body) in (1..).zip(&bodies) {
+            assert_eq!(*body, format!("doc {} ", n).repeat(n * 300).as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_column_fields_after_add_column() {
+        let schema = open_fixture("altered.db");
+        let table = schema.table("items").unwrap();
+        let types: Vec<_> = table
+            .column_fields("note")
+            .unwrap()
+            .map(|field| field.unwrap().ty())
+            .collect();
+        assert_eq!(types, vec![Type::Null, Type::Null, Type::Text]);
+
+        // The default is given with the column's affinity, as a real.
+        let prices: Vec<_> = table
+            .column_fields("price")
+            .unwrap()
+            .map(|field| field.unwrap().as_float().unwrap())
+            .collect();
+        assert_eq!(prices, vec![2.0, 2.0, 0.5]);
+    }
+
+    #[test]
+    fn test_select_after_add_column() {
+        let schema = open_fixture("altered.db");
+        let table = schema.table("items").unwrap();
+        let mut rows = table.select(vec!["id", "qty", "price", "note"]).unwrap();
+        rows.sort_by_key(|row| row["id"].as_integer().unwrap());
+        let qty: Vec<_> = rows
+            .iter()
+            .map(|row| row["qty"].as_integer().unwrap())
+            .collect();
+        assert_eq!(qty, vec![5, 5, 7]);
+        assert_eq!(rows[0]["price"].as_float().unwrap(), 2.0);
+        assert_eq!(rows[1]["note"].ty(), Type::Null);
+        assert_eq!(rows[2]["note"].as_text().unwrap(), "loose");
+
+        // And the other ways of reading rows.
+        let row = table.get(1, &["qty", "note"]).unwrap().unwrap();
+        assert_eq!(row["qty"].as_integer().unwrap(), 5);
+        assert_eq!(row["note"].ty(), Type::Null);
+        assert_eq!(table.last_n(3, vec!["price"]).unwrap().count(), 3);
+        assert_eq!(
+            table
+                .select_range(None, Some(2), vec!["qty"])
+                .unwrap()
+                .count(),
+            2
+        );
+        assert_eq!(table.scan_physical(vec!["note"]).unwrap().count(), 3);
+    }
+}
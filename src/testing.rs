@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+
+use crate::errors::*;
+use crate::pager::Pager;
+use crate::query::{Params, Statement};
+use crate::record::Field;
+use crate::schema::Schema;
+
+/// Opens one of the databases built by `fixtures/generate.py`.
+pub fn open_fixture(name: &str) -> Schema {
+    let pager = Pager::open(format!("fixtures/{}", name)).unwrap();
+    Schema::new(Rc::new(pager)).unwrap()
+}
+
+/// Runs a statement that takes no parameters, and returns its rows.
+pub fn query(schema: &Schema, sql: &str) -> Vec<HashMap<String, Field>> {
+    Statement::prepare(sql)
+        .unwrap()
+        .execute(schema, Params::None)
+        .unwrap()
+}
+
+/// Opens a copy of one of the fixtures, after letting `edit` change its bytes.
+pub fn open_doctored_fixture<F: FnOnce(&mut Vec<u8>)>(name: &str, edit: F) -> Rc<Pager> {
+    open_doctored_fixture_with(name, edit, |path| Pager::open(path))
+}
+
+/// Like `open_doctored_fixture()`, but opens the copy with `open`.
+pub fn open_doctored_fixture_with<F, O>(name: &str, edit: F, open: O) -> Rc<Pager>
+where
+    F: FnOnce(&mut Vec<u8>),
+    O: FnOnce(&Path) -> Result<Pager>,
+{
+    let mut bytes = fs::read(format!("fixtures/{}", name)).unwrap();
+    edit(&mut bytes);
+
+    // Tests run in parallel, so each copy needs its own name.
+    let path = env::temp_dir().join(format!("rqlite-{}-{}-{:p}", process::id(), name, &bytes));
+    fs::write(&path, &bytes).unwrap();
+    let pager = open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    Rc::new(pager)
+}
@@ -46,9 +46,79 @@ pub fn read_varint<R: Read>(mut data: R) -> Result<u64> {
     Ok(value)
 }
 
+/// Like `read_varint()`, but reads from the start of a slice, returning the
+/// value along with the number of bytes it took up.
+pub fn read_varint_from(data: &[u8]) -> Result<(u64, usize)> {
+    let mut value: u64 = 0;
+    for (i, &byte) in data.iter().take(9).enumerate() {
+        if i == 8 {
+            return Ok(((value << 8) | byte as u64, 9));
+        }
+        value = (value << 7) | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    bail!(ErrorKind::InvalidVarint)
+}
+
+/// Encodes a varint the way sqlite3's putVarint64() does: the inverse of
+/// `read_varint()`, using as few bytes as it can.
+pub fn write_varint(mut value: u64) -> Vec<u8> {
+    if value > 0x00ff_ffff_ffff_ffff {
+        let mut bytes = vec![0; 9];
+        bytes[8] = value as u8;
+        value >>= 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (value & 0x7F) as u8 | 0x80;
+            value >>= 7;
+        }
+        return bytes;
+    }
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value != 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+/// Formats bytes as a classic hex dump: 16 bytes per line, each line starting
+/// with the offset of its first byte (counting from `base`) and ending with
+/// the printable ASCII characters among them.
+pub fn hexdump(data: &[u8], base: usize) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex = line
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        out.push_str(&format!(
+            "{:08x}  {:<47}  |{}|\n",
+            base + i * 16,
+            hex,
+            ascii
+        ));
+    }
+    out
+}
+
 #[cfg(test)]
 mod test {
-    use super::read_varint;
+    use super::{hexdump, read_varint, read_varint_from, write_varint};
 
     use std::io::Cursor;
 
@@ -76,4 +146,41 @@ mod test {
         // have it's most significant bit clear.
         assert!(read_varint(Cursor::new(&[0x81])).is_err());
     }
+
+    #[test]
+    fn test_read_varint_from() {
+        let values = [
+            0,
+            0x7f,
+            0x80,
+            0x3fff,
+            0x4000,
+            0x1234567,
+            0x00ff_ffff_ffff_ffff,
+            0x0100_0000_0000_0000,
+            u64::MAX,
+        ];
+        for &value in &values {
+            let mut bytes = write_varint(value);
+            let len = bytes.len();
+            // Anything after the varint is left alone.
+            bytes.extend(&[0xff, 0xff]);
+            assert_eq!(read_varint_from(&bytes).unwrap(), (value, len));
+            assert_eq!(read_varint(Cursor::new(&bytes)).unwrap(), value);
+        }
+        assert_eq!(write_varint(u64::MAX), vec![0xff; 9]);
+
+        assert!(read_varint_from(&[]).is_err());
+        assert!(read_varint_from(&[0x81, 0x81]).is_err());
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dump = hexdump(b"SQLite format 3\0\x10\x00", 0x20);
+        assert_eq!(
+            dump,
+            "00000020  53 51 4c 69 74 65 20 66 6f 72 6d 61 74 20 33 00  |SQLite format 3.|\n\
+             00000030  10 00                                            |..|\n"
+        );
+    }
 }
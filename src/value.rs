@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use crate::errors::*;
+use crate::record::{Field, LiteralValue};
+use crate::types::Type;
+
+/// An owned SQL value.
+///
+/// `Field` is how values come out of a record, and may still borrow the
+/// page it was read from. `Value` is what callers hand to us (e.g. when
+/// binding parameters), so it's a plain owned enum.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// What to do with text that isn't valid UTF-8 when it's read.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TextDecoding {
+    /// Fail, as the text can't be read as it was written.
+    Strict,
+    /// Replace the invalid bytes with U+FFFD, so that the rest of a
+    /// partially corrupt table can still be read.
+    Lossy,
+}
+
+impl Value {
+    pub fn ty(&self) -> Type {
+        match *self {
+            Value::Null => Type::Null,
+            Value::Integer(_) => Type::Integer,
+            Value::Float(_) => Type::Float,
+            Value::Text(_) => Type::Text,
+            Value::Blob(_) => Type::Blob,
+        }
+    }
+
+    /// An owned copy of a field read from a record.
+    pub fn from_field(field: &Field) -> Result<Value> {
+        Value::from_field_with(field, TextDecoding::Strict)
+    }
+
+    /// Like `from_field()`, but decodes text as `decoding` says.
+    pub fn from_field_with(field: &Field, decoding: TextDecoding) -> Result<Value> {
+        Ok(match field.ty() {
+            Type::Null => Value::Null,
+            // XXX Field doesn't know integers are signed yet.
+            Type::Integer => Value::Integer(field.as_integer()? as i64),
+            Type::Float => Value::Float(field.as_float()?),
+            Type::Text => match decoding {
+                TextDecoding::Strict => Value::Text(field.as_text()?.to_owned()),
+                TextDecoding::Lossy => Value::Text(field.as_text_lossy()?.into_owned()),
+            },
+            Type::Blob => Value::Blob(field.as_blob()?.to_owned()),
+        })
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Float(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Text(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::Text(value)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Value {
+        Value::Blob(value)
+    }
+}
+
+impl From<Value> for Field {
+    fn from(value: Value) -> Field {
+        if let Value::Float(f) = value {
+            return Field::from(f);
+        }
+        Field::Literal(match value {
+            Value::Null => LiteralValue::Null,
+            // XXX Field doesn't know integers are signed yet, so we store the
+            //     two's complement bit pattern, which is what's on disk.
+            Value::Integer(i) => LiteralValue::Integer(i as u64),
+            Value::Float(f) => LiteralValue::Float(f),
+            Value::Text(s) => LiteralValue::Str(s),
+            Value::Blob(b) => LiteralValue::Blob(b),
+        })
+    }
+}
+
+/// A type that a `Value` can be converted to, for reading rows as Rust
+/// types with `FromRow`.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Value> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<i64> {
+        match *value {
+            Value::Integer(i) => Ok(i),
+            _ => bail!(ErrorKind::UnexpectedType(Type::Integer, value.ty())),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<f64> {
+        match *value {
+            Value::Float(f) => Ok(f),
+            // A REAL column may store whole numbers as integers.
+            Value::Integer(i) => Ok(i as f64),
+            _ => bail!(ErrorKind::UnexpectedType(Type::Float, value.ty())),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<String> {
+        match *value {
+            Value::Text(ref s) => Ok(s.clone()),
+            _ => bail!(ErrorKind::UnexpectedType(Type::Text, value.ty())),
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Vec<u8>> {
+        match *value {
+            Value::Blob(ref b) => Ok(b.clone()),
+            _ => bail!(ErrorKind::UnexpectedType(Type::Blob, value.ty())),
+        }
+    }
+}
+
+/// NULL is `None`; anything else is converted to `T`.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Option<T>> {
+        match *value {
+            Value::Null => Ok(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// A type that a `Row` can be converted to, such as a tuple of its values:
+///
+/// ```
+/// # use rqlite::db::Database;
+/// # use rqlite::errors::Result;
+/// # fn main() -> Result<()> {
+/// # let db = Database::open("fixtures/views.db")?;
+/// # let table = db.schema().table("people")?;
+/// let rows: Vec<(i64, String)> = table.query_as(vec!["id", "name"])?;
+/// # assert_eq!(rows[0], (1, "ann".to_owned()));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// There's no derive, but a struct can be read by implementing this by hand:
+///
+/// ```
+/// # use rqlite::errors::Result;
+/// # use rqlite::value::{FromRow, FromValue, Row};
+/// # struct Person {
+/// #     id: i64,
+/// #     name: String,
+/// # }
+/// impl FromRow for Person {
+///     fn from_row(row: &Row) -> Result<Person> {
+///         Ok(Person {
+///             id: i64::from_value(row.value("id")?)?,
+///             name: String::from_value(row.value("name")?)?,
+///         })
+///     }
+/// }
+/// ```
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+impl FromRow for Row {
+    fn from_row(row: &Row) -> Result<Row> {
+        Ok(row.clone())
+    }
+}
+
+// Tuples are read from the row's values in order, and the row must have
+// exactly as many values as the tuple.
+macro_rules! tuple_from_row {
+    ($len:expr => $($t:ident $i:tt),+) => {
+        impl<$($t: FromValue),+> FromRow for ($($t,)+) {
+            fn from_row(row: &Row) -> Result<Self> {
+                if row.len() != $len {
+                    bail!("Expected {} columns, but row has {}", $len, row.len());
+                }
+                Ok(($($t::from_value(row.value_at($i)?)?,)+))
+            }
+        }
+    };
+}
+
+tuple_from_row!(1 => A 0);
+tuple_from_row!(2 => A 0, B 1);
+tuple_from_row!(3 => A 0, B 1, C 2);
+tuple_from_row!(4 => A 0, B 1, C 2, D 3);
+tuple_from_row!(5 => A 0, B 1, C 2, D 3, E 4);
+tuple_from_row!(6 => A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// A row of results: its values, in the order of its columns, along with
+/// the names of the columns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    columns: Vec<String>,
+    values: Vec<Value>,
+}
+
+impl Row {
+    pub fn new(columns: Vec<String>, values: Vec<Value>) -> Row {
+        assert_eq!(columns.len(), values.len());
+        Row { columns, values }
+    }
+
+    /// Copies the fields named by `columns` out of a row read from a table,
+    /// in that order.
+    pub fn from_fields<S: AsRef<str>>(
+        columns: &[S],
+        fields: &HashMap<String, Field>,
+    ) -> Result<Row> {
+        Row::from_fields_with(columns, fields, TextDecoding::Strict)
+    }
+
+    /// Like `from_fields()`, but decodes text as `decoding` says.
+    pub fn from_fields_with<S: AsRef<str>>(
+        columns: &[S],
+        fields: &HashMap<String, Field>,
+        decoding: TextDecoding,
+    ) -> Result<Row> {
+        let values = columns
+            .iter()
+            .map(|name| match fields.get(name.as_ref()) {
+                Some(field) => Value::from_field_with(field, decoding),
+                None => bail!("No such column: {}", name.as_ref()),
+            })
+            .collect::<Result<_>>()?;
+        let columns = columns
+            .iter()
+            .map(|name| name.as_ref().to_owned())
+            .collect();
+        Ok(Row { columns, values })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The row as a map from column name to field, as the query executor
+    /// works with.
+    pub fn into_map(self) -> HashMap<String, Field> {
+        self.columns
+            .into_iter()
+            .zip(self.values.into_iter().map(Field::from))
+            .collect()
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The value of the column called `name`:
+    ///
+    /// ```
+    /// # use rqlite::errors::Result;
+    /// # use rqlite::value::{Row, Value};
+    /// # fn main() -> Result<()> {
+    /// let row = Row::new(vec!["id".to_owned()], vec![Value::Integer(1)]);
+    /// assert_eq!(row.value("id")?, &Value::Integer(1));
+    /// assert!(row.value("name").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn value(&self, name: &str) -> Result<&Value> {
+        match self.columns.iter().position(|column| column == name) {
+            Some(i) => Ok(&self.values[i]),
+            None => bail!("No such column: {}", name),
+        }
+    }
+
+    /// The value of the `i`th column, counting from zero:
+    ///
+    /// ```
+    /// # use rqlite::errors::Result;
+    /// # use rqlite::value::{Row, Value};
+    /// # fn main() -> Result<()> {
+    /// let row = Row::new(vec!["id".to_owned()], vec![Value::Integer(1)]);
+    /// assert_eq!(row.value_at(0)?, &Value::Integer(1));
+    /// assert!(row.value_at(1).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn value_at(&self, i: usize) -> Result<&Value> {
+        match self.values.get(i) {
+            Some(value) => Ok(value),
+            None => bail!("Column {} out of range: row has {} columns", i, self.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_row_values() {
+        let mut fields = HashMap::new();
+        fields.insert("id".to_owned(), Field::from(7u64));
+        fields.insert("name".to_owned(), Field::from("ann"));
+        fields.insert("age".to_owned(), Field::Literal(LiteralValue::Null));
+        let row = Row::from_fields(&["name", "id", "age"], &fields).unwrap();
+
+        assert_eq!(row.columns(), &["name", "id", "age"]);
+        assert_eq!(row.value("id").unwrap(), &Value::Integer(7));
+        assert_eq!(row.value("age").unwrap(), &Value::Null);
+        assert_eq!(row.value_at(0).unwrap(), &Value::Text("ann".to_owned()));
+        assert!(row.value("missing").is_err());
+        assert!(row.value_at(3).is_err());
+
+        assert!(Row::from_fields(&["missing"], &fields).is_err());
+    }
+
+    #[test]
+    fn test_from_row() {
+        let columns = vec!["id".to_owned(), "name".to_owned(), "age".to_owned()];
+        let row = Row::new(
+            columns,
+            vec![Value::Integer(7), Value::from("ann"), Value::Null],
+        );
+        let (id, name, age) = <(i64, String, Option<i64>)>::from_row(&row).unwrap();
+        assert_eq!((id, name.as_str(), age), (7, "ann", None));
+
+        assert!(<(String, String, Option<i64>)>::from_row(&row).is_err());
+        assert!(<(i64, String, i64)>::from_row(&row).is_err());
+        // The tuple must have as many values as the row.
+        assert!(<(i64, String)>::from_row(&row).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use bytes::Bytes;
+
+use crate::errors::*;
+use crate::pager::is_valid_page_size;
+
+const WAL_HEADER_LEN: usize = 32;
+const FRAME_HEADER_LEN: usize = 24;
+
+// "The magic number is either 0x377f0682 or 0x377f0683. If the magic number
+//  is 0x377f0682, then checksum values are computed using little-endian
+//  arithmetic. If the magic number is 0x377f0683, then checksum values are
+//  computed using big-endian arithmetic."
+const MAGIC_LITTLE_ENDIAN: u32 = 0x377f_0682;
+const MAGIC_BIG_ENDIAN: u32 = 0x377f_0683;
+
+/// The pages of a write-ahead log that belong to committed transactions,
+/// which haven't been checkpointed back into the database file yet.
+///
+/// SQLite readers normally find these through the `-shm` wal-index. That's
+/// only a cache of what's in the `-wal` file, and is often missing from
+/// copied databases, so this is built from the `-wal` file alone.
+#[derive(Debug, Default)]
+pub struct Wal {
+    pub page_size: usize,
+    // The latest committed version of each page in the log.
+    pages: HashMap<usize, Bytes>,
+    // The size of the database in pages, as of the last commit.
+    num_pages: Option<usize>,
+}
+
+impl Wal {
+    /// Reads the log beside the database at `path`, if there is one.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Option<Wal>> {
+        let mut wal_path = path.as_ref().as_os_str().to_owned();
+        wal_path.push("-wal");
+        match fs::read(&wal_path) {
+            Ok(data) => Ok(Some(Wal::from_bytes(Bytes::from(data))?)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).chain_err(|| "Error reading write-ahead log"),
+        }
+    }
+
+    /// Indexes the frames of a log. Frames are only used up to the last
+    /// valid commit: any after it are from a transaction that was never
+    /// committed, or are left over from before the log was last reset.
+    pub fn from_bytes(data: Bytes) -> Result<Wal> {
+        // A log too short for its header, or with a bad one, is as good as
+        // empty: SQLite would ignore it too.
+        if data.len() < WAL_HEADER_LEN {
+            return Ok(Wal::default());
+        }
+        let big_endian = match BigEndian::read_u32(&data) {
+            MAGIC_LITTLE_ENDIAN => false,
+            MAGIC_BIG_ENDIAN => true,
+            _ => return Ok(Wal::default()),
+        };
+        let header = &data[..WAL_HEADER_LEN];
+        let page_size = match BigEndian::read_u32(&header[8..]) {
+            1 => 65536,
+            n if is_valid_page_size(n as usize) => n as usize,
+            n => bail!("Invalid write-ahead log page size: {}", n),
+        };
+        let mut sum = checksum(big_endian, (0, 0), &header[..24]);
+        if sum
+            != (
+                BigEndian::read_u32(&header[24..]),
+                BigEndian::read_u32(&header[28..]),
+            )
+        {
+            return Ok(Wal::default());
+        }
+        let salts = &header[16..24];
+
+        let mut wal = Wal {
+            page_size,
+            ..Wal::default()
+        };
+        let mut uncommitted = Vec::new();
+        let frame_len = FRAME_HEADER_LEN + page_size;
+        let mut offset = WAL_HEADER_LEN;
+        while offset + frame_len <= data.len() {
+            let frame = &data[offset..offset + frame_len];
+            // "A frame is considered valid if and only if ... the salt-1 and
+            //  salt-2 values in the frame-header match salt values in the
+            //  wal-header [and] the checksum values in the final 8 bytes of
+            //  the frame-header exactly match the checksum computed
+            //  consecutively on the first 24 bytes of the WAL header and the
+            //  first 8 bytes and the content of all frames up to and
+            //  including the current frame."
+            if &frame[8..16] != salts {
+                break;
+            }
+            sum = checksum(big_endian, sum, &frame[..8]);
+            sum = checksum(big_endian, sum, &frame[FRAME_HEADER_LEN..]);
+            if sum
+                != (
+                    BigEndian::read_u32(&frame[16..]),
+                    BigEndian::read_u32(&frame[20..]),
+                )
+            {
+                break;
+            }
+
+            let page_num = BigEndian::read_u32(frame) as usize;
+            let page = data.slice(offset + FRAME_HEADER_LEN, offset + frame_len);
+            uncommitted.push((page_num, page));
+            // "For commit records, the size of the database file in pages
+            //  after the commit. For all other records, zero."
+            match BigEndian::read_u32(&frame[4..]) {
+                0 => {}
+                num_pages => {
+                    wal.pages.extend(uncommitted.drain(..));
+                    wal.num_pages = Some(num_pages as usize);
+                }
+            }
+            offset += frame_len;
+        }
+        Ok(wal)
+    }
+
+    /// The latest committed version of a page, if it's in the log.
+    pub fn page(&self, page_num: usize) -> Option<Bytes> {
+        self.pages.get(&page_num).cloned()
+    }
+
+    /// The size of the database in pages, if the log has any commits.
+    pub fn num_pages(&self) -> Option<usize> {
+        self.num_pages
+    }
+}
+
+// The log's checksum, continued from `sum` over `data`, which is a multiple
+// of 8 bytes long. It treats the data as 32-bit words, in the byte order given
+// by the log's magic number.
+fn checksum(big_endian: bool, sum: (u32, u32), data: &[u8]) -> (u32, u32) {
+    let read = |bytes: &[u8]| {
+        if big_endian {
+            BigEndian::read_u32(bytes)
+        } else {
+            LittleEndian::read_u32(bytes)
+        }
+    };
+    let (mut s1, mut s2) = sum;
+    for words in data.chunks(8) {
+        s1 = s1.wrapping_add(read(&words[..4])).wrapping_add(s2);
+        s2 = s2.wrapping_add(read(&words[4..])).wrapping_add(s1);
+    }
+    (s1, s2)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let data = fs::read("fixtures/wal.db-wal").unwrap();
+        let wal = Wal::from_bytes(Bytes::from(data.clone())).unwrap();
+        assert_eq!(wal.page_size, 4096);
+        assert!(wal.page(1).is_some());
+        let num_pages = wal.num_pages().unwrap();
+        assert!(wal.page(num_pages + 1).is_none());
+
+        // The log ends with a torn frame, after the last commit. Corrupting
+        // the commit before it drops that transaction.
+        let frame_len = FRAME_HEADER_LEN + 4096;
+        let last_commit =
+            WAL_HEADER_LEN + ((data.len() - WAL_HEADER_LEN) / frame_len - 2) * frame_len;
+        let page_num = BigEndian::read_u32(&data[last_commit..]) as usize;
+        assert_eq!(
+            wal.page(page_num).unwrap(),
+            &data[last_commit + FRAME_HEADER_LEN..last_commit + frame_len]
+        );
+        let mut corrupted = data.clone();
+        corrupted[last_commit + FRAME_HEADER_LEN + 100] ^= 1;
+        let wal = Wal::from_bytes(Bytes::from(corrupted)).unwrap();
+        assert!(wal.num_pages().is_some());
+        assert_ne!(
+            wal.page(page_num),
+            Some(Bytes::from(
+                &data[last_commit + FRAME_HEADER_LEN..last_commit + frame_len]
+            ))
+        );
+
+        assert!(Wal::from_bytes(Bytes::from(&data[..16]))
+            .unwrap()
+            .num_pages()
+            .is_none());
+    }
+
+    #[test]
+    fn test_page_size() {
+        // A header with no frames after it, giving `page_size`.
+        let header = |page_size: u32| {
+            let mut data = vec![0; WAL_HEADER_LEN];
+            BigEndian::write_u32(&mut data, MAGIC_BIG_ENDIAN);
+            BigEndian::write_u32(&mut data[8..], page_size);
+            let (s1, s2) = checksum(true, (0, 0), &data[..24]);
+            BigEndian::write_u32(&mut data[24..], s1);
+            BigEndian::write_u32(&mut data[28..], s2);
+            Wal::from_bytes(Bytes::from(data))
+        };
+        assert_eq!(header(512).unwrap().page_size, 512);
+        assert_eq!(header(65536).unwrap().page_size, 65536);
+        assert_eq!(header(1).unwrap().page_size, 65536);
+        assert!(header(1000).is_err());
+        assert!(header(131_072).is_err());
+    }
+}
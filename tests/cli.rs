@@ -0,0 +1,120 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// Runs the binary on fixtures/sales.db with `args`.
+fn rqlite(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rqlite"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = rqlite(args);
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+const QUERY: &str = "SELECT id, region FROM sales WHERE id < 3 ORDER BY id";
+
+#[test]
+fn test_one_shot_query() {
+    assert_eq!(
+        stdout(&["fixtures/sales.db", QUERY]),
+        "id  region\n--  ------\n1   north\n2   north\n"
+    );
+    assert_eq!(
+        stdout(&["--format", "json", "fixtures/sales.db", QUERY]),
+        "[{\"id\":1,\"region\":\"north\"},\n{\"id\":2,\"region\":\"north\"}]\n"
+    );
+    assert_eq!(
+        stdout(&["--csv", "fixtures/sales.db", QUERY]),
+        "id,region\n1,north\n2,north\n"
+    );
+}
+
+#[test]
+fn test_one_shot_errors() {
+    let output = rqlite(&["fixtures/sales.db", "SELECT id FROM missing"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("missing"));
+
+    assert!(!rqlite(&["--format", "xml", "fixtures/sales.db", QUERY])
+        .status
+        .success());
+}
+
+// Runs the REPL on `path`, typing `input` into it.
+fn repl(path: &str, input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rqlite"))
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_repl_schema() {
+    let output = repl("fixtures/views.db", ".schema people_insert\n.schema missing\n.quit\n");
+    assert!(output.contains("Trigger("));
+    assert!(output.contains("CREATE TRIGGER people_insert"));
+    assert!(output.contains("Failed to read missing"));
+}
+
+#[test]
+fn test_repl_hexdump() {
+    let output = repl("fixtures/vacuum.db", ".hexdump 2\n.hexdump 3\n.quit\n");
+    let ptrmap = output.find("Page 2 (").unwrap();
+    let table = output.find("Page 3 (").unwrap();
+    assert!(output[ptrmap..table].contains("page type:      pointer map"));
+    assert!(output[table..].contains("page type:      0x0"));
+}
+
+#[test]
+fn test_repl_estimates_rows() {
+    let output = repl("fixtures/large.db", ".quit\n");
+    let line = output
+        .lines()
+        .find(|line| line.starts_with("numbers: about "))
+        .unwrap();
+    let rows: f64 = line["numbers: about ".len()..line.len() - " rows".len()]
+        .parse()
+        .unwrap();
+    assert!(rows > 2500.0 && rows < 10000.0, "{}", line);
+}
+
+#[test]
+fn test_altered_table() {
+    // The first two rows were written before qty and note were added.
+    assert_eq!(
+        stdout(&["fixtures/altered.db", "SELECT id, qty, note FROM items"]),
+        "id  qty  note\n--  ---  -----\n1   5\n2   5\n3   7    loose\n"
+    );
+    let output = repl(
+        "fixtures/altered.db",
+        "SELECT qty FROM items WHERE id = 1;\n.quit\n",
+    );
+    assert!(output.contains("5"), "{}", output);
+
+    // Errors are reported, rather than panicking.
+    let output = rqlite(&["fixtures/altered.db", "SELECT qty FROM missing"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("missing") && !stderr.contains("panicked"),
+        "{}",
+        stderr
+    );
+}